@@ -0,0 +1,342 @@
+use crate::domain::models::{CloneKind, CloneLocation, ClonePair, ModuleKind, NewModuleProposal, PerlModule, Signature, Subroutine};
+use crate::metrics::subroutine_metrics;
+use crate::parser::lexer::{self, TokenKind};
+
+/// Default lowest similarity reported as a near-miss clone
+pub const DEFAULT_MIN_SIMILARITY: f32 = 0.8;
+/// Subroutines with fewer tokens are too small to be worth sharing
+const MIN_TOKENS: usize = 30;
+/// Words kept when names are disregarded, because they give code its shape
+const KEYWORDS: &[&str] = &[
+    "my", "our", "local", "state", "return", "if", "elsif", "else", "unless", "while", "until", "for", "foreach",
+    "last", "next", "redo", "and", "or", "not", "eq", "ne", "lt", "gt", "le", "ge", "cmp", "x", "shift", "unshift",
+    "push", "pop", "splice", "keys", "values", "each", "delete", "exists", "defined", "undef", "scalar", "ref",
+    "bless", "die", "warn", "eval", "do", "sub", "map", "grep", "sort", "join", "split", "wantarray", "__PACKAGE__",
+];
+
+/// Subroutines of `modules` that copy each other, within one module or
+/// across modules, at least `min_similarity` alike. Whitespace, comments
+/// and POD never count; renamed copies only differ in names and literals;
+/// near-miss copies are compared by the longest common subsequence of
+/// their tokens once names and literals are disregarded.
+pub fn find_clones(modules: &[PerlModule], min_similarity: f32) -> Vec<ClonePair> {
+    let subs: Vec<(&PerlModule, &Subroutine, Fingerprint)> = modules
+        .iter()
+        .flat_map(|m| m.subroutines.iter().map(move |s| (m, s)))
+        .filter_map(|(m, s)| Fingerprint::new(s).map(|f| (m, s, f)))
+        .collect();
+
+    let mut pairs = Vec::new();
+    for (i, (first_module, first, a)) in subs.iter().enumerate() {
+        for (second_module, second, b) in &subs[i + 1..] {
+            let (shorter, longer) = (a.normalized.len().min(b.normalized.len()), a.normalized.len().max(b.normalized.len()));
+            if (shorter as f32) < min_similarity * longer as f32 {
+                continue;
+            }
+            let (kind, similarity) = if a.exact == b.exact {
+                (CloneKind::Exact, 1.0)
+            } else if a.normalized == b.normalized {
+                (CloneKind::Renamed, 1.0)
+            } else {
+                let common = longest_common_subsequence(&a.normalized, &b.normalized);
+                (CloneKind::NearMiss, 2.0 * common as f32 / (a.normalized.len() + b.normalized.len()) as f32)
+            };
+            if similarity >= min_similarity {
+                pairs.push(ClonePair {
+                    kind,
+                    similarity,
+                    first: location(first_module, first),
+                    second: location(second_module, second),
+                });
+            }
+        }
+    }
+    pairs
+}
+
+/// A module for cloned subroutines, built by `helper_module`
+#[derive(Debug, Clone)]
+pub struct SharedModule {
+    pub module: NewModuleProposal,
+    /// Groups of clones the module holds one copy of
+    pub groups: usize,
+}
+
+/// A module named `name` holding one copy of every group of cloned
+/// subroutines, which the others can call instead. `None` without clones.
+/// The first subroutine of a group is the copy kept; its code goes into the
+/// module unchanged and is exported on request. Exact copies of it become
+/// subroutines of the module that call it, so that they can be dropped too.
+/// A group whose copy has the name of a subroutine already in the module
+/// is left out and listed as such in its responsibility.
+pub fn helper_module(name: &str, modules: &[PerlModule], clones: &[ClonePair]) -> Option<SharedModule> {
+    // Group clones transitively, keeping the order they were found in
+    let mut groups: Vec<Vec<&CloneLocation>> = Vec::new();
+    for pair in clones {
+        let found: Vec<usize> = groups
+            .iter()
+            .enumerate()
+            .filter(|(_, g)| g.contains(&&pair.first) || g.contains(&&pair.second))
+            .map(|(i, _)| i)
+            .collect();
+        let mut group: Vec<&CloneLocation> = Vec::new();
+        for i in found.iter().rev() {
+            let mut merged = groups.remove(*i);
+            merged.extend(group);
+            group = merged;
+        }
+        for location in [&pair.first, &pair.second] {
+            if !group.contains(&location) {
+                group.push(location);
+            }
+        }
+        let at = found.first().copied().unwrap_or(groups.len());
+        groups.insert(at, group);
+    }
+
+    let mut subroutines: Vec<Subroutine> = Vec::new();
+    let mut replaced = Vec::new();
+    let mut not_extracted = Vec::new();
+    let mut dependencies: Vec<String> = Vec::new();
+    let mut uses: Vec<String> = Vec::new();
+    for group in &groups {
+        let kept = group[0];
+        let Some(module) = modules.iter().find(|m| m.name == kept.module && m.path == kept.path) else {
+            continue;
+        };
+        let Some(sub) = module.subroutines.iter().find(|s| s.name == kept.subroutine) else {
+            continue;
+        };
+        let copies: Vec<String> = group[1..].iter().map(|l| format!("{}::{}", l.module, l.subroutine)).collect();
+        if subroutines.iter().any(|s| s.name == sub.name) {
+            not_extracted.push(format!("{}::{} (copied by {})", kept.module, sub.name, copies.join(", ")));
+            continue;
+        }
+        for dependency in module.dependencies_of(&[sub.name.as_str()]) {
+            let declaration = dependency.declaration();
+            let written = ["strict", "warnings", "Exporter"].contains(&dependency.name.as_str());
+            if !written && !uses.contains(&declaration) {
+                uses.push(declaration);
+            }
+            if !dependencies.contains(&dependency.name) {
+                dependencies.push(dependency.name);
+            }
+        }
+        replaced.push(format!("{} (replacing {})", sub.name, copies.join(", ")));
+        subroutines.push(sub.clone());
+
+        for copy in &group[1..] {
+            let exact = clones.iter().any(|c| {
+                c.kind == CloneKind::Exact
+                    && ((&c.first == kept && &c.second == *copy) || (&c.first == *copy && &c.second == kept))
+            });
+            if !exact || subroutines.iter().any(|s| s.name == copy.subroutine) {
+                continue;
+            }
+            let code = format!("sub {} {{ return {}(@_) }}", copy.subroutine, sub.name);
+            subroutines.push(Subroutine {
+                name: copy.subroutine.clone(),
+                metrics: subroutine_metrics(&code, &Signature::default()),
+                code,
+                kind: sub.kind,
+                ..Default::default()
+            });
+        }
+    }
+    if subroutines.is_empty() {
+        return None;
+    }
+    let groups = replaced.len();
+    let mut responsibility = format!("Shared helpers for duplicated code: {}", replaced.join("; "));
+    if !not_extracted.is_empty() {
+        responsibility.push_str(&format!(
+            ". Not extracted, because a subroutine of the same name already is: {}",
+            not_extracted.join("; ")
+        ));
+    }
+
+    let names: Vec<&str> = subroutines.iter().map(|s| s.name.as_str()).collect();
+    let mut code = format!("package {};\nuse strict;\nuse warnings;\n", name);
+    for declaration in &uses {
+        code.push_str(declaration);
+        code.push('\n');
+    }
+    code.push_str(&format!("use Exporter 'import';\n\nour @EXPORT_OK = qw({});\n", names.join(" ")));
+    for sub in &subroutines {
+        code.push('\n');
+        code.push_str(&sub.code);
+        code.push('\n');
+    }
+    code.push_str("\n1;\n");
+
+    let module = NewModuleProposal {
+        name: name.to_string(),
+        kind: ModuleKind::Module,
+        responsibility,
+        subroutines,
+        attributes: Vec::new(),
+        dependencies,
+        suggested_code: code,
+        confidence: clones.iter().map(|c| c.similarity).fold(1.0, f32::min),
+    };
+    Some(SharedModule { module, groups })
+}
+
+/// The tokens of a subroutine body, as written and with names and literals
+/// replaced by placeholders
+struct Fingerprint {
+    exact: Vec<String>,
+    normalized: Vec<String>,
+}
+
+impl Fingerprint {
+    fn new(sub: &Subroutine) -> Option<Self> {
+        let tokens = lexer::significant_tokens(&sub.code);
+        // Skip `sub name`
+        let body = tokens.get(2..)?;
+        if body.len() < MIN_TOKENS {
+            return None;
+        }
+        let exact = body.iter().map(|t| t.text.to_string()).collect();
+        let normalized = body
+            .iter()
+            .map(|t| match t.kind {
+                TokenKind::Variable if matches!(t.text, "@_" | "$_" | "$self" | "$class") => t.text.to_string(),
+                TokenKind::Variable | TokenKind::Cast => t.text[..1].to_string(),
+                TokenKind::Number => "0".to_string(),
+                TokenKind::String | TokenKind::InterpolatedString | TokenKind::QuoteWords | TokenKind::Heredoc => "''".to_string(),
+                TokenKind::Regex | TokenKind::Substitution => "//".to_string(),
+                TokenKind::Word if KEYWORDS.contains(&t.text) => t.text.to_string(),
+                TokenKind::Word => "name".to_string(),
+                _ => t.text.to_string(),
+            })
+            .collect();
+        Some(Self { exact, normalized })
+    }
+}
+
+fn location(module: &PerlModule, sub: &Subroutine) -> CloneLocation {
+    CloneLocation {
+        module: module.name.clone(),
+        path: module.path.clone(),
+        subroutine: sub.name.clone(),
+        span: sub.span,
+    }
+}
+
+fn longest_common_subsequence(a: &[String], b: &[String]) -> usize {
+    let mut previous = vec![0; b.len() + 1];
+    let mut current = vec![0; b.len() + 1];
+    for x in a {
+        for (j, y) in b.iter().enumerate() {
+            current[j + 1] = if x == y { previous[j] + 1 } else { previous[j + 1].max(current[j]) };
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use crate::parser::StaticModuleParser;
+    use super::*;
+
+    const CONTENT: &str = r#"package Report;
+use strict;
+use List::Util qw(sum);
+
+sub order_total {
+    my ($order) = @_;
+    my $total = 0;
+    foreach my $line (@{ $order->{lines} }) {
+        $total += $line->{price} * $line->{quantity};
+    }
+    return sprintf('%.2f', $total);
+}
+
+# Copied from order_total
+sub invoice_total {
+    my ($invoice) = @_;
+    my $sum = 0;
+    foreach my $item (@{ $invoice->{items} }) {
+        $sum += $item->{amount} * $item->{count};
+    }
+    return sprintf("%.2f", $sum);
+}
+
+sub cart_total {
+    my ($cart) = @_;
+    my $total = 0;
+    foreach my $line (@{ $cart->{lines} }) {
+        next unless $line->{active};
+        $total += $line->{price} * $line->{quantity};
+    }
+    return sprintf('%.2f', $total);
+}
+
+sub average { my @values = @_; return @values ? sum(@values) / @values : 0 }
+
+1;
+"#;
+
+    #[test]
+    fn test_find_clones_and_helper_module() {
+        let module = StaticModuleParser::new().parse_source(Path::new("Report.pm"), CONTENT).remove(0);
+        let copy = StaticModuleParser::new()
+            .parse_source(Path::new("Legacy.pm"), &CONTENT.replace("package Report;", "package Legacy;"))
+            .remove(0);
+
+        let clones = find_clones(std::slice::from_ref(&module), DEFAULT_MIN_SIMILARITY);
+        let found: Vec<(&str, &str, CloneKind)> = clones
+            .iter()
+            .map(|c| (c.first.subroutine.as_str(), c.second.subroutine.as_str(), c.kind))
+            .collect();
+        assert_eq!(found, vec![
+            ("order_total", "invoice_total", CloneKind::Renamed),
+            ("order_total", "cart_total", CloneKind::NearMiss),
+            ("invoice_total", "cart_total", CloneKind::NearMiss),
+        ]);
+        assert!(clones[1].similarity > 0.8 && clones[1].similarity < 1.0);
+        assert_eq!(clones[0].first.span.map(|s| s.line_start), Some(5));
+
+        // Across files, with the exact copies in the other file
+        let across = find_clones(&[module.clone(), copy.clone()], 1.0);
+        assert!(across.iter().any(|c| c.kind == CloneKind::Exact && c.first.module == "Report" && c.second.module == "Legacy"));
+
+        let shared = helper_module("Report::Shared", std::slice::from_ref(&module), &clones).expect("a helper module");
+        assert_eq!(shared.groups, 1);
+        let helper = shared.module;
+        assert_eq!(helper.subroutines.iter().map(|s| s.name.as_str()).collect::<Vec<_>>(), vec!["order_total"]);
+        assert!(helper.responsibility.contains("Report::invoice_total, Report::cart_total"));
+        assert!(helper.suggested_code.starts_with("package Report::Shared;\nuse strict;\nuse warnings;\nuse Exporter 'import';"));
+        assert!(helper_module("Report::Shared", std::slice::from_ref(&module), &[]).is_none());
+
+        // Exact copies call the copy kept, unless they have its name
+        let renamed = StaticModuleParser::new()
+            .parse_source(Path::new("Legacy.pm"), &copy.content.replace("sub order_total", "sub sum_order"))
+            .remove(0);
+        let modules = [module, renamed];
+        let shared = helper_module("Report::Shared", &modules, &find_clones(&modules, 1.0)).expect("a helper module");
+        assert_eq!(shared.groups, 2);
+        let helper = shared.module;
+        let names: Vec<&str> = helper.subroutines.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["order_total", "sum_order", "cart_total"]);
+        assert!(helper.suggested_code.contains("\nsub sum_order { return order_total(@_) }\n"));
+
+        // Groups that keep subroutines of the same name from different files
+        let body = "{\n    my ($order) = @_;\n    my $total = 0;\n    foreach my $line (@{ $order->{lines} }) {\n        \
+            $total += $line->{price} * $line->{quantity};\n    }\n    return sprintf('%.2f', $total);\n}\n";
+        let active = body.replace("{\n        ", "{\n        next unless $line->{active};\n        ");
+        let parse = |file: &str, content: String| StaticModuleParser::new().parse_source(Path::new(file), &content).remove(0);
+        let modules = [
+            parse("A.pm", format!("package A;\nsub total {}sub sum {}1;\n", body, body)),
+            parse("B.pm", format!("package B;\nsub total {}sub sum_active {}1;\n", active, active)),
+        ];
+        let shared = helper_module("Shared", &modules, &find_clones(&modules, 1.0)).expect("a helper module");
+        assert_eq!(shared.groups, 1);
+        let names: Vec<&str> = shared.module.subroutines.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["total", "sum"]);
+        assert!(shared.module.responsibility.ends_with("Not extracted, because a subroutine of the same name already is: B::total (copied by B::sum_active)"));
+    }
+}
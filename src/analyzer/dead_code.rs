@@ -0,0 +1,155 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use crate::domain::models::{HazardKind, PerlModule};
+use crate::error::Error;
+use crate::parser::{lexer::{self, TokenKind}, perl_files, read_source};
+
+/// Subroutines Perl or common frameworks call implicitly
+const IMPLICIT: &[&str] = &[
+    "new", "import", "unimport", "DESTROY", "AUTOLOAD", "CLONE", "BUILD", "BUILDARGS", "DEMOLISH", "can", "isa",
+    "DOES", "VERSION", "main",
+];
+
+/// The names each Perl file under a directory refers to, to tell whether a
+/// subroutine is used outside its own file
+#[derive(Debug, Clone, Default)]
+pub struct SourceTree {
+    pub root: PathBuf,
+    files: Vec<(PathBuf, HashSet<String>)>,
+}
+
+impl SourceTree {
+    /// Read every Perl file under `root` (see `perl_files`)
+    pub async fn scan(root: impl AsRef<Path>) -> Result<Self, Error> {
+        let root = root.as_ref().to_path_buf();
+        let mut files = Vec::new();
+        for path in perl_files(&root).await? {
+            let (content, _) = read_source(&path).await?;
+            let names = references(&content).into_iter().map(|(name, _)| name).collect();
+            files.push((canonical(&path), names));
+        }
+        Ok(Self { root, files })
+    }
+
+    /// Whether a file other than `except` refers to `name`
+    fn refers_to(&self, name: &str, except: &Path) -> bool {
+        let except = canonical(except);
+        self.files.iter().any(|(path, names)| *path != except && names.contains(name))
+    }
+}
+
+/// Subroutines of `module` that nothing seems to use: no code of its file
+/// outside the subroutine refers to them, they are not exported, Perl does
+/// not call them implicitly, they are not linked to an attribute or
+/// generated for one, and no file of `tree` refers to them. Without a tree,
+/// only private subroutines (`_name`) and those of scripts and test files
+/// qualify, since others may be called from anywhere.
+///
+/// Dynamic code can call any subroutine by name, so a package with string
+/// eval, dynamic dispatch, glob assignments or AUTOLOAD has no candidates.
+pub fn deletion_candidates(module: &PerlModule, tree: Option<&SourceTree>) -> Vec<String> {
+    if module.hazards.iter().any(|h| h.kind != HazardKind::Caller) {
+        return Vec::new();
+    }
+    let referenced = references(&module.content);
+    let implicit: Vec<&str> = module.attributes
+        .iter()
+        .flat_map(|a| a.linked_subroutines().into_iter().chain(a.methods.iter().map(|m| m.as_str())))
+        .chain(IMPLICIT.iter().copied())
+        .collect();
+    module.subroutines
+        .iter()
+        .filter(|sub| {
+            let outside = |line: usize| line < sub.line_start || line > sub.line_end;
+            !referenced.iter().any(|(name, line)| name == &sub.name && outside(*line))
+        })
+        .filter(|sub| !module.exports.is_exported(&sub.name) && !implicit.contains(&sub.name.as_str()))
+        .filter(|sub| match tree {
+            Some(tree) => !tree.refers_to(&sub.name, &module.path),
+            None => sub.name.starts_with('_') || module.is_script(),
+        })
+        .map(|sub| sub.name.clone())
+        .collect()
+}
+
+/// Names `content` refers to, with their lines: barewords, `&name`, and
+/// strings and quote-words that could name a subroutine, unqualified.
+/// Subroutine definitions are not references.
+fn references(content: &str) -> Vec<(String, usize)> {
+    let tokens = lexer::significant_tokens(content);
+    let mut names = Vec::new();
+    let unqualified = |name: &str| name.rsplit("::").next().unwrap_or(name).to_string();
+    for (i, token) in tokens.iter().enumerate() {
+        let defined = i.checked_sub(1).is_some_and(|p| tokens[p].is_word("sub"));
+        match token.kind {
+            TokenKind::Word if !defined => names.push((unqualified(token.text), token.line)),
+            TokenKind::Variable if token.text.starts_with('&') => names.push((unqualified(&token.text[1..]), token.line)),
+            TokenKind::String | TokenKind::InterpolatedString => {
+                let text = lexer::string_contents(token.text).unwrap_or_default();
+                if !text.is_empty() && text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':') {
+                    names.push((unqualified(text), token.line));
+                }
+            }
+            TokenKind::QuoteWords => {
+                names.extend(lexer::quote_words(token.text).iter().map(|w| (unqualified(w.trim_start_matches('&')), token.line)));
+            }
+            _ => {}
+        }
+    }
+    names
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::StaticModuleParser;
+    use super::*;
+
+    const CONTENT: &str = r#"package Util;
+use strict;
+use Exporter 'import';
+our @EXPORT_OK = qw(trim);
+
+my %handlers = (csv => \&_parse_csv);
+
+sub trim { my ($s) = @_; $s =~ s/^\s+|\s+$//g; return _squeeze($s) }
+sub _squeeze { my ($s) = @_; $s =~ s/\s+/ /g; return $s }
+sub _parse_csv { return [split /,/, shift] }
+sub _legacy_trim { return _legacy_trim(@_) }
+sub format_date { return scalar localtime }
+sub unused_helper { return 1 }
+
+1;
+"#;
+
+    #[tokio::test]
+    async fn test_deletion_candidates() -> Result<(), Error> {
+        let dir = tempfile::tempdir()?;
+        std::fs::create_dir(dir.path().join("lib"))?;
+        std::fs::create_dir(dir.path().join("bin"))?;
+        std::fs::write(dir.path().join("lib/Util.pm"), CONTENT)?;
+        std::fs::write(dir.path().join("bin/report.pl"), "use Util;\nprint Util::format_date();\n")?;
+
+        let path = dir.path().join("lib/Util.pm");
+        let module = StaticModuleParser::new().parse_source(&path, CONTENT).remove(0);
+        // Recursion does not count as a use
+        assert_eq!(deletion_candidates(&module, None), vec!["_legacy_trim"]);
+
+        let tree = SourceTree::scan(dir.path()).await?;
+        assert_eq!(deletion_candidates(&module, Some(&tree)), vec!["_legacy_trim", "unused_helper"]);
+
+        let dynamic = CONTENT.replace("sub unused_helper { return 1 }", "sub dispatch { my $m = shift; return __PACKAGE__->$m() }");
+        let module = StaticModuleParser::new().parse_source(&path, &dynamic).remove(0);
+        assert!(deletion_candidates(&module, Some(&tree)).is_empty());
+
+        // Moo calls builders and triggers itself
+        let content = "package Mailer;\nuse Moo;\nhas smtp => (is => 'lazy');\nhas conn => (is => 'ro', lazy_build => 1, trigger => \\&_conn_set);\n\
+            sub _build_smtp { return {} }\nsub _build_conn { return {} }\nsub _conn_set { }\nsub _unused { }\n1;\n";
+        let module = StaticModuleParser::new().parse_source(Path::new("Mailer.pm"), content).remove(0);
+        assert_eq!(deletion_candidates(&module, None), vec!["_unused"]);
+        Ok(())
+    }
+}
@@ -0,0 +1,464 @@
+use async_trait::async_trait;
+use crate::domain::{
+    models::{CallGraph, PerlModule, ResponsibilityCluster},
+    traits::ResponsibilityAnalyzer,
+};
+use crate::error::Error;
+use crate::parser::{build_call_graph, lower_confidence};
+
+/// Weight of a call in either direction between two subroutines
+const CALL_WEIGHT: f64 = 1.0;
+/// Weight of two subroutines using exactly the same shared variables and
+/// attributes; partial overlap counts proportionally
+const STATE_WEIGHT: f64 = 1.0;
+/// Weight of two subroutines using exactly the same external modules
+const DEPENDENCY_WEIGHT: f64 = 0.5;
+/// Weight of two subroutines whose names consist of the same words
+const NAME_WEIGHT: f64 = 0.5;
+/// Words that say what a subroutine does rather than what it works on
+const GENERIC_WORDS: &[&str] = &[
+    "get", "set", "is", "has", "can", "do", "new", "init", "build", "make", "add", "remove", "delete", "update",
+    "load", "save", "to", "from", "of", "for", "and", "with", "by", "the", "all",
+];
+/// Confidence of a subroutine that ended up in a cluster of its own
+const SINGLETON_CONFIDENCE: f32 = 0.1;
+/// Computed clusters never claim the certainty of a reviewed grouping
+const MAX_CONFIDENCE: f32 = 0.9;
+
+/// Offline, deterministic implementation of the ResponsibilityAnalyzer trait.
+///
+/// Builds a weighted graph of the subroutines from their calls, the shared
+/// variables and attributes they use, the external modules they depend on
+/// and the words their names share, and splits it into communities with
+/// the Louvain method. A cluster's confidence is the share of its
+/// subroutines' link weight that stays inside it, lowered for dynamic code
+/// as in the parsers. The same module always gives the same clusters.
+#[derive(Debug, Clone, Default)]
+pub struct GraphResponsibilityAnalyzer {
+    min_confidence: f32,
+}
+
+impl GraphResponsibilityAnalyzer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Leave out clusters whose confidence ends up below `min_confidence`
+    pub fn with_min_confidence(mut self, min_confidence: f32) -> Self {
+        self.min_confidence = min_confidence;
+        self
+    }
+
+    /// Cluster the subroutines of `module`
+    pub fn clusters(&self, module: &PerlModule) -> Vec<ResponsibilityCluster> {
+        if module.subroutines.is_empty() {
+            return Vec::new();
+        }
+        let graph = SubroutineGraph::new(module);
+        let communities = louvain(&graph.weights);
+
+        let mut members: Vec<Vec<usize>> = Vec::new();
+        for (node, &community) in communities.iter().enumerate() {
+            if community >= members.len() {
+                members.resize(community + 1, Vec::new());
+            }
+            members[community].push(node);
+        }
+        members.retain(|m| !m.is_empty());
+        members.sort_by_key(|m| m[0]);
+
+        let mut clusters: Vec<ResponsibilityCluster> = Vec::new();
+        for nodes in &members {
+            let mut cluster = graph.cluster(module, nodes);
+            let base = cluster.name.clone();
+            let mut n = 2;
+            while clusters.iter().any(|c| c.name == cluster.name) {
+                cluster.name = format!("{}{}", base, n);
+                n += 1;
+            }
+            cluster.suggested_module_name = Some(format!("{}::{}", module.namespace(), cluster.name));
+            clusters.push(cluster);
+        }
+        lower_confidence(&module.subroutines, &mut clusters);
+        clusters.retain(|c| c.confidence >= self.min_confidence);
+        clusters
+    }
+}
+
+#[async_trait]
+impl ResponsibilityAnalyzer for GraphResponsibilityAnalyzer {
+    async fn analyze_module(&self, module: &PerlModule) -> Result<Vec<ResponsibilityCluster>, Error> {
+        Ok(self.clusters(module))
+    }
+
+    /// Nothing to cancel: clustering runs to completion without waiting
+    async fn cancel(&self) {}
+}
+
+/// How far two clusterings of `module` agree, from 0.0 to 1.0: the share of
+/// pairs of subroutines that both put together, or both keep apart. Use it
+/// to check AI clusters against the graph clusters.
+pub fn cluster_agreement(module: &PerlModule, a: &[ResponsibilityCluster], b: &[ResponsibilityCluster]) -> f32 {
+    let together = |clusters: &[ResponsibilityCluster], x: &str, y: &str| {
+        clusters
+            .iter()
+            .any(|c| c.related_subroutines.iter().any(|s| s == x) && c.related_subroutines.iter().any(|s| s == y))
+    };
+    let names: Vec<&str> = module.subroutines.iter().map(|s| s.name.as_str()).collect();
+    let (mut pairs, mut agreeing) = (0, 0);
+    for (i, x) in names.iter().enumerate() {
+        for y in &names[i + 1..] {
+            pairs += 1;
+            if together(a, x, y) == together(b, x, y) {
+                agreeing += 1;
+            }
+        }
+    }
+    if pairs == 0 { 1.0 } else { agreeing as f32 / pairs as f32 }
+}
+
+/// Subroutines as nodes, with symmetric link weights and what each link is
+/// made of
+struct SubroutineGraph {
+    weights: Vec<Vec<f64>>,
+    call_graph: CallGraph,
+    state: Vec<Vec<String>>,
+    dependencies: Vec<Vec<String>>,
+    words: Vec<Vec<String>>,
+}
+
+impl SubroutineGraph {
+    fn new(module: &PerlModule) -> Self {
+        let call_graph = if module.call_graph.edges.is_empty() {
+            build_call_graph(module)
+        } else {
+            module.call_graph.clone()
+        };
+        let subs = &module.subroutines;
+        let state: Vec<Vec<String>> = subs
+            .iter()
+            .map(|sub| {
+                let mut state: Vec<String> = sub.reads.iter().chain(&sub.writes).cloned().collect();
+                let attributes = module.attributes.iter().filter(|a| a.linked_subroutines().contains(&sub.name.as_str()));
+                state.extend(attributes.map(|a| a.name.clone()));
+                state.sort();
+                state.dedup();
+                state
+            })
+            .collect();
+        let dependencies: Vec<Vec<String>> = subs
+            .iter()
+            .map(|sub| {
+                let mut dependencies = sub.dependencies.clone();
+                dependencies.sort();
+                dependencies.dedup();
+                dependencies
+            })
+            .collect();
+        let words: Vec<Vec<String>> = subs.iter().map(|sub| name_words(&sub.name)).collect();
+
+        let mut weights = vec![vec![0.0; subs.len()]; subs.len()];
+        for i in 0..subs.len() {
+            for j in i + 1..subs.len() {
+                let calls = [(i, j), (j, i)]
+                    .iter()
+                    .filter(|(from, to)| call_graph.callees(&subs[*from].name).contains(&subs[*to].name.as_str()))
+                    .count();
+                let weight = CALL_WEIGHT * calls as f64
+                    + STATE_WEIGHT * jaccard(&state[i], &state[j])
+                    + DEPENDENCY_WEIGHT * jaccard(&dependencies[i], &dependencies[j])
+                    + NAME_WEIGHT * jaccard(&words[i], &words[j]);
+                weights[i][j] = weight;
+                weights[j][i] = weight;
+            }
+        }
+        Self { weights, call_graph, state, dependencies, words }
+    }
+
+    /// The cluster of the subroutines at `nodes`, named after the word of
+    /// their names or the shared variable they have most in common
+    fn cluster(&self, module: &PerlModule, nodes: &[usize]) -> ResponsibilityCluster {
+        let subs: Vec<&str> = nodes.iter().map(|&n| module.subroutines[n].name.as_str()).collect();
+        let (mut internal, mut cut) = (0.0, 0.0);
+        for &i in nodes {
+            for (j, weight) in self.weights[i].iter().enumerate() {
+                if !nodes.contains(&j) {
+                    cut += weight;
+                } else if i < j {
+                    internal += weight;
+                }
+            }
+        }
+
+        let shared_state = shared(nodes, &self.state);
+        let shared_dependencies = shared(nodes, &self.dependencies);
+        // Words of the package name, like `order` in `OrderManager`, say nothing about the cluster
+        let package_words = name_words(&module.namespace());
+        let name = shared(nodes, &self.words)
+            .iter()
+            .find(|word| !package_words.contains(word))
+            .or_else(|| shared_state.first())
+            .map(|name| camel_case(name))
+            .unwrap_or_else(|| {
+                // The subroutine most tightly linked to the rest
+                let hub = nodes
+                    .iter()
+                    .copied()
+                    .max_by(|&a, &b| {
+                        let total = |n: usize| nodes.iter().map(|&m| self.weights[n][m]).sum::<f64>();
+                        total(a).total_cmp(&total(b)).then(b.cmp(&a))
+                    })
+                    .unwrap_or(nodes[0]);
+                camel_case(&module.subroutines[hub].name)
+            });
+
+        let description = if nodes.len() == 1 {
+            format!("{} has no strong links to the other subroutines", subs[0])
+        } else {
+            let calls = self.call_graph.edges
+                .iter()
+                .filter(|e| subs.contains(&e.caller.as_str()) && subs.contains(&e.callee.as_str()) && e.caller != e.callee)
+                .count();
+            let mut links = vec![format!("{} call(s) between them", calls)];
+            if !shared_state.is_empty() {
+                links.push(format!("shared state {}", shared_state.join(", ")));
+            }
+            if !shared_dependencies.is_empty() {
+                links.push(format!("dependencies {}", shared_dependencies.join(", ")));
+            }
+            format!("Subroutines linked by {}", links.join("; "))
+        };
+
+        let confidence = if nodes.len() == 1 || internal == 0.0 {
+            SINGLETON_CONFIDENCE
+        } else {
+            (internal / (internal + cut)) as f32 * MAX_CONFIDENCE
+        };
+
+        ResponsibilityCluster {
+            name,
+            description,
+            related_subroutines: subs.iter().map(|s| s.to_string()).collect(),
+            suggested_module_name: None,
+            confidence,
+        }
+    }
+}
+
+/// Communities of the graph with symmetric `weights`, as one community
+/// index per node.
+///
+/// Louvain: nodes move to the neighbouring community that raises modularity
+/// most, then communities are merged into single nodes and this repeats
+/// until nothing moves. Nodes are visited in order and ties go to the
+/// community first found, so the result is deterministic.
+fn louvain(weights: &[Vec<f64>]) -> Vec<usize> {
+    let mut assignment: Vec<usize> = (0..weights.len()).collect();
+    let mut graph = weights.to_vec();
+    loop {
+        let communities = move_nodes(&graph);
+        let count = communities.iter().max().map_or(0, |&c| c + 1);
+        if count == graph.len() {
+            return assignment;
+        }
+        for community in &mut assignment {
+            *community = communities[*community];
+        }
+        let mut merged = vec![vec![0.0; count]; count];
+        for (i, row) in graph.iter().enumerate() {
+            for (j, weight) in row.iter().enumerate() {
+                merged[communities[i]][communities[j]] += weight;
+            }
+        }
+        graph = merged;
+    }
+}
+
+/// One local moving phase of `louvain`; communities are numbered from 0 in
+/// order of their first node
+fn move_nodes(weights: &[Vec<f64>]) -> Vec<usize> {
+    let n = weights.len();
+    let degree: Vec<f64> = weights.iter().map(|row| row.iter().sum()).collect();
+    let total: f64 = degree.iter().sum();
+    let mut community: Vec<usize> = (0..n).collect();
+    if total == 0.0 {
+        return community;
+    }
+    let mut community_degree = degree.clone();
+
+    let mut moved = true;
+    while moved {
+        moved = false;
+        for node in 0..n {
+            let current = community[node];
+            community_degree[current] -= degree[node];
+
+            let mut links: Vec<(usize, f64)> = Vec::new();
+            for (other, &weight) in weights[node].iter().enumerate() {
+                if other == node || weight == 0.0 {
+                    continue;
+                }
+                match links.iter_mut().find(|(c, _)| *c == community[other]) {
+                    Some((_, sum)) => *sum += weight,
+                    None => links.push((community[other], weight)),
+                }
+            }
+            let gain = |c: usize, link: f64| link - community_degree[c] * degree[node] / total;
+            let stay = links.iter().find(|(c, _)| *c == current).map_or(0.0, |(_, link)| *link);
+            let (mut best, mut best_gain) = (current, gain(current, stay));
+            for &(c, link) in &links {
+                if gain(c, link) > best_gain + f64::EPSILON {
+                    (best, best_gain) = (c, gain(c, link));
+                }
+            }
+
+            community_degree[best] += degree[node];
+            if best != current {
+                community[node] = best;
+                moved = true;
+            }
+        }
+    }
+
+    let mut numbers: Vec<usize> = Vec::new();
+    community
+        .iter()
+        .map(|c| match numbers.iter().position(|n| n == c) {
+            Some(number) => number,
+            None => {
+                numbers.push(*c);
+                numbers.len() - 1
+            }
+        })
+        .collect()
+}
+
+fn jaccard(a: &[String], b: &[String]) -> f64 {
+    let common = a.iter().filter(|x| b.contains(x)).count();
+    let all = a.len() + b.len() - common;
+    if all == 0 { 0.0 } else { common as f64 / all as f64 }
+}
+
+/// Items of `sets` used by at least two of `nodes`, most used first
+fn shared(nodes: &[usize], sets: &[Vec<String>]) -> Vec<String> {
+    let mut counts: Vec<(String, usize)> = Vec::new();
+    for &node in nodes {
+        for item in &sets[node] {
+            match counts.iter_mut().find(|(i, _)| i == item) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((item.clone(), 1)),
+            }
+        }
+    }
+    counts.retain(|(_, count)| *count >= 2);
+    // Stable, so equally used items keep their order of first use
+    counts.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    counts.into_iter().map(|(item, _)| item).collect()
+}
+
+/// The meaningful lowercase words of a subroutine name, from snake_case or
+/// camelCase
+fn name_words(name: &str) -> Vec<String> {
+    let mut words: Vec<String> = Vec::new();
+    let mut word = String::new();
+    let mut previous_lower = false;
+    for c in name.chars().chain(std::iter::once('_')) {
+        if !c.is_ascii_alphanumeric() || (c.is_ascii_uppercase() && previous_lower) {
+            if word.len() > 1 && !GENERIC_WORDS.contains(&word.as_str()) && !words.contains(&word) {
+                words.push(word.clone());
+            }
+            word.clear();
+        }
+        if c.is_ascii_alphanumeric() {
+            word.push(c.to_ascii_lowercase());
+        }
+        previous_lower = c.is_ascii_lowercase() || c.is_ascii_digit();
+    }
+    words
+}
+
+/// `%items` or `order_total` as `Items` or `OrderTotal`
+fn camel_case(name: &str) -> String {
+    name.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            chars.next().map(|c| c.to_ascii_uppercase()).into_iter().chain(chars).collect::<String>()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use crate::parser::StaticModuleParser;
+    use super::*;
+
+    const CONTENT: &str = r#"package Shop;
+use strict;
+use DBI;
+use HTML::Template;
+
+my %cart;
+my $dbh;
+
+sub add_item { my ($name, $qty) = @_; $cart{$name} += $qty; return cart_total() }
+sub remove_item { my ($name) = @_; delete $cart{$name}; return cart_total() }
+sub cart_total { my $sum = 0; $sum += $_ for values %cart; return $sum }
+
+sub connect_db { $dbh = DBI->connect('dbi:SQLite:shop.db') }
+sub fetch_price { my ($name) = @_; connect_db() unless $dbh; return $dbh->selectrow_array('SELECT price FROM items WHERE name = ?', undef, $name) }
+sub store_order { connect_db() unless $dbh; $dbh->do('INSERT INTO orders DEFAULT VALUES') }
+
+sub render_page { my $t = HTML::Template->new(filename => 'page.tmpl'); return $t->output }
+
+1;
+"#;
+
+    #[test]
+    fn test_graph_clusters() {
+        let module = StaticModuleParser::new().parse_source(Path::new("Shop.pm"), CONTENT).remove(0);
+        let analyzer = GraphResponsibilityAnalyzer::new();
+        let clusters = analyzer.clusters(&module);
+
+        let groups: Vec<Vec<&str>> = clusters
+            .iter()
+            .map(|c| c.related_subroutines.iter().map(|s| s.as_str()).collect())
+            .collect();
+        assert_eq!(groups, vec![
+            vec!["add_item", "remove_item", "cart_total"],
+            vec!["connect_db", "fetch_price", "store_order"],
+            vec!["render_page"],
+        ]);
+        assert_eq!(clusters[0].name, "Item");
+        assert_eq!(clusters[0].suggested_module_name.as_deref(), Some("Shop::Item"));
+        assert!(clusters[0].description.contains("%cart"));
+        assert!(clusters[0].confidence > 0.5);
+        assert_eq!(clusters[2].confidence, SINGLETON_CONFIDENCE);
+
+        // Deterministic, and a clustering agrees with itself
+        assert_eq!(analyzer.clusters(&module), clusters);
+        assert_eq!(cluster_agreement(&module, &clusters, &clusters), 1.0);
+        let one = vec![ResponsibilityCluster {
+            related_subroutines: module.subroutines.iter().map(|s| s.name.clone()).collect(),
+            ..clusters[0].clone()
+        }];
+        assert!(cluster_agreement(&module, &clusters, &one) < 0.5);
+
+        let filtered = GraphResponsibilityAnalyzer::new().with_min_confidence(0.5).clusters(&module);
+        assert_eq!(filtered.len(), 2);
+    }
+
+    #[test]
+    fn test_louvain_splits_two_triangles() {
+        let mut weights = vec![vec![0.0; 6]; 6];
+        for (a, b) in [(0, 1), (1, 2), (0, 2), (3, 4), (4, 5), (3, 5)] {
+            weights[a][b] = 1.0;
+            weights[b][a] = 1.0;
+        }
+        weights[2][3] = 0.1;
+        weights[3][2] = 0.1;
+        assert_eq!(louvain(&weights), vec![0, 0, 0, 1, 1, 1]);
+        assert_eq!(louvain(&vec![vec![0.0; 2]; 2]), vec![0, 1]);
+    }
+}
@@ -1,33 +1,284 @@
+use std::error::Error as _;
 use async_trait::async_trait;
-use tokio::sync::{oneshot, Mutex};
+use rig::{
+    agent::{Agent, AgentBuilder},
+    completion::{CompletionModel, Prompt},
+};
+use serde::Serialize;
 use crate::domain::{
     models::{PerlModule, ResponsibilityCluster},
     traits::ResponsibilityAnalyzer,
 };
+use crate::cancel::Cancellation;
 use crate::error::Error;
+use crate::parser::{lower_confidence, summarize_subroutines, ClusterResponse, SubroutineSummary};
+
+mod clones;
+mod dead_code;
+mod graph;
+
+pub use clones::{find_clones, helper_module, SharedModule, DEFAULT_MIN_SIMILARITY};
+pub use dead_code::{deletion_candidates, SourceTree};
+pub use graph::{cluster_agreement, GraphResponsibilityAnalyzer};
+
+const ANALYSIS_PROMPT: &str = "Identify the responsibilities of this Perl package so it can be split into smaller modules. Its structure has already been extracted: you are given the package name, its dependencies, the shared state and attributes its subroutines use, and each subroutine with its signature, a summary and the other subroutines of the package it calls. Return ONLY a raw JSON object (no markdown formatting, no code blocks) containing:
+            - responsibility_clusters: Array of objects, each containing:
+                - name: A descriptive name for this responsibility cluster
+                - description: A clear explanation of what this group of subroutines does
+                - related_subroutines: Array of subroutine names that belong to this cluster
+                - suggested_module_name: A suggested name for a new module if this cluster were extracted
+                - confidence: A float between 0.0 and 1.0 indicating confidence in this grouping
+
+            IMPORTANT:
+            1. Only use subroutine names from the package below, and place every subroutine in at least one cluster.
+            2. Group subroutines that work together on one task: they call each other, read or write the same shared variables, or use the same attributes.
+            3. Keep an attribute's builder, trigger and the methods using it together.
+            4. Instance methods of a class only belong to a separate cluster if they could be extracted into a role.
+            5. A subroutine may belong to several clusters only if it clearly serves several purposes.
+            6. Assign high confidence (>0.8) only when the relationship is very clear.
+
+            Package:
+            {}
+
+            ";
+
+/// What the model gets to see about the package
+#[derive(Debug, Serialize)]
+struct PackageDescription<'a> {
+    package_name: &'a str,
+    /// `use`/`require` statements, so runtime and conditional loads stand out
+    dependencies: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    class: Option<String>,
+    /// Attribute names with the subroutines linked to them
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    attributes: Vec<String>,
+    subroutines: Vec<SubroutineDescription>,
+}
+
+#[derive(Debug, Serialize)]
+struct SubroutineDescription {
+    #[serde(flatten)]
+    summary: SubroutineSummary,
+    /// File-scoped and package variables the subroutine reads or writes
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    shared_state: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<String>,
+}
 
-pub struct AIResponsibilityAnalyzer {
-    cancel_sender: Mutex<Option<oneshot::Sender<()>>>,
+/// AI-powered implementation of the ResponsibilityAnalyzer trait.
+///
+/// Clusters the subroutines of an already parsed module, e.g. one loaded
+/// from a saved analysis, so clustering can be repeated with other settings
+/// or another model without parsing the file again. Subroutine names the
+/// model makes up are dropped, confidence is lowered for dynamic code as in
+/// the parsers, and clusters below the minimum confidence are left out.
+pub struct AIResponsibilityAnalyzer<M: CompletionModel> {
+    agent: Agent<M>,
+    include_code: bool,
+    min_confidence: f32,
+    cancellation: Cancellation,
 }
 
-impl AIResponsibilityAnalyzer {
-    pub fn new() -> Self {
+impl<M: CompletionModel> AIResponsibilityAnalyzer<M> {
+    pub fn new(agent_builder: AgentBuilder<M>) -> Self {
         Self {
-            cancel_sender: Mutex::new(None),
+            agent: agent_builder
+                .preamble("You are a Perl code analyzer. You will group the subroutines of a Perl package into cohesive responsibilities.")
+                .build(),
+            include_code: false,
+            min_confidence: 0.0,
+            cancellation: Cancellation::new(),
+        }
+    }
+
+    /// Share `cancellation` with other parts of the pipeline, so cancelling
+    /// it or calling `cancel` stops them all
+    pub fn with_cancellation(mut self, cancellation: Cancellation) -> Self {
+        self.cancellation = cancellation;
+        self
+    }
+
+    /// Send the full code of every subroutine instead of only its summary
+    pub fn with_full_code(mut self, include_code: bool) -> Self {
+        self.include_code = include_code;
+        self
+    }
+
+    /// Leave out clusters whose confidence ends up below `min_confidence`
+    pub fn with_min_confidence(mut self, min_confidence: f32) -> Self {
+        self.min_confidence = min_confidence;
+        self
+    }
+
+    fn describe<'a>(&self, module: &'a PerlModule) -> PackageDescription<'a> {
+        let subroutines = summarize_subroutines(module)
+            .into_iter()
+            .zip(&module.subroutines)
+            .map(|(summary, sub)| {
+                let mut shared_state: Vec<String> = sub.reads.iter().chain(&sub.writes).cloned().collect();
+                shared_state.sort();
+                shared_state.dedup();
+                SubroutineDescription {
+                    summary,
+                    shared_state,
+                    code: self.include_code.then(|| sub.code.clone()),
+                }
+            })
+            .collect();
+
+        PackageDescription {
+            package_name: &module.name,
+            dependencies: module.dependencies.iter().map(|d| d.declaration()).collect(),
+            class: module.class.as_ref().map(|class| match (&class.object_system, class.is_role) {
+                (Some(system), true) => format!("{} role", system),
+                (Some(system), false) => format!("{} class", system),
+                (None, _) => "class".to_string(),
+            }),
+            attributes: module.attributes
+                .iter()
+                .map(|a| format!("{} (used by {})", a.name, a.linked_subroutines().join(", ")))
+                .collect(),
+            subroutines,
         }
     }
 }
 
 #[async_trait]
-impl ResponsibilityAnalyzer for AIResponsibilityAnalyzer {
-    async fn analyze_module(&self, _module: &PerlModule) -> Result<Vec<ResponsibilityCluster>, Error> {
-        // TODO: Implement actual analysis logic using AI
-        Err(Error::AnalysisError("Not implemented".to_string()))
+impl<M: CompletionModel> ResponsibilityAnalyzer for AIResponsibilityAnalyzer<M> {
+    async fn analyze_module(&self, module: &PerlModule) -> Result<Vec<ResponsibilityCluster>, Error> {
+        if module.subroutines.is_empty() {
+            return Ok(Vec::new());
+        }
+        let prompt = ANALYSIS_PROMPT.replace("{}", &serde_json::to_string_pretty(&self.describe(module))?);
+
+        let response = self.cancellation.run(self.agent.prompt(prompt.as_str())).await?;
+        let response = response.map_err(|e| {
+            if let Some(source) = e.source() {
+                eprintln!("{}", source);
+            }
+            Error::AIError(e.to_string())
+        })?;
+
+        let mut clusters = serde_json::from_str::<ClusterResponse>(&response)
+            .map_err(|e| {
+                eprintln!("Failed to parse response content: {}", response);
+                Error::AnalysisError(format!("Failed to parse AI response: {}", e))
+            })?
+            .into_clusters(&module.subroutines);
+        lower_confidence(&module.subroutines, &mut clusters);
+        clusters.retain(|c| c.confidence >= self.min_confidence);
+        Ok(clusters)
     }
 
     async fn cancel(&self) {
-        if let Some(sender) = self.cancel_sender.lock().await.take() {
-            let _ = sender.send(());
-        }
+        self.cancellation.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use rig::providers::azure::Client;
+    use serde_json::json;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+    use wiremock::matchers::{method, path};
+    use crate::parser::StaticModuleParser;
+    use super::*;
+
+    const CONTENT: &str = r#"package Store;
+use strict;
+
+my %items;
+
+sub add { my ($name, $price) = @_; $items{$name} = $price }
+sub total { my $sum = 0; $sum += $_ for values %items; return $sum }
+sub render { my ($title) = @_; return "<h1>$title</h1>" }
+
+1;
+"#;
+
+    #[tokio::test]
+    async fn test_analyze_parsed_module() -> Result<(), Box<dyn std::error::Error>> {
+        let mock_server = MockServer::start().await;
+        let clusters = json!({
+            "responsibility_clusters": [
+                {
+                    "name": "Inventory",
+                    "description": "Keeps items and prices",
+                    "related_subroutines": ["add", "total", "remove"],
+                    "suggested_module_name": "Store::Inventory",
+                    "confidence": 0.9
+                },
+                {
+                    "name": "Rendering",
+                    "description": "Renders HTML",
+                    "related_subroutines": ["render"],
+                    "suggested_module_name": null,
+                    "confidence": 0.4
+                },
+                {
+                    "name": "Imaginary",
+                    "description": "Only unknown subroutines",
+                    "related_subroutines": ["checkout"],
+                    "suggested_module_name": null,
+                    "confidence": 0.95
+                }
+            ]
+        });
+        Mock::given(method("POST"))
+            .and(path("/openai/deployments/gpt-4o-2024-08-06/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "test-id",
+                "object": "chat.completion",
+                "created": 1234567890,
+                "model": "gpt-4o-2024-08-06",
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": clusters.to_string() },
+                    "finish_reason": "stop"
+                }],
+                "usage": { "prompt_tokens": 100, "completion_tokens": 50, "total_tokens": 150 }
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let module = StaticModuleParser::new().parse_source(Path::new("Store.pm"), CONTENT).remove(0);
+        let client = Client::from_api_key("test-key", "test-version", mock_server.uri().as_str());
+        let analyzer = AIResponsibilityAnalyzer::new(AgentBuilder::new(client.completion_model("gpt-4o-2024-08-06")))
+            .with_min_confidence(0.5);
+        let clusters = analyzer.analyze_module(&module).await?;
+
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].name, "Inventory");
+        assert_eq!(clusters[0].related_subroutines, vec!["add", "total"]);
+
+        let requests = mock_server.received_requests().await.unwrap_or_default();
+        let body = String::from_utf8_lossy(&requests[0].body);
+        assert!(body.contains("%items"));
+        assert!(!body.contains("<h1>"));
+        Ok(())
     }
-} 
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_cancel_stops_request_in_flight() -> Result<(), Box<dyn std::error::Error>> {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(30)))
+            .mount(&mock_server)
+            .await;
+
+        let module = StaticModuleParser::new().parse_source(Path::new("Store.pm"), CONTENT).remove(0);
+        let client = Client::from_api_key("test-key", "test-version", mock_server.uri().as_str());
+        let analyzer = AIResponsibilityAnalyzer::new(AgentBuilder::new(client.completion_model("gpt-4o-2024-08-06")));
+        let (result, _) = tokio::join!(analyzer.analyze_module(&module), async {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            analyzer.cancel().await;
+        });
+
+        assert!(matches!(result, Err(Error::Cancelled)));
+        Ok(())
+    }
+}
@@ -0,0 +1,76 @@
+use std::future::Future;
+use std::sync::Arc;
+use tokio::sync::watch;
+use crate::error::Error;
+
+/// Cooperative cancellation shared by the parsers, the analyzer and the
+/// proposer.
+///
+/// Clones share the same state: cancelling one cancels them all, and stays
+/// in effect. AI requests in flight are dropped at the next `run`, which
+/// then returns `Error::Cancelled`; work finished before that is kept.
+#[derive(Debug, Clone)]
+pub struct Cancellation {
+    sender: Arc<watch::Sender<bool>>,
+}
+
+impl Default for Cancellation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Cancellation {
+    pub fn new() -> Self {
+        Self { sender: Arc::new(watch::Sender::new(false)) }
+    }
+
+    pub fn cancel(&self) {
+        self.sender.send_replace(true);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.sender.borrow()
+    }
+
+    /// Resolves once `cancel` has been called
+    pub async fn cancelled(&self) {
+        let mut receiver = self.sender.subscribe();
+        // The sender lives as long as `self`, so this only ends by cancelling
+        let _ = receiver.wait_for(|cancelled| *cancelled).await;
+    }
+
+    /// Run `future` unless cancelled first, dropping it when cancelled while
+    /// it runs
+    pub async fn run<F: Future>(&self, future: F) -> Result<F::Output, Error> {
+        if self.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        tokio::select! {
+            output = future => Ok(output),
+            _ = self.cancelled() => Err(Error::Cancelled),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::*;
+
+    #[tokio::test]
+    async fn test_cancel_stops_pending_work() {
+        let cancellation = Cancellation::new();
+        assert_eq!(cancellation.run(async { 1 }).await.ok(), Some(1));
+
+        let handle = cancellation.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            handle.cancel();
+        });
+        let pending = cancellation.run(tokio::time::sleep(Duration::from_secs(60))).await;
+        assert!(matches!(pending, Err(Error::Cancelled)));
+        assert!(cancellation.is_cancelled());
+        assert!(matches!(cancellation.run(async { 2 }).await, Err(Error::Cancelled)));
+    }
+}
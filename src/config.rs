@@ -1,19 +1,23 @@
 use std::env;
-use std::mem::ManuallyDrop;
 use rig::completion::CompletionModel;
 use rig::providers::{/*azure,*/ azure, groq};
 use rig::agent::{AgentBuilder};
+use crate::error::Error;
 
 
 pub struct Config {
-    pub provider_client: groq::Client,
+    pub provider_client: Option<groq::Client>,
+    /// Model the agents are built for
+    pub model: String,
 }
 
 pub trait AgentProvider<M: CompletionModel> {
     fn get_agent() -> AgentBuilder<M>;
 }
 
+#[allow(dead_code)]
 struct GroqConfig {}
+#[allow(dead_code)]
 struct AzureConfig {}
 
 impl AgentProvider<groq::CompletionModel> for GroqConfig {
@@ -21,7 +25,7 @@ impl AgentProvider<groq::CompletionModel> for GroqConfig {
         groq::Client::from_env().agent(groq::LLAMA_3_2_90B_VISION_PREVIEW)
     }
 }
-    
+
 impl AgentProvider<azure::CompletionModel> for AzureConfig {
     fn get_agent() -> AgentBuilder<azure::CompletionModel> {
         azure::Client::from_env().agent(azure::GPT_4O)
@@ -29,19 +33,36 @@ impl AgentProvider<azure::CompletionModel> for AzureConfig {
 }
 
 impl Config {
+    /// Load the AI provider from the environment. A missing provider is not an
+    /// error here so that offline commands (e.g. static parsing) keep working;
+    /// `get_agent` reports it instead.
     pub fn from_env() -> Self {
         match env::var("GROQ_API_KEY") {
             Ok(_) =>  Self {
-                provider_client: groq::Client::from_env(),
+                provider_client: Some(groq::Client::from_env()),
+                model: groq::LLAMA_3_2_90B_VISION_PREVIEW.to_string(),
             },
             //Err(_) => match env::var("AZURE_API_KEY") {
             //    Ok(_) => Self {
             //        ai_provider: AIProvider::Azure(azure::Client::from_env().agent(azure::GPT_4O)),
             //    },
-            Err(_) => panic!("No AI provider found"),
+            Err(_) => Self {
+                provider_client: None,
+                model: groq::LLAMA_3_2_90B_VISION_PREVIEW.to_string(),
+            },
         }
     }
-    pub fn get_agent(&self) -> AgentBuilder<groq::CompletionModel> {
-        self.provider_client.agent(groq::LLAMA_3_2_90B_VISION_PREVIEW)
+
+    /// Build agents for another model of the same provider
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = model.into();
+        self
+    }
+
+    pub fn get_agent(&self) -> Result<AgentBuilder<groq::CompletionModel>, Error> {
+        self.provider_client
+            .as_ref()
+            .map(|client| client.agent(&self.model))
+            .ok_or(Error::NoAIProvider)
     }
-} 
\ No newline at end of file
+}
@@ -1,105 +1,685 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::fs;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
 use crate::{
+    cancel::Cancellation,
     config::Config,
-    parser::AIModuleParser,
+    parser::{encode_source, perl_files, AIModuleParser, AnalysisProgress, HybridModuleParser, StaticModuleParser, DEFAULT_TOKEN_BUDGET},
+    analyzer::{
+        cluster_agreement, deletion_candidates, find_clones, helper_module, AIResponsibilityAnalyzer, GraphResponsibilityAnalyzer,
+        SourceTree, DEFAULT_MIN_SIMILARITY,
+    },
     proposer::AIRefactoringProposer,
+    validator::DefaultDependencyValidator,
     domain::{
-        models::{PerlModule, RefactoringProposal},
-        traits::{ModuleParser, RefactoringProposer},
+        models::{Charset, CloneLocation, ClonePair, FileKind, LineEnding, LoadPhase, ModuleKind, ModuleMetrics, NewModuleProposal, PerlModule, RefactoringProposal, SourceEncoding, SubroutineKind, TestPlan},
+        traits::{DependencyValidator, ModuleParser, RefactoringProposer, ResponsibilityAnalyzer},
     },
     error::Error,
 };
 
 
+/// Which parser implementation the app uses to analyze Perl files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ParserMode {
+    /// Structure and responsibility clusters come from the AI provider
+    #[default]
+    Ai,
+    /// Offline, deterministic parsing; no responsibility clusters
+    Static,
+    /// Static structure extraction; the AI provider only identifies clusters
+    Hybrid,
+}
+
+impl FromStr for ParserMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ai" => Ok(Self::Ai),
+            "static" => Ok(Self::Static),
+            "hybrid" => Ok(Self::Hybrid),
+            other => Err(Error::ValidationError(format!("Unknown parser '{}', expected 'ai', 'static' or 'hybrid'", other))),
+        }
+    }
+}
+
+/// Which analyzer identifies responsibility clusters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AnalyzerMode {
+    /// The AI provider groups subroutines, or the parser does in AI and
+    /// hybrid mode
+    #[default]
+    Ai,
+    /// Offline, deterministic community detection on the graph of calls,
+    /// shared state, dependencies and names
+    Graph,
+}
+
+impl FromStr for AnalyzerMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ai" => Ok(Self::Ai),
+            "graph" => Ok(Self::Graph),
+            other => Err(Error::ValidationError(format!("Unknown analyzer '{}', expected 'ai' or 'graph'", other))),
+        }
+    }
+}
+
+/// On-disk analysis format. A single package is stored as a plain object so
+/// that analyses of one-package files keep their original shape.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SavedAnalysis {
+    Package(Box<PerlModule>),
+    Packages(Vec<PerlModule>),
+}
+
+/// What `App::detect_clones` prints as JSON
+#[derive(Serialize)]
+struct CloneReport<'a> {
+    clones: &'a [ClonePair],
+    helper_module: Option<&'a NewModuleProposal>,
+}
+
+fn analysis_json(modules: &[PerlModule]) -> Result<String, Error> {
+    let json = match modules {
+        [module] => serde_json::to_string_pretty(module),
+        modules => serde_json::to_string_pretty(modules),
+    };
+    json.map_err(|e| Error::SerializationError(e.to_string()))
+}
+
+/// Text for a file next to the original: in its encoding and line endings,
+/// or in UTF-8 (with a warning) when the text does not fit its charset
+fn encoded(text: &str, encoding: &SourceEncoding) -> Vec<u8> {
+    encode_source(text, encoding).unwrap_or_else(|e| {
+        eprintln!("Warning: {}; writing UTF-8 instead", e);
+        let utf8 = SourceEncoding { charset: Charset::Utf8, ..encoding.clone() };
+        encode_source(text, &utf8).unwrap_or_else(|_| text.as_bytes().to_vec())
+    })
+}
+
+/// Write through a temporary file renamed into place, so an interrupted run
+/// never leaves a half-written file behind
+fn write_file(path: &Path, contents: &[u8]) -> Result<(), Error> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    fs::write(&partial, contents).map_err(Error::IOError)?;
+    fs::rename(&partial, path).map_err(Error::IOError)
+}
+
+fn metrics_text(metrics: &ModuleMetrics) -> String {
+    format!(
+        "{} ({} subs): LCOM4 {}, {:.0}% internal calls ({} internal, {} external), fan-in {}, fan-out {}, instability {:.2}",
+        metrics.name,
+        metrics.subroutines,
+        metrics.lcom4,
+        metrics.internal_call_ratio * 100.0,
+        metrics.internal_calls,
+        metrics.external_calls,
+        metrics.afferent_coupling,
+        metrics.efferent_coupling,
+        metrics.instability
+    )
+}
+
+/// Write the code of `module` under `base_dir`, at the path its name gives
+/// (MyModule::Submodule -> MyModule/Submodule.pm)
+fn write_module(base_dir: &Path, module: &NewModuleProposal, encoding: &SourceEncoding) -> Result<PathBuf, Error> {
+    let path_parts: Vec<_> = module.name.split("::").collect();
+    let mut file_path = base_dir.to_path_buf();
+
+    // Create directory structure if needed
+    for part in &path_parts[0..path_parts.len() - 1] {
+        file_path.push(part);
+    }
+    fs::create_dir_all(&file_path).map_err(Error::IOError)?;
+
+    file_path.push(format!("{}.pm", path_parts.last().unwrap_or(&"Unknown")));
+    write_file(&file_path, &encoded(&module.suggested_code, encoding))?;
+    Ok(file_path)
+}
+
+/// Progress goes to stderr so that JSON output stays parseable
+fn print_progress(progress: &AnalysisProgress) {
+    match progress {
+        AnalysisProgress::Analyzing { chunk, total, line_start, line_end } => {
+            eprintln!("Analyzing part {}/{} (lines {}-{})...", chunk, total, line_start, line_end);
+        }
+        AnalysisProgress::Failed { chunk, total, error } => eprintln!("Part {}/{} failed: {}", chunk, total, error),
+        AnalysisProgress::Merging { package } => eprintln!("Merging responsibility clusters of {}...", package),
+    }
+}
+
+/// Below this share of agreeing subroutine pairs, AI clusters are reported
+/// as differing from the graph clusters
+const MIN_AGREEMENT: f32 = 0.6;
+
+/// How `App::analyze_responsibilities` clusters subroutines
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AnalysisSettings {
+    /// Send the code of every subroutine instead of summaries
+    pub full_code: bool,
+    /// Drop clusters with a lower confidence
+    pub min_confidence: f32,
+}
+
 pub struct App {
     config: Config,
+    parser_mode: ParserMode,
+    analyzer_mode: AnalyzerMode,
+    token_budget: usize,
+    source_tree: Option<PathBuf>,
+    extract_clones: bool,
+    cancellation: Cancellation,
 }
 
 impl App {
     pub fn new(config: Config) -> Self {
-        Self { config }
+        Self {
+            config,
+            parser_mode: ParserMode::default(),
+            analyzer_mode: AnalyzerMode::default(),
+            token_budget: DEFAULT_TOKEN_BUDGET,
+            source_tree: None,
+            extract_clones: false,
+            cancellation: Cancellation::new(),
+        }
     }
 
-    pub async fn parse_module(&self, file: &PathBuf, format: &str, save: Option<&PathBuf>) -> Result<PerlModule, Error> {
+    pub fn with_parser_mode(mut self, parser_mode: ParserMode) -> Self {
+        self.parser_mode = parser_mode;
+        self
+    }
 
-        let parser = AIModuleParser::new(self.config.get_agent());
-        let module = parser.parse_module(file).await?;
+    /// With `AnalyzerMode::Graph`, parsed packages are clustered offline
+    /// instead of by the AI provider
+    pub fn with_analyzer_mode(mut self, analyzer_mode: AnalyzerMode) -> Self {
+        self.analyzer_mode = analyzer_mode;
+        self
+    }
+
+    /// Estimated tokens of module content per AI request; larger files are
+    /// analyzed in chunks
+    pub fn with_token_budget(mut self, token_budget: usize) -> Self {
+        self.token_budget = token_budget;
+        self
+    }
+
+    /// Look for callers of a module's subroutines in the Perl files under
+    /// `source_tree` before reporting them as candidates for deletion
+    pub fn with_source_tree(mut self, source_tree: PathBuf) -> Self {
+        self.source_tree = Some(source_tree);
+        self
+    }
+
+    /// Add a module with one copy of each group of cloned subroutines to
+    /// every refactoring proposal that has clones
+    pub fn with_clone_extraction(mut self, extract_clones: bool) -> Self {
+        self.extract_clones = extract_clones;
+        self
+    }
+
+    /// Stop AI requests with `Error::Cancelled` once `cancellation` is
+    /// cancelled, keeping the results finished before that
+    pub fn with_cancellation(mut self, cancellation: Cancellation) -> Self {
+        self.cancellation = cancellation;
+        self
+    }
+
+    /// Parse every package in `file`, print the results and optionally save them
+    pub async fn parse_module(&self, file: &PathBuf, format: &str, save: Option<&PathBuf>) -> Result<Vec<PerlModule>, Error> {
+
+        let mut modules = match self.parser_mode {
+            ParserMode::Ai => {
+                AIModuleParser::new(self.config.get_agent()?)
+                    .with_token_budget(self.token_budget)
+                    .with_progress(print_progress)
+                    .with_cancellation(self.cancellation.clone())
+                    .parse_packages(file)
+                    .await?
+            }
+            ParserMode::Static => StaticModuleParser::new().parse_packages(file).await?,
+            ParserMode::Hybrid => {
+                HybridModuleParser::new(self.config.get_agent()?)
+                    .with_cancellation(self.cancellation.clone())
+                    .parse_packages(file)
+                    .await?
+            }
+        };
+        let tree = match &self.source_tree {
+            Some(root) => Some(SourceTree::scan(root).await?),
+            None => None,
+        };
+        for module in &mut modules {
+            module.deletion_candidates = deletion_candidates(module, tree.as_ref());
+            module.clones = find_clones(std::slice::from_ref(module), DEFAULT_MIN_SIMILARITY);
+        }
+        if self.analyzer_mode == AnalyzerMode::Graph {
+            let analyzer = GraphResponsibilityAnalyzer::new();
+            for module in &mut modules {
+                module.responsibility_clusters = analyzer.clusters(module);
+            }
+        }
 
         // Save analysis to file if requested
         if let Some(save_path) = save {
-            self.save_analysis_to_file(&module, save_path)?;
+            self.save_analysis_to_file(&modules, save_path)?;
         }
 
         // Output the results based on format
         match format {
-            "json" => println!("{}", serde_json::to_string_pretty(&module)?),
-            _ => self.print_module_analysis(&module),
+            "json" => println!("{}", analysis_json(&modules)?),
+            _ => {
+                for (i, module) in modules.iter().enumerate() {
+                    if i > 0 {
+                        println!();
+                    }
+                    self.print_module_analysis(module);
+                }
+            }
+        }
+
+        Ok(modules)
+    }
+
+    /// Cluster the subroutines of already parsed packages again, replacing
+    /// their responsibility clusters, then print the results and optionally
+    /// save them. Packages without subroutines are left as they are. If
+    /// cancelled, the packages analyzed so far are still saved.
+    ///
+    /// Without an AI provider, the graph analyzer is used instead. AI
+    /// clusters are checked against graph clusters, and a low agreement is
+    /// reported.
+    pub async fn analyze_responsibilities(
+        &self,
+        mut modules: Vec<PerlModule>,
+        settings: AnalysisSettings,
+        format: &str,
+        save: Option<&PathBuf>,
+    ) -> Result<Vec<PerlModule>, Error> {
+        let graph = GraphResponsibilityAnalyzer::new().with_min_confidence(settings.min_confidence);
+        let agent = match (self.analyzer_mode, self.config.get_agent()) {
+            (AnalyzerMode::Ai, Ok(agent)) => Some(agent),
+            (AnalyzerMode::Ai, Err(Error::NoAIProvider)) => {
+                eprintln!("Warning: no AI provider configured; clustering with the graph analyzer");
+                None
+            }
+            (AnalyzerMode::Ai, Err(e)) => return Err(e),
+            (AnalyzerMode::Graph, _) => None,
+        };
+        // AI clusters get checked against the graph clusters
+        let checked = agent.is_some();
+        let analyzer: Box<dyn ResponsibilityAnalyzer> = match agent {
+            Some(agent) => Box::new(
+                AIResponsibilityAnalyzer::new(agent)
+                    .with_full_code(settings.full_code)
+                    .with_min_confidence(settings.min_confidence)
+                    .with_cancellation(self.cancellation.clone()),
+            ),
+            None => Box::new(graph.clone()),
+        };
+        let mut cancelled = false;
+        for module in &mut modules {
+            if module.subroutines.is_empty() {
+                continue;
+            }
+            eprintln!("Identifying responsibilities of {}...", module.name);
+            match analyzer.analyze_module(module).await {
+                Ok(clusters) => {
+                    if checked {
+                        let agreement = cluster_agreement(module, &clusters, &graph.clusters(module));
+                        if agreement < MIN_AGREEMENT {
+                            eprintln!(
+                                "Warning: the clusters of {} agree with the graph clusters on only {:.0}% of subroutine pairs",
+                                module.name,
+                                agreement * 100.0
+                            );
+                        }
+                    }
+                    module.responsibility_clusters = clusters;
+                }
+                Err(Error::Cancelled) => {
+                    cancelled = true;
+                    break;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        if let Some(save_path) = save {
+            self.save_analysis_to_file(&modules, save_path)?;
+        }
+        if cancelled {
+            return Err(Error::Cancelled);
+        }
+        match format {
+            "json" => println!("{}", analysis_json(&modules)?),
+            _ => {
+                for (i, module) in modules.iter().enumerate() {
+                    if i > 0 {
+                        println!();
+                    }
+                    self.print_module_analysis(module);
+                }
+            }
         }
 
-        Ok(module)
+        Ok(modules)
     }
 
+    /// Generate, print and save a refactoring proposal for every package that
+    /// has responsibility clusters. If cancelled, the modules generated so
+    /// far are saved before `Error::Cancelled` is returned.
     pub async fn propose_refactoring(
         &self,
-        module: &PerlModule,
+        modules: &[PerlModule],
         format: &str,
         output_dir: Option<&PathBuf>
     ) -> Result<(), Error> {
-        if module.responsibility_clusters.is_empty() {
+        if modules.iter().all(|m| m.responsibility_clusters.is_empty()) {
             return Err(Error::ValidationError("No responsibility clusters found to base refactoring on".to_string()));
         }
 
 
-        let proposer = AIRefactoringProposer::new(self.config.get_agent());
-        println!("Generating refactoring proposal...");
-        let proposal = proposer.generate_proposal(module).await?;
-        
-        self.print_proposal(&proposal, format)?;
-        self.save_modules(&proposal, output_dir)?;
+        let proposer = AIRefactoringProposer::new(self.config.get_agent()?)
+            .with_cancellation(self.cancellation.clone())
+            .with_clone_extraction(self.extract_clones);
+        for module in modules {
+            if module.responsibility_clusters.is_empty() {
+                println!("Skipping {}: no responsibility clusters found", module.name);
+                continue;
+            }
+
+            println!("Generating refactoring proposal for {}...", module.name);
+            let proposal = proposer.generate_proposal(module).await?;
+
+            self.print_proposal(&proposal, format)?;
+            if format != "json" {
+                self.print_validation(&proposal)?;
+            }
+            self.save_modules(&proposal, output_dir)?;
+            if proposal.cancelled {
+                return Err(Error::Cancelled);
+            }
+        }
 
         Ok(())
     }
 
-    fn save_analysis_to_file(&self, module: &PerlModule, path: &PathBuf) -> Result<(), Error> {
-        let json = serde_json::to_string_pretty(module)
-            .map_err(|e| Error::SerializationError(e.to_string()))?;
+    /// Find cloned subroutines in `path`, a Perl file or a directory of
+    /// them, print them with a module that could hold one copy of each, and
+    /// optionally write that module to `output_dir`
+    pub async fn detect_clones(
+        &self,
+        path: &Path,
+        min_similarity: f32,
+        format: &str,
+        output_dir: Option<&PathBuf>,
+    ) -> Result<Vec<ClonePair>, Error> {
+        let files = if path.is_dir() { perl_files(path).await? } else { vec![path.to_path_buf()] };
+        let parser = StaticModuleParser::new();
+        let mut modules = Vec::new();
+        for file in &files {
+            modules.extend(parser.parse_packages(file).await?);
+        }
+        let clones = find_clones(&modules, min_similarity);
+
+        // Under the namespace the modules have in common
+        let namespaces: Vec<String> = modules.iter().map(|m| m.namespace()).collect();
+        let mut common: Vec<&str> = namespaces.first().map(|n| n.split("::").collect()).unwrap_or_default();
+        for namespace in &namespaces {
+            let parts: Vec<&str> = namespace.split("::").collect();
+            let shared = common.iter().zip(&parts).take_while(|(a, b)| a == b).count();
+            common.truncate(shared);
+        }
+        common.push("Shared");
+        let helper = helper_module(&common.join("::"), &modules, &clones).map(|shared| shared.module);
+
+        match format {
+            "json" => println!("{}", serde_json::to_string_pretty(&CloneReport { clones: &clones, helper_module: helper.as_ref() })?),
+            _ => {
+                println!("Clones in {} file(s):", files.len());
+                for clone in &clones {
+                    let line = |l: &CloneLocation| l.span.map_or(0, |s| s.line_start);
+                    println!(
+                        "  - {}::{} ({}:{}) and {}::{} ({}:{}): {}, {:.0}% similar",
+                        clone.first.module, clone.first.subroutine, clone.first.path.display(), line(&clone.first),
+                        clone.second.module, clone.second.subroutine, clone.second.path.display(), line(&clone.second),
+                        clone.kind.label(), clone.similarity * 100.0
+                    );
+                }
+                if let Some(helper) = &helper {
+                    println!("\nSuggested helper module: {}", helper.name);
+                    println!("  {}", helper.responsibility);
+                }
+            }
+        }
+
+        if let (Some(dir), Some(helper)) = (output_dir, &helper) {
+            let file_path = write_module(dir, helper, &modules[0].encoding)?;
+            // On stderr so that JSON output stays parseable
+            eprintln!("  - Written: {}", file_path.display());
+        }
+        Ok(clones)
+    }
+
+    fn save_analysis_to_file(&self, modules: &[PerlModule], path: &PathBuf) -> Result<(), Error> {
+        let json = analysis_json(modules)?;
         
         fs::write(path, json)
-            .map_err(|e| Error::IOError(e))?;
+            .map_err(Error::IOError)?;
         
         println!("Analysis saved to: {}", path.display());
         Ok(())
     }
 
-    pub fn load_analysis_from_file(&self, path: &PathBuf) -> Result<PerlModule, Error> {
+    /// Load a saved analysis, either a single module or an array of packages
+    pub fn load_analysis_from_file(&self, path: &PathBuf) -> Result<Vec<PerlModule>, Error> {
         let content = fs::read_to_string(path)
-            .map_err(|e| Error::IOError(e))?;
+            .map_err(Error::IOError)?;
         
-        let module: PerlModule = serde_json::from_str(&content)
+        let analysis: SavedAnalysis = serde_json::from_str(&content)
             .map_err(|e| Error::DeserializationError(format!("Failed to parse saved analysis: {}", e)))?;
         
-        Ok(module)
+        Ok(match analysis {
+            SavedAnalysis::Package(module) => vec![*module],
+            SavedAnalysis::Packages(modules) => modules,
+        })
     }
 
     fn print_module_analysis(&self, module: &PerlModule) {
         println!("Module Analysis Results:");
         println!("Name: {}", module.name);
         println!("Path: {}", module.path.display());
+        let encoding = &module.encoding;
+        println!(
+            "Encoding: {}{}{}",
+            encoding.charset.label(),
+            if encoding.bom { " with BOM" } else { "" },
+            if encoding.line_ending == LineEnding::CrLf { ", CRLF line endings" } else { "" }
+        );
+        match module.file_kind {
+            FileKind::Module => {}
+            FileKind::Script => println!("Kind: script"),
+            FileKind::Test => println!("Kind: test file"),
+        }
         println!("\nDependencies:");
         for dep in &module.dependencies {
-            println!("  - {}", dep);
+            match dep.phase {
+                LoadPhase::CompileTime => println!("  - {}", dep.declaration()),
+                LoadPhase::Runtime => println!("  - {} (runtime)", dep.declaration()),
+                LoadPhase::Conditional => println!("  - {} (conditional)", dep.declaration()),
+            }
+        }
+        if let Some(class) = &module.class {
+            println!("\n{}:", if class.is_role { "Role" } else { "Class" });
+            if let Some(system) = &class.object_system {
+                println!("  Object system: {}", system);
+            }
+            if !class.superclasses.is_empty() {
+                println!("  Superclasses: {}", class.superclasses.join(", "));
+            }
+            if !class.roles.is_empty() {
+                println!("  Roles: {}", class.roles.join(", "));
+            }
+            if let Some(constructor) = &class.constructor {
+                println!("  Constructor: {}", constructor);
+            }
+        }
+        if !module.attributes.is_empty() {
+            println!("\nAttributes:");
+            for attribute in &module.attributes {
+                let mut options = Vec::new();
+                options.extend(attribute.is.as_deref().map(|is| format!("is {}", is)));
+                options.extend(attribute.isa.as_deref().map(|isa| format!("isa {}", isa)));
+                if attribute.required {
+                    options.push("required".to_string());
+                }
+                if attribute.lazy {
+                    options.push("lazy".to_string());
+                }
+                println!("  - {} ({}), lines {}-{}", attribute.name, options.join(", "), attribute.line_start, attribute.line_end);
+                let linked = attribute.linked_subroutines();
+                if !linked.is_empty() {
+                    println!("    Used by: {}", linked.join(", "));
+                }
+            }
+        }
+        if !module.exports.is_empty() {
+            let exports = &module.exports;
+            println!("\nExports ({}):", exports.exporter.as_deref().unwrap_or("unknown exporter"));
+            if !exports.default.is_empty() {
+                println!("  Default: {}", exports.default.join(", "));
+            }
+            if !exports.optional.is_empty() {
+                println!("  On request: {}", exports.optional.join(", "));
+            }
+            for tag in &exports.tags {
+                println!("  :{}: {}", tag.name, tag.symbols.join(", "));
+            }
+        }
+        if !module.documentation.is_empty() {
+            let headings: Vec<&str> = module.documentation.iter().map(|s| s.heading.as_str()).collect();
+            println!("\nDocumentation: {}", headings.join(", "));
         }
         println!("\nSubroutines:");
         for sub in &module.subroutines {
             println!("\n  {}", sub.name);
             println!("  Lines: {}-{}", sub.line_start, sub.line_end);
+            match sub.kind {
+                SubroutineKind::InstanceMethod => println!("  Kind: instance method"),
+                SubroutineKind::ClassMethod => println!("  Kind: class method"),
+                SubroutineKind::Function => {}
+            }
+            let declaration = sub.signature.declaration();
+            if !declaration.is_empty() {
+                println!("  Signature: {}", declaration);
+            }
             if !sub.dependencies.is_empty() {
                 println!("  Dependencies:");
                 for dep in &sub.dependencies {
                     println!("    - {}", dep);
                 }
             }
+            let calls = module.call_graph.callees(&sub.name);
+            if !calls.is_empty() {
+                println!("  Calls: {}", calls.join(", "));
+            }
+            if sub.pod.is_some() {
+                println!("  Documented: yes");
+            }
+            let metrics = &sub.metrics;
+            println!(
+                "  Metrics: {} lines, {} statements, complexity {}, nesting {}, {} parameters, {} returns",
+                metrics.physical_loc,
+                metrics.logical_loc,
+                metrics.cyclomatic_complexity,
+                metrics.max_nesting,
+                metrics.parameters,
+                metrics.return_points
+            );
+            if !metrics.hotspots.is_empty() {
+                println!("  Hotspot: {}", metrics.hotspots.iter().map(|h| h.label()).collect::<Vec<_>>().join(", "));
+            }
+            if !sub.reads.is_empty() {
+                println!("  Reads: {}", sub.reads.join(", "));
+            }
+            if !sub.writes.is_empty() {
+                println!("  Writes: {}", sub.writes.join(", "));
+            }
+        }
+        if !module.deletion_candidates.is_empty() {
+            println!("\nCandidates for deletion: {}", module.deletion_candidates.join(", "));
+        }
+        if !module.clones.is_empty() {
+            println!("\nClones:");
+            for clone in &module.clones {
+                println!(
+                    "  - {} and {}: {}, {:.0}% similar",
+                    clone.first.subroutine, clone.second.subroutine, clone.kind.label(), clone.similarity * 100.0
+                );
+            }
+        }
+        let hotspots: Vec<&str> = module.subroutines
+            .iter()
+            .filter(|s| !s.metrics.hotspots.is_empty())
+            .map(|s| s.name.as_str())
+            .collect();
+        if !hotspots.is_empty() {
+            println!("\nHotspots: {}", hotspots.join(", "));
+        }
+        if !module.variables.is_empty() {
+            println!("\nShared State:");
+            for variable in &module.variables {
+                match variable.line {
+                    Some(line) => println!("  - {} ({:?}, line {})", variable.name, variable.scope, line),
+                    None => println!("  - {} ({:?})", variable.name, variable.scope),
+                }
+            }
+        }
+        if !module.special_blocks.is_empty() {
+            println!("\nLoad-time Code:");
+            for block in &module.special_blocks {
+                println!("  - {} (lines {}-{})", block.kind.label(), block.line_start, block.line_end);
+                let linked = block.linked_subroutines();
+                if !linked.is_empty() {
+                    println!("    Linked to: {}", linked.join(", "));
+                }
+            }
+        }
+        if let Some(data) = &module.data_section {
+            println!("\nData Section: {} (line {})", data.marker, data.line);
+            if !data.used_by.is_empty() {
+                println!("  Read by: {}", data.used_by.join(", "));
+            }
+        }
+        if !module.hazards.is_empty() {
+            println!("\nDynamic Code:");
+            for hazard in &module.hazards {
+                let location = hazard.subroutine.as_deref().unwrap_or("top level");
+                println!("  - {} at line {} ({}): {}", hazard.kind.label(), hazard.line, location, hazard.code);
+            }
+        }
+        if let Some(suite) = &module.test_suite {
+            println!("\nTests ({}):", suite.frameworks.join(", "));
+            match suite.plan {
+                TestPlan::None => println!("  Plan: none"),
+                TestPlan::Tests(count) => println!("  Plan: {} tests", count),
+                TestPlan::SkipAll => println!("  Plan: skip all"),
+                TestPlan::DoneTesting => println!("  Plan: done_testing"),
+            }
+            println!("  Assertions outside subtests: {}", suite.assertions);
+            for subtest in &suite.subtests {
+                println!("  - subtest '{}' (lines {}-{}, {} assertions)", subtest.name, subtest.line_start, subtest.line_end, subtest.assertions);
+                if !subtest.helpers.is_empty() {
+                    println!("    Helpers: {}", subtest.helpers.join(", "));
+                }
+            }
         }
         println!("\nResponsibility Clusters:");
         for cluster in &module.responsibility_clusters {
@@ -114,6 +694,18 @@ impl App {
                 println!("  Suggested module name: {}", name);
             }
         }
+        if !module.diagnostics.is_empty() {
+            println!("\nDiagnostics:");
+            for diagnostic in &module.diagnostics {
+                match &diagnostic.span {
+                    Some(span) => println!(
+                        "  - [{:?}] {}:{}:{}: {}",
+                        diagnostic.severity, module.path.display(), span.line_start, span.column_start, diagnostic.message
+                    ),
+                    None => println!("  - [{:?}] {}", diagnostic.severity, diagnostic.message),
+                }
+            }
+        }
     }
 
     fn print_proposal(&self, proposal: &RefactoringProposal, format: &str) -> Result<(), Error> {
@@ -121,12 +713,19 @@ impl App {
             "json" => println!("{}", serde_json::to_string_pretty(&proposal)?),
             _ => {
                 println!("Refactoring Proposal for {}", proposal.original_module.name);
+                if proposal.cancelled {
+                    println!("(cancelled: only the modules generated before that are included)");
+                }
                 println!("\nSuggested modules:");
                 
                 for module in &proposal.suggested_modules {
-                    println!("\n  {} (confidence: {:.2})", module.name, module.confidence);
+                    let role = if module.kind == ModuleKind::Role { ", role" } else { "" };
+                    println!("\n  {} (confidence: {:.2}{})", module.name, module.confidence, role);
                     println!("  Responsibility: {}", module.responsibility);
                     println!("  Subroutines: {}", module.subroutines.iter().map(|s| s.name.clone()).collect::<Vec<_>>().join(", "));
+                    if !module.attributes.is_empty() {
+                        println!("  Attributes: {}", module.attributes.iter().map(|a| a.name.clone()).collect::<Vec<_>>().join(", "));
+                    }
                     println!("  Dependencies: {}", module.dependencies.join(", "));
                 }
                 
@@ -143,20 +742,47 @@ impl App {
                 for benefit in &proposal.impact.benefits {
                     println!("    - {}", benefit);
                 }
+
+                if !proposal.deletion_candidates.is_empty() {
+                    println!("\n  Candidates for deletion (not moved): {}", proposal.deletion_candidates.join(", "));
+                }
+
+                let metrics = &proposal.impact.metrics;
+                println!("\n  Metrics:");
+                println!("    Before: {}", metrics_text(&metrics.before));
+                for after in &metrics.after {
+                    println!("    After:  {}", metrics_text(after));
+                }
             }
         }
         
         Ok(())
     }
 
+    fn print_validation(&self, proposal: &RefactoringProposal) -> Result<(), Error> {
+        let validation = DefaultDependencyValidator::new().validate_dependencies(proposal)?;
+        if validation.issues.is_empty() && validation.warnings.is_empty() {
+            return Ok(());
+        }
+
+        println!("\nValidation:");
+        for issue in &validation.issues {
+            println!("  - [Error] {}", issue);
+        }
+        for warning in &validation.warnings {
+            println!("  - [Warning] {}", warning);
+        }
+        Ok(())
+    }
+
     fn save_modules(&self, proposal: &RefactoringProposal, output_dir: Option<&PathBuf>) -> Result<(), Error> {
         let base_dir = match output_dir {
             Some(dir) => dir.clone(),
             None => {
-                // Create a directory based on the original module name
-                let dir = PathBuf::from(format!("refactored_{}", proposal.original_module.name));
+                // Create a directory based on the original package name (Foo::Bar -> refactored_Foo_Bar)
+                let dir = PathBuf::from(format!("refactored_{}", proposal.original_module.namespace().replace("::", "_")));
                 if !dir.exists() {
-                    fs::create_dir_all(&dir).map_err(|e| Error::IOError(e))?;
+                    fs::create_dir_all(&dir).map_err(Error::IOError)?;
                 }
                 dir
             }
@@ -166,26 +792,14 @@ impl App {
         
         // Save each suggested module
         for module in &proposal.suggested_modules {
-            // Convert module name to path (MyModule::Submodule -> MyModule/Submodule.pm)
-            let path_parts: Vec<_> = module.name.split("::").collect();
-            let mut file_path = base_dir.clone();
-            
-            // Create directory structure if needed
-            if path_parts.len() > 1 {
-                for part in &path_parts[0..path_parts.len()-1] {
-                    file_path.push(part);
-                    if !file_path.exists() {
-                        fs::create_dir_all(&file_path).map_err(|e| Error::IOError(e))?;
-                    }
-                }
-            }
-            
-            // Add filename with .pm extension
-            file_path.push(format!("{}.pm", path_parts.last().unwrap_or(&"Unknown")));
-            
-            // Write the module code to file
-            fs::write(&file_path, &module.suggested_code).map_err(|e| Error::IOError(e))?;
-            
+            let file_path = write_module(&base_dir, module, &proposal.original_module.encoding)?;
+            println!("  - Written: {}", file_path.display());
+        }
+
+        // The script itself, next to the modules it now uses
+        if let (Some(script), Some(file_name)) = (&proposal.rewritten_script, proposal.original_module.path.file_name()) {
+            let file_path = base_dir.join(file_name);
+            write_file(&file_path, &encoded(script, &proposal.original_module.encoding))?;
             println!("  - Written: {}", file_path.display());
         }
         
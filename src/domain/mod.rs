@@ -15,6 +15,7 @@ mod tests {
             subroutines: vec![],
             dependencies: vec![],
             responsibility_clusters: vec![],
+            ..Default::default()
         };
 
         assert_eq!(module.name, "Test::Module");
@@ -29,6 +30,7 @@ mod tests {
             line_start: 1,
             line_end: 3,
             dependencies: vec!["Dependency::One".to_string()],
+            ..Default::default()
         };
 
         assert_eq!(sub.name, "test_sub");
@@ -36,6 +38,19 @@ mod tests {
         assert_eq!(sub.dependencies.len(), 1);
     }
 
+    #[test]
+    fn test_dependencies_from_names_and_structures() {
+        let dependencies: Vec<Dependency> = serde_json::from_str(
+            r#"["strict", {"name": "List::Util", "imports": ["sum"], "phase": "runtime"}]"#,
+        ).unwrap();
+
+        assert_eq!(dependencies, vec!["strict", "List::Util"]);
+        assert_eq!(dependencies[0].kind, DependencyKind::Pragma);
+        assert_eq!(dependencies[1].kind, DependencyKind::Module);
+        assert_eq!(dependencies[1].phase, LoadPhase::Runtime);
+        assert_eq!(dependencies[1].declaration(), "require List::Util;");
+    }
+
     #[test]
     fn test_responsibility_cluster_creation() {
         let cluster = ResponsibilityCluster {
@@ -1,26 +1,988 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct PerlModule {
     pub name: String,
     pub path: PathBuf,
     pub content: String,
     pub subroutines: Vec<Subroutine>,
-    pub dependencies: Vec<String>,
+    pub dependencies: Vec<Dependency>,
     pub responsibility_clusters: Vec<ResponsibilityCluster>,
+    #[serde(default)]
+    pub diagnostics: Vec<Diagnostic>,
+    #[serde(default)]
+    pub call_graph: CallGraph,
+    /// File-scoped lexicals, package variables and globals used by the subroutines
+    #[serde(default)]
+    pub variables: Vec<SharedVariable>,
+    /// The module's public interface
+    #[serde(default)]
+    pub exports: Exports,
+    /// Object-oriented structure; `None` unless the package is a class or role
+    #[serde(default)]
+    pub class: Option<ClassInfo>,
+    /// Moose/Moo/Mouse/Mojo::Base `has` declarations
+    #[serde(default)]
+    pub attributes: Vec<Attribute>,
+    /// Module-level POD (NAME, SYNOPSIS, DESCRIPTION, ...); the sections
+    /// documenting single subroutines are kept on those subroutines
+    #[serde(default)]
+    pub documentation: Vec<PodSection>,
+    /// `BEGIN`/`END`/... blocks and other code that runs when the module is loaded
+    #[serde(default)]
+    pub special_blocks: Vec<SpecialBlock>,
+    /// The `__DATA__` or `__END__` payload, if the package has one
+    #[serde(default)]
+    pub data_section: Option<DataSection>,
+    /// Dynamic code anywhere in the package, in subroutines or at top level
+    #[serde(default)]
+    pub hazards: Vec<Hazard>,
+    /// Whether the file is a module, a script or a test file
+    #[serde(default)]
+    pub file_kind: FileKind,
+    /// The Test::More structure of a test file
+    #[serde(default)]
+    pub test_suite: Option<TestSuite>,
+    /// How the file was encoded; `content` is always decoded, with LF line endings
+    #[serde(default)]
+    pub encoding: SourceEncoding,
+    /// Subroutines nothing seems to call, see `analyzer::deletion_candidates`
+    #[serde(default)]
+    pub deletion_candidates: Vec<String>,
+    /// Subroutines of the package that copy each other
+    #[serde(default)]
+    pub clones: Vec<ClonePair>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl PerlModule {
+    /// Whether the module loads `name` with `use` or `require`
+    pub fn depends_on(&self, name: &str) -> bool {
+        self.dependencies.iter().any(|d| d.name == name && !d.unimport)
+    }
+
+    /// Attributes whose builder, trigger and users are all among `names`.
+    /// These can move together with those subroutines.
+    pub fn attributes_owned_by(&self, names: &[&str]) -> Vec<&Attribute> {
+        self.attributes
+            .iter()
+            .filter(|attribute| {
+                let linked = attribute.linked_subroutines();
+                !linked.is_empty() && linked.iter().all(|s| names.contains(s))
+            })
+            .collect()
+    }
+
+    /// Special blocks linked only to subroutines among `names`. These can move
+    /// together with those subroutines.
+    pub fn blocks_owned_by(&self, names: &[&str]) -> Vec<&SpecialBlock> {
+        self.special_blocks
+            .iter()
+            .filter(|block| {
+                let linked = block.linked_subroutines();
+                !linked.is_empty() && linked.iter().all(|s| names.contains(s))
+            })
+            .collect()
+    }
+
+    /// The dependencies the subroutines `names` need in a module of their
+    /// own: every pragma, the modules they reference, and modules imported
+    /// with their default list, which may provide the functions they call.
+    /// Import lists are cut down to the symbols these subroutines mention;
+    /// tags and `-options` are kept.
+    pub fn dependencies_of(&self, names: &[&str]) -> Vec<Dependency> {
+        let subs: Vec<&Subroutine> = self.subroutines.iter().filter(|s| names.contains(&s.name.as_str())).collect();
+        self.dependencies
+            .iter()
+            .filter_map(|dependency| {
+                if dependency.kind == DependencyKind::Pragma || dependency.unimport {
+                    return Some(dependency.clone());
+                }
+                let referenced = subs.iter().any(|s| s.dependencies.contains(&dependency.name));
+                let Some(imports) = &dependency.imports else {
+                    return (referenced || dependency.phase == LoadPhase::CompileTime).then(|| dependency.clone());
+                };
+                let used: Vec<String> = imports
+                    .iter()
+                    .filter(|symbol| symbol.starts_with([':', '-']) || subs.iter().any(|s| mentions(&s.code, symbol)))
+                    .cloned()
+                    .collect();
+                (referenced || !used.is_empty()).then(|| Dependency { imports: Some(used), ..dependency.clone() })
+            })
+            .collect()
+    }
+
+    /// The module-level POD section with the given heading, ignoring case
+    pub fn pod_section(&self, heading: &str) -> Option<&PodSection> {
+        self.documentation.iter().find(|s| s.heading.eq_ignore_ascii_case(heading))
+    }
+
+    /// Whether this is the `main` package of a script or test file, whose
+    /// subroutines are extracted into library modules the file then uses
+    pub fn is_script(&self) -> bool {
+        self.file_kind != FileKind::Module && self.name == "main"
+    }
+
+    /// The package name new modules are nested under: the module's own name,
+    /// or for a script the file name in CamelCase (`load_data.pl` gives
+    /// `LoadData`)
+    pub fn namespace(&self) -> String {
+        if !self.is_script() {
+            return self.name.clone();
+        }
+        let stem = self.path.file_stem().and_then(|s| s.to_str()).unwrap_or("Script");
+        let name: String = stem
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|part| !part.is_empty())
+            .map(|part| {
+                let mut chars = part.chars();
+                chars.next().map(|c| c.to_ascii_uppercase()).into_iter().chain(chars).collect::<String>()
+            })
+            .collect();
+        if name.starts_with(|c: char| c.is_ascii_alphabetic()) { name } else { format!("Script{}", name) }
+    }
+}
+
+/// The encoding and line endings of a source file, so files written for it
+/// can match
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SourceEncoding {
+    pub charset: Charset,
+    /// Starts with a UTF-8 byte order mark
+    pub bom: bool,
+    pub line_ending: LineEnding,
+    /// What the source declares with `use utf8` or `use encoding '...'`
+    pub declared: Option<Charset>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Charset {
+    #[default]
+    Utf8,
+    /// ISO-8859-1
+    Latin1,
+    /// Windows-1252: Latin-1 with printable characters such as `€` and
+    /// curly quotes in 0x80-0x9F
+    Cp1252,
+}
+
+impl Charset {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Utf8 => "UTF-8",
+            Self::Latin1 => "ISO-8859-1",
+            Self::Cp1252 => "Windows-1252",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    CrLf,
+}
+
+/// What kind of Perl file a package was parsed from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileKind {
+    /// A `.pm` library module
+    #[default]
+    Module,
+    /// A `.pl` script, or a file without extension that starts with a perl `#!` line
+    Script,
+    /// A `.t` test file
+    Test,
+}
+
+impl FileKind {
+    pub fn of(path: &Path, content: &str) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("t") => Self::Test,
+            Some("pl") | Some("cgi") => Self::Script,
+            None if content.lines().next().is_some_and(|l| l.starts_with("#!") && l.contains("perl")) => Self::Script,
+            _ => Self::Module,
+        }
+    }
+}
+
+/// How a test file is structured: its plan, subtests and assertions
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TestSuite {
+    /// The `Test::` modules it loads, e.g. `Test::More` or `Test::Exception`
+    pub frameworks: Vec<String>,
+    pub plan: TestPlan,
+    pub subtests: Vec<Subtest>,
+    /// Assertions outside any subtest
+    pub assertions: usize,
+}
+
+/// How a test file declares the number of tests it runs
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TestPlan {
+    #[default]
+    None,
+    /// `use Test::More tests => 3` or `plan tests => 3`
+    Tests(usize),
+    /// `plan skip_all => ...`
+    SkipAll,
+    /// `done_testing` at the end
+    DoneTesting,
+}
+
+/// A `subtest 'name' => sub { ... }` block
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Subtest {
+    pub name: String,
+    pub line_start: usize,
+    pub line_end: usize,
+    /// Assertions inside the subtest, including nested subtests
+    pub assertions: usize,
+    /// Subroutines of the file the subtest calls, i.e. its test helpers
+    pub helpers: Vec<String>,
+}
+
+/// Whether `code` contains `word` as a whole identifier
+fn mentions(code: &str, word: &str) -> bool {
+    let is_identifier = |c: char| c.is_alphanumeric() || c == '_' || c == ':';
+    code.match_indices(word).any(|(start, _)| {
+        let before = code[..start].chars().next_back();
+        let after = code[start + word.len()..].chars().next();
+        !before.is_some_and(is_identifier) && !after.is_some_and(is_identifier)
+    })
+}
+
+/// A module or pragma loaded with `use`, `no` or `require`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(from = "DependencyEntry")]
+pub struct Dependency {
+    pub name: String,
+    pub kind: DependencyKind,
+    /// Minimum version, as in `use DBI 1.6`
+    pub version: Option<String>,
+    /// The import list as written; `None` when the module's default imports
+    /// are used, an empty list for `use POSIX ()`
+    pub imports: Option<Vec<String>>,
+    pub phase: LoadPhase,
+    /// Loaded with `no`, e.g. `no warnings 'redefine'`
+    pub unimport: bool,
+    /// The statement loading the dependency
+    pub span: Option<Span>,
+}
+
+impl Dependency {
+    /// A compile-time `use` of `name` with its default imports
+    pub fn new(name: impl Into<String>) -> Self {
+        let name = name.into();
+        let kind = if name.starts_with(|c: char| c.is_ascii_lowercase()) {
+            DependencyKind::Pragma
+        } else {
+            DependencyKind::Module
+        };
+        Self { name, kind, ..Default::default() }
+    }
+
+    /// The statement loading the dependency, e.g. `use List::Util 1.45 qw(sum max);`
+    pub fn declaration(&self) -> String {
+        let keyword = match (self.unimport, self.phase) {
+            (true, _) => "no",
+            (false, LoadPhase::CompileTime) => "use",
+            (false, _) => "require",
+        };
+        let mut statement = format!("{} {}", keyword, self.name);
+        if keyword == "require" {
+            return format!("{};", statement);
+        }
+        if let Some(version) = &self.version {
+            statement.push_str(&format!(" {}", version));
+        }
+        match &self.imports {
+            Some(imports) if imports.is_empty() => statement.push_str(" ()"),
+            Some(imports) => statement.push_str(&format!(" qw({})", imports.join(" "))),
+            None => {}
+        }
+        format!("{};", statement)
+    }
+}
+
+impl PartialEq<&str> for Dependency {
+    fn eq(&self, name: &&str) -> bool {
+        self.name == *name
+    }
+}
+
+/// Analyses saved before dependencies were structured list plain names
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DependencyEntry {
+    Name(String),
+    Full {
+        name: String,
+        #[serde(default)]
+        kind: Option<DependencyKind>,
+        #[serde(default)]
+        version: Option<String>,
+        #[serde(default)]
+        imports: Option<Vec<String>>,
+        #[serde(default)]
+        phase: LoadPhase,
+        #[serde(default)]
+        unimport: bool,
+        #[serde(default)]
+        span: Option<Span>,
+    },
+}
+
+impl From<DependencyEntry> for Dependency {
+    fn from(entry: DependencyEntry) -> Self {
+        match entry {
+            DependencyEntry::Name(name) => Dependency::new(name),
+            DependencyEntry::Full { name, kind, version, imports, phase, unimport, span } => {
+                let default = Dependency::new(name);
+                Dependency {
+                    kind: kind.unwrap_or(default.kind),
+                    version,
+                    imports,
+                    phase,
+                    unimport,
+                    span,
+                    ..default
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DependencyKind {
+    #[default]
+    Module,
+    /// Lowercase modules such as `strict`, `warnings`, `parent` or `constant`
+    Pragma,
+}
+
+/// When a dependency is loaded
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoadPhase {
+    /// `use` and `no`, resolved while the module is compiled
+    #[default]
+    CompileTime,
+    /// `require` that always runs when reached
+    Runtime,
+    /// `require` under a condition or inside `eval`, `eval "use ..."` and
+    /// `use if ...`; the module may never be loaded
+    Conditional,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Subroutine {
     pub name: String,
     pub code: String,
     pub line_start: usize,
     pub line_end: usize,
     pub dependencies: Vec<String>,
+    /// Shared variables (see `PerlModule::variables`) read by the subroutine
+    #[serde(default)]
+    pub reads: Vec<String>,
+    /// Shared variables (see `PerlModule::variables`) modified by the subroutine
+    #[serde(default)]
+    pub writes: Vec<String>,
+    #[serde(default)]
+    pub kind: SubroutineKind,
+    /// The POD section documenting the subroutine, e.g. its `=head2` or `=item`
+    #[serde(default)]
+    pub pod: Option<String>,
+    #[serde(default)]
+    pub signature: Signature,
+    /// Dynamic code in the subroutine
+    #[serde(default)]
+    pub hazards: Vec<Hazard>,
+    /// From `sub` to the closing brace; `code` is exactly this text
+    #[serde(default)]
+    pub span: Option<Span>,
+    /// Location of `pod` in the file
+    #[serde(default)]
+    pub pod_span: Option<Span>,
+    #[serde(default)]
+    pub metrics: SubroutineMetrics,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Size and complexity of a subroutine
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SubroutineMetrics {
+    /// Lines from `sub` to the closing brace
+    pub physical_loc: usize,
+    /// Statements
+    pub logical_loc: usize,
+    /// 1 plus one for every branch: conditions, loops, `&&`, `||`, `//`,
+    /// `and`, `or` and `?:`
+    pub cyclomatic_complexity: usize,
+    /// Deepest nesting of control blocks; 0 for straight-line code
+    pub max_nesting: usize,
+    pub parameters: usize,
+    /// Explicit `return` statements
+    pub return_points: usize,
+    /// Metrics over their threshold
+    #[serde(default)]
+    pub hotspots: Vec<Hotspot>,
+}
+
+/// A subroutine metric over its threshold, see `metrics::Thresholds`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Hotspot {
+    Long,
+    Complex,
+    DeeplyNested,
+    ManyParameters,
+    ManyReturns,
+}
+
+impl Hotspot {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Long => "long",
+            Self::Complex => "complex",
+            Self::DeeplyNested => "deeply nested",
+            Self::ManyParameters => "many parameters",
+            Self::ManyReturns => "many return points",
+        }
+    }
+}
+
+/// Two subroutines with the same or nearly the same code
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClonePair {
+    pub kind: CloneKind,
+    /// From 0.0 to 1.0; 1.0 for exact and renamed copies
+    pub similarity: f32,
+    pub first: CloneLocation,
+    pub second: CloneLocation,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CloneKind {
+    /// Type 1: the same tokens, apart from whitespace, comments and POD
+    Exact,
+    /// Type 2: the same tokens once names and literals are disregarded
+    Renamed,
+    /// Type 3: statements added, removed or changed as well
+    NearMiss,
+}
+
+impl CloneKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Exact => "exact copy",
+            Self::Renamed => "renamed copy",
+            Self::NearMiss => "near-miss copy",
+        }
+    }
+}
+
+/// One subroutine of a clone pair
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CloneLocation {
+    /// The package defining the subroutine
+    pub module: String,
+    pub path: PathBuf,
+    pub subroutine: String,
+    pub span: Option<Span>,
+}
+
+/// Code whose behaviour depends on names resolved at runtime, which a
+/// refactoring cannot follow
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Hazard {
+    pub kind: HazardKind,
+    pub line: usize,
+    /// The source line, trimmed
+    pub code: String,
+    /// The subroutine containing it; `None` for code outside subroutines
+    pub subroutine: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HazardKind {
+    /// `eval $code` or `eval "..."`
+    StringEval,
+    /// `*{"${pkg}::$name"} = sub { ... }`, `*alias = \&foo`
+    GlobAssignment,
+    /// `$obj->$method`, `->can('name')`, `&{"name"}`
+    DynamicDispatch,
+    /// `sub AUTOLOAD`
+    Autoload,
+    /// `caller`
+    Caller,
+}
+
+impl HazardKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::StringEval => "string eval",
+            Self::GlobAssignment => "glob assignment",
+            Self::DynamicDispatch => "dynamic dispatch",
+            Self::Autoload => "AUTOLOAD",
+            Self::Caller => "caller",
+        }
+    }
+}
+
+/// How a subroutine declares and receives its arguments
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Signature {
+    /// Prototype without its parentheses, e.g. `$$;@`, from `sub f($$;@)` or
+    /// `:prototype($$;@)`
+    pub prototype: Option<String>,
+    /// Subroutine attributes without the colon, e.g. `lvalue` or `method`
+    pub attributes: Vec<String>,
+    pub parameters: Vec<Parameter>,
+    pub style: ParameterStyle,
+}
+
+impl Signature {
+    /// The parameter list as it would appear in a signature, e.g. `($self, %args)`
+    pub fn parameter_list(&self) -> String {
+        let parameters: Vec<String> = self.parameters
+            .iter()
+            .map(|p| match &p.default {
+                Some(default) => format!("{} = {}", p.name, default),
+                None => p.name.clone(),
+            })
+            .collect();
+        format!("({})", parameters.join(", "))
+    }
+
+    /// Parameters, prototype and attributes written as a declaration would
+    /// show them, e.g. `($x, $y) :prototype($$) :lvalue`; empty when the
+    /// subroutine declares none of them
+    pub fn declaration(&self) -> String {
+        let mut parts = Vec::new();
+        if self.style != ParameterStyle::None {
+            parts.push(self.parameter_list());
+        }
+        if let Some(prototype) = &self.prototype {
+            parts.push(format!(":prototype({})", prototype));
+        }
+        parts.extend(self.attributes.iter().map(|a| format!(":{}", a)));
+        parts.join(" ")
+    }
+}
+
+/// A named parameter of a subroutine
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Parameter {
+    /// Variable name with its sigil; `@_`-style names such as `$_[0]` when the
+    /// body reads its arguments directly
+    pub name: String,
+    /// Default value, from a signature (`$x = 1`) or `shift // 1`
+    pub default: Option<String>,
+}
+
+/// Where a subroutine's parameters come from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParameterStyle {
+    /// The subroutine does not look at its arguments
+    #[default]
+    None,
+    /// A signature, `sub f ($x, $y) { ... }`
+    Signature,
+    /// `my ($x, $y) = @_;`
+    List,
+    /// `my $x = shift;`, possibly followed by `my %args = @_;`
+    Shift,
+    /// `$_[0]` or `@_` used in the body
+    Direct,
+}
+
+/// When a special block runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockKind {
+    Begin,
+    Unitcheck,
+    Check,
+    Init,
+    End,
+    /// Statements outside any subroutine, run when the module is loaded
+    TopLevel,
+}
+
+impl BlockKind {
+    /// How the block is written in Perl
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Begin => "BEGIN",
+            Self::Unitcheck => "UNITCHECK",
+            Self::Check => "CHECK",
+            Self::Init => "INIT",
+            Self::End => "END",
+            Self::TopLevel => "top-level code",
+        }
+    }
+}
+
+/// Code of a package that is not part of any subroutine
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpecialBlock {
+    pub kind: BlockKind,
+    pub code: String,
+    pub line_start: usize,
+    pub line_end: usize,
+    /// Subroutines of the module the block calls
+    pub calls: Vec<String>,
+    /// Shared variables the block reads
+    pub reads: Vec<String>,
+    /// Shared variables the block sets up
+    pub writes: Vec<String>,
+    /// Subroutines that use the variables the block writes
+    pub used_by: Vec<String>,
+}
+
+impl SpecialBlock {
+    /// Subroutines the block calls or sets up state for, without duplicates
+    pub fn linked_subroutines(&self) -> Vec<&str> {
+        let mut linked = Vec::new();
+        for sub in self.calls.iter().chain(&self.used_by) {
+            if !linked.contains(&sub.as_str()) {
+                linked.push(sub.as_str());
+            }
+        }
+        linked
+    }
+}
+
+/// Text after `__DATA__` or `__END__`, readable through the `DATA` filehandle
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DataSection {
+    /// `__DATA__` or `__END__`
+    pub marker: String,
+    /// Everything after the marker line
+    pub content: String,
+    /// Line of the marker
+    pub line: usize,
+    /// Subroutines that read from `DATA`
+    pub used_by: Vec<String>,
+}
+
+/// A `=head1` POD section, from its heading up to the next one
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PodSection {
+    pub heading: String,
+    /// The POD text including the heading
+    pub text: String,
+    pub line_start: usize,
+    pub line_end: usize,
+    /// From the heading to the end of the section's last line
+    #[serde(default)]
+    pub span: Option<Span>,
+}
+
+/// Identifies the source file a span points into. Derived from the path, so
+/// the same file gets the same id in every run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FileId(pub u32);
+
+impl FileId {
+    /// FNV-1a hash of the path
+    pub fn for_path(path: &Path) -> Self {
+        let hash = path
+            .to_string_lossy()
+            .bytes()
+            .fold(0x811c_9dc5u32, |hash, byte| (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193));
+        Self(hash)
+    }
+}
+
+/// A range of source text: byte offsets into `PerlModule.content`, and the
+/// 1-based line and column (in characters) where it starts and ends. The
+/// end is exclusive.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Span {
+    pub file: FileId,
+    pub start: usize,
+    pub end: usize,
+    pub line_start: usize,
+    pub column_start: usize,
+    pub line_end: usize,
+    pub column_end: usize,
+}
+
+impl Span {
+    /// The text the span covers, if `content` is the source it was taken from
+    pub fn text<'a>(&self, content: &'a str) -> Option<&'a str> {
+        content.get(self.start..self.end)
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Whether the byte offset `offset` lies inside the span
+    pub fn contains(&self, offset: usize) -> bool {
+        (self.start..self.end).contains(&offset)
+    }
+}
+
+/// How a subroutine expects to be called, judging by its first parameter
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubroutineKind {
+    #[default]
+    Function,
+    /// Takes the object as `$self` (or `$this`)
+    InstanceMethod,
+    /// Takes the class name as `$class` (or `$proto`)
+    ClassMethod,
+}
+
+/// An attribute declared with `has`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Attribute {
+    pub name: String,
+    /// The complete `has` statement
+    pub code: String,
+    pub line_start: usize,
+    pub line_end: usize,
+    /// `ro`, `rw`, `rwp`, `lazy` or `bare`
+    pub is: Option<String>,
+    /// Type constraint as written (`Str`, `ArrayRef[Int]`, `InstanceOf['Foo']`)
+    pub isa: Option<String>,
+    pub required: bool,
+    pub lazy: bool,
+    /// Source text of the `default`
+    pub default: Option<String>,
+    /// Subroutine that builds the value (`builder`, `lazy_build`)
+    pub builder: Option<String>,
+    /// Named subroutine passed as `trigger`
+    pub trigger: Option<String>,
+    /// Methods generated for the attribute: accessors, predicate, clearer and delegations
+    pub methods: Vec<String>,
+    /// Subroutines that call the attribute's methods or access `$self->{name}`
+    pub used_by: Vec<String>,
+}
+
+impl Attribute {
+    /// Builder, trigger and users, without duplicates
+    pub fn linked_subroutines(&self) -> Vec<&str> {
+        let mut linked = Vec::new();
+        let subs = self.builder.iter().chain(&self.trigger).chain(&self.used_by);
+        for sub in subs {
+            if !linked.contains(&sub.as_str()) {
+                linked.push(sub.as_str());
+            }
+        }
+        linked
+    }
+}
+
+/// Object-oriented structure of a package
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ClassInfo {
+    /// `Moose`, `Moo`, `Mouse`, `Mojo::Base`, ...; `None` for plain `bless`ed classes
+    pub object_system: Option<String>,
+    /// Whether the package is a role rather than a class
+    pub is_role: bool,
+    /// From `use parent`, `use base`, `@ISA` or `extends`
+    pub superclasses: Vec<String>,
+    /// From `with`
+    pub roles: Vec<String>,
+    /// The subroutine that `bless`es the object, or `new` when the object system provides it
+    pub constructor: Option<String>,
+}
+
+impl Subroutine {
+    /// Whether the subroutine reads or modifies the shared variable `name`
+    pub fn uses_variable(&self, name: &str) -> bool {
+        self.reads.iter().chain(&self.writes).any(|v| v == name)
+    }
+}
+
+/// A variable that lives outside any single subroutine call
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SharedVariable {
+    /// Name including its sigil, e.g. `$dsn` or `%cache`
+    pub name: String,
+    pub scope: VariableScope,
+    /// Line of the first declaration; `None` for globals such as `%ENV`
+    pub line: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VariableScope {
+    /// File-scoped `my` or `state` variable
+    Lexical,
+    /// `our`, `use vars` or fully qualified package variable
+    Package,
+    /// Interpreter-wide special variable (`%ENV`, `@ARGV`, `$/`, ...)
+    Global,
+}
+
+/// Symbols a module exports to the code that `use`s it
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Exports {
+    /// Exporting module, e.g. `Exporter`, `Exporter::Tiny` or `Sub::Exporter`
+    pub exporter: Option<String>,
+    /// Exported unless the caller asks otherwise (`@EXPORT`, Sub::Exporter's `default` group)
+    pub default: Vec<String>,
+    /// Exported on request (`@EXPORT_OK`, Sub::Exporter's `exports`)
+    pub optional: Vec<String>,
+    /// Named groups (`%EXPORT_TAGS`, Sub::Exporter's other `groups`)
+    pub tags: Vec<ExportTag>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportTag {
+    pub name: String,
+    pub symbols: Vec<String>,
+}
+
+impl Exports {
+    pub fn is_empty(&self) -> bool {
+        self.default.is_empty() && self.optional.is_empty() && self.tags.is_empty()
+    }
+
+    /// Every exportable symbol, without duplicates
+    pub fn symbols(&self) -> Vec<&str> {
+        let mut symbols = Vec::new();
+        let tagged = self.tags.iter().flat_map(|t| &t.symbols);
+        for symbol in self.default.iter().chain(&self.optional).chain(tagged) {
+            if !symbols.contains(&symbol.as_str()) {
+                symbols.push(symbol.as_str());
+            }
+        }
+        symbols
+    }
+
+    pub fn is_exported(&self, name: &str) -> bool {
+        self.symbols().contains(&name)
+    }
+
+    /// The part of the interface made up of the subroutines in `names`.
+    /// Tags that end up empty are dropped; exported variables are not kept.
+    pub fn restricted_to(&self, names: &[&str]) -> Exports {
+        let keep = |symbols: &[String]| -> Vec<String> {
+            symbols.iter().filter(|s| names.contains(&s.as_str())).cloned().collect()
+        };
+        Exports {
+            exporter: self.exporter.clone(),
+            default: keep(&self.default),
+            optional: keep(&self.optional),
+            tags: self.tags
+                .iter()
+                .map(|tag| ExportTag { name: tag.name.clone(), symbols: keep(&tag.symbols) })
+                .filter(|tag| !tag.symbols.is_empty())
+                .collect(),
+        }
+    }
+}
+
+/// Calls between the subroutines of a single module
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CallGraph {
+    pub edges: Vec<CallEdge>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CallEdge {
+    pub caller: String,
+    pub callee: String,
+    pub kind: CallKind,
+    /// Line of the first such call in the caller
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CallKind {
+    /// `foo(...)`, `&foo(...)` or `Package::foo(...)`
+    Function,
+    /// `$self->foo`, `$class->foo`, `__PACKAGE__->foo`
+    Method,
+    /// `\&foo`
+    CodeReference,
+}
+
+impl CallGraph {
+    /// Subroutines called by `name`, in source order and without duplicates
+    pub fn callees(&self, name: &str) -> Vec<&str> {
+        let mut callees = Vec::new();
+        for edge in self.edges.iter().filter(|e| e.caller == name) {
+            if !callees.contains(&edge.callee.as_str()) {
+                callees.push(edge.callee.as_str());
+            }
+        }
+        callees
+    }
+
+    /// Subroutines that call `name`, in source order and without duplicates
+    pub fn callers(&self, name: &str) -> Vec<&str> {
+        let mut callers = Vec::new();
+        for edge in self.edges.iter().filter(|e| e.callee == name) {
+            if !callers.contains(&edge.caller.as_str()) {
+                callers.push(edge.caller.as_str());
+            }
+        }
+        callers
+    }
+}
+
+/// A note about how a parsed module differs from what was reported or expected
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub kind: DiagnosticKind,
+    pub message: String,
+    pub subroutine: Option<String>,
+    /// The source the diagnostic is about, if it points at a specific place
+    #[serde(default)]
+    pub span: Option<Span>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiagnosticKind {
+    /// The reported line range did not match the source
+    LineRangeCorrected,
+    /// The reported code was replaced with the exact source text
+    CodeReplaced,
+    /// A reported subroutine does not exist in the source
+    SubroutineRemoved,
+    /// A subroutine in the source was missing from the report
+    SubroutineAdded,
+    /// A package in the source was missing from the report
+    PackageAdded,
+    /// Part of a file that was analyzed in chunks could not be analyzed
+    ChunkFailed,
+    /// Clusters found in separate chunks of a file could not be merged
+    ClustersNotMerged,
+    /// The file is not in the encoding it declares
+    EncodingMismatch,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ResponsibilityCluster {
     pub name: String,
     pub description: String,
@@ -34,24 +996,85 @@ pub struct RefactoringProposal {
     pub original_module: PerlModule,
     pub suggested_modules: Vec<NewModuleProposal>,
     pub impact: RefactoringImpact,
+    /// For a script or test file: the file rewritten to `use` the new modules
+    #[serde(default)]
+    pub rewritten_script: Option<String>,
+    /// Generation was cancelled; only the modules finished before that are included
+    #[serde(default)]
+    pub cancelled: bool,
+    /// Subroutines nothing seems to call; they are not moved into any module
+    #[serde(default)]
+    pub deletion_candidates: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct NewModuleProposal {
     pub name: String,
+    #[serde(default)]
+    pub kind: ModuleKind,
     pub responsibility: String,
     pub subroutines: Vec<Subroutine>,
+    /// Attributes that move with the subroutines
+    #[serde(default)]
+    pub attributes: Vec<Attribute>,
     pub dependencies: Vec<String>,
     pub suggested_code: String,
     pub confidence: f32,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModuleKind {
+    /// A standalone module
+    #[default]
+    Module,
+    /// A role consumed by the original class, used for methods that need `$self`
+    Role,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct RefactoringImpact {
     pub complexity: u32,
     pub effort: String,
     pub risks: Vec<String>,
     pub benefits: Vec<String>,
+    /// Cohesion and coupling before and after the split
+    #[serde(default)]
+    pub metrics: ProposalMetrics,
+}
+
+/// Cohesion and coupling of the original module and of the modules it is
+/// split into
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProposalMetrics {
+    pub before: ModuleMetrics,
+    /// One per suggested module, then the subroutines that stay in the
+    /// original module, if any
+    pub after: Vec<ModuleMetrics>,
+}
+
+/// Cohesion and coupling of one module. Coupling counts the other modules
+/// of the same split and the external modules the subroutines use.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModuleMetrics {
+    pub name: String,
+    pub subroutines: usize,
+    /// LCOM4: groups of subroutines not linked by calls, shared variables or
+    /// attributes. 1 is cohesive; more means the module could be split.
+    pub lcom4: usize,
+    /// Calls between subroutines of the module
+    pub internal_calls: usize,
+    /// Calls to subroutines that end up in another module of the split
+    pub external_calls: usize,
+    /// Internal calls out of all calls; 1.0 without any calls
+    pub internal_call_ratio: f32,
+    /// Fan-in (Ca): modules of the split calling this one
+    pub afferent_coupling: usize,
+    /// Fan-out (Ce): modules of the split this one calls, plus the external
+    /// modules it uses
+    pub efferent_coupling: usize,
+    /// Ce / (Ca + Ce), from 0.0 (stable) to 1.0 (only depends on others)
+    pub instability: f32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -5,13 +5,33 @@ use crate::error::Error;
 
 #[async_trait]
 pub trait ModuleParser: Send + Sync {
+    /// Parse every package declared in the Perl file at the given path
+    /// 
+    /// Each package becomes its own `PerlModule` with its own subroutines,
+    /// dependencies and responsibility clusters. A file without a `package`
+    /// statement yields a single module.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns `Error::ParseError` if the file cannot be parsed
+    /// Returns `Error::IOError` if the file cannot be read
+    async fn parse_packages(&self, path: impl AsRef<Path> + Send) -> Result<Vec<PerlModule>, Error>;
+
     /// Parse a Perl module from the given path
     /// 
+    /// Returns the first package declared in the file.
+    /// 
     /// # Errors
     /// 
     /// Returns `Error::ParseError` if the module cannot be parsed
     /// Returns `Error::IOError` if the file cannot be read
-    async fn parse_module(&self, path: impl AsRef<Path> + Send) -> Result<PerlModule, Error>;
+    async fn parse_module(&self, path: impl AsRef<Path> + Send) -> Result<PerlModule, Error> {
+        self.parse_packages(path)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::ParseError("No package found".to_string()))
+    }
 }
 
 #[async_trait]
@@ -22,9 +42,11 @@ pub trait ResponsibilityAnalyzer: Send + Sync {
     /// 
     /// Returns `Error::AnalysisError` if the analysis fails
     /// Returns `Error::AIError` if the AI service fails
+    /// Returns `Error::Cancelled` if the analysis was cancelled
     async fn analyze_module(&self, module: &PerlModule) -> Result<Vec<ResponsibilityCluster>, Error>;
     
-    /// Cancel any ongoing analysis
+    /// Cancel any ongoing analysis. Analyses started afterwards are
+    /// cancelled as well.
     async fn cancel(&self);
 }
 
@@ -32,9 +54,13 @@ pub trait ResponsibilityAnalyzer: Send + Sync {
 pub trait RefactoringProposer: Send + Sync {
     /// Generate a refactoring proposal based on identified responsibilities
     /// 
+    /// If generation is cancelled after some modules were generated, the
+    /// proposal holds those modules and has `cancelled` set.
+    /// 
     /// # Errors
     /// 
     /// Returns `Error::ValidationError` if the proposal cannot be generated
+    /// Returns `Error::Cancelled` if it was cancelled before any module was generated
     async fn generate_proposal(
         &self,
         module: &PerlModule,
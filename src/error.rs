@@ -18,6 +18,10 @@ pub enum Error {
     #[error("AI service error: {0}")]
     AIError(String),
     
+    /// Text could not be written in the encoding of the original file
+    #[error("Encoding error: {0}")]
+    EncodingError(String),
+
     /// IO error occurred
     #[error("I/O error: {0}")]
     IOError(#[from] std::io::Error),
@@ -34,6 +38,10 @@ pub enum Error {
     #[error("No AI provider available")]
     NoAIProvider,
 
+    /// The operation was cancelled before it finished
+    #[error("Cancelled")]
+    Cancelled,
+
     /// Missing environment variable
     #[error("Missing environment variable: {0}")]
     MissingEnvVar(String),
@@ -1,12 +1,15 @@
+pub mod cancel;
 pub mod config;
 pub mod core;
 pub mod domain;
 pub mod error;
+pub mod metrics;
 pub mod parser;
 pub mod analyzer;
 pub mod proposer;
 pub mod validator;
 
+pub use cancel::Cancellation;
 pub use config::Config;
-pub use core::App;
+pub use core::{AnalysisSettings, AnalyzerMode, App, ParserMode};
 pub use error::Error; 
\ No newline at end of file
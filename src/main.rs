@@ -1,7 +1,7 @@
 use std::path::PathBuf;
 use clap::{Parser, Subcommand};
 use dotenv::dotenv;
-use secret_agent::{App, Config, Error};
+use secret_agent::{analyzer::DEFAULT_MIN_SIMILARITY, parser::DEFAULT_TOKEN_BUDGET, AnalysisSettings, AnalyzerMode, App, Cancellation, Config, Error, ParserMode};
 
 #[derive(Parser, Debug)]
 #[command(
@@ -13,13 +13,33 @@ use secret_agent::{App, Config, Error};
 struct Args {
     #[command(subcommand)]
     command: Commands,
+
+    /// Parser to use (ai, static or hybrid)
+    #[arg(long, global = true, default_value = "ai")]
+    parser: String,
+
+    /// Analyzer identifying responsibility clusters (ai, or graph to cluster offline)
+    #[arg(long, global = true, default_value = "ai")]
+    analyzer: String,
+
+    /// Estimated tokens of module content per AI request; larger files are analyzed in chunks
+    #[arg(long, global = true, default_value_t = DEFAULT_TOKEN_BUDGET)]
+    token_budget: usize,
+
+    /// Directory whose Perl files are searched for callers before subroutines are reported as dead code
+    #[arg(long, global = true)]
+    source_tree: Option<PathBuf>,
+
+    /// Model of the AI provider to use instead of the default
+    #[arg(long, global = true)]
+    model: Option<String>,
 }
 
 #[derive(Subcommand, Debug)]
 enum Commands {
     /// Parse and analyze a Perl module
     Parse {
-        /// Path to the Perl module to analyze
+        /// Path to the Perl module, script (.pl) or test file (.t) to analyze
         #[arg(short = 'p', long)]
         file: PathBuf,
 
@@ -32,9 +52,32 @@ enum Commands {
         save: Option<PathBuf>,
     },
     
+    /// Identify responsibility clusters again in a saved analysis, without re-parsing
+    Analyze {
+        /// Path to a saved analysis file
+        #[arg(short = 'a', long)]
+        analysis: PathBuf,
+
+        /// Output format (text or json)
+        #[arg(short = 'o', long, default_value = "text")]
+        format: String,
+
+        /// Save the new analysis to file
+        #[arg(short = 's', long)]
+        save: Option<PathBuf>,
+
+        /// Send the full code of every subroutine instead of summaries
+        #[arg(long)]
+        full_code: bool,
+
+        /// Drop clusters with a lower confidence (0.0 to 1.0)
+        #[arg(long, default_value_t = 0.0)]
+        min_confidence: f32,
+    },
+
     /// Generate refactoring proposals for a Perl module
     Propose {
-        /// Path to the Perl module to refactor
+        /// Path to the Perl module, script (.pl) or test file (.t) to refactor
         #[arg(short = 'p', long)]
         file: Option<PathBuf>,
 
@@ -49,6 +92,29 @@ enum Commands {
         /// Output format (text or json)
         #[arg(short = 'o', long, default_value = "text")]
         format: String,
+
+        /// Add a module holding one copy of each group of cloned subroutines
+        #[arg(long)]
+        extract_clones: bool,
+    },
+
+    /// Find cloned subroutines in a Perl file or across a directory
+    Clones {
+        /// Path to a Perl file or a directory of them
+        #[arg(short = 'p', long)]
+        path: PathBuf,
+
+        /// Lowest similarity reported (0.0 to 1.0)
+        #[arg(long, default_value_t = DEFAULT_MIN_SIMILARITY)]
+        min_similarity: f32,
+
+        /// Output directory for a helper module holding one copy of each clone
+        #[arg(short = 'd', long)]
+        output_dir: Option<PathBuf>,
+
+        /// Output format (text or json)
+        #[arg(short = 'o', long, default_value = "text")]
+        format: String,
     }
 }
 
@@ -58,14 +124,58 @@ async fn main() -> Result<(), Error> {
     dotenv().ok();
 
     let args = Args::parse();
-    let app = App::new(Config::from_env());
+    let parser_mode: ParserMode = args.parser.parse()?;
+    let analyzer_mode: AnalyzerMode = args.analyzer.parse()?;
+    let config = match &args.model {
+        Some(model) => Config::from_env().with_model(model),
+        None => Config::from_env(),
+    };
+    let cancellation = Cancellation::new();
+    let mut app = App::new(config)
+        .with_parser_mode(parser_mode)
+        .with_analyzer_mode(analyzer_mode)
+        .with_token_budget(args.token_budget)
+        .with_cancellation(cancellation.clone());
+    if let Some(source_tree) = &args.source_tree {
+        app = app.with_source_tree(source_tree.clone());
+    }
+    if let Commands::Propose { extract_clones: true, .. } = args.command {
+        app = app.with_clone_extraction(true);
+    }
+
+    // The first Ctrl-C stops the AI requests and keeps finished work; a second one exits at once
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            eprintln!("\nCancelling... press Ctrl-C again to exit immediately");
+            cancellation.cancel();
+        }
+        if tokio::signal::ctrl_c().await.is_ok() {
+            std::process::exit(130);
+        }
+    });
+
+    match run(&app, &args).await {
+        Err(Error::Cancelled) => {
+            eprintln!("Cancelled");
+            std::process::exit(130);
+        }
+        result => result,
+    }
+}
 
+async fn run(app: &App, args: &Args) -> Result<(), Error> {
     match &args.command {
         Commands::Parse { file, format, save } => {
             app.parse_module(file, format, save.as_ref()).await?;
         },
-        Commands::Propose { file, analysis, output_dir, format } => {
-            let module = match (file, analysis) {
+        Commands::Analyze { analysis, format, save, full_code, min_confidence } => {
+            eprintln!("Loading analysis from: {}", analysis.display());
+            let modules = app.load_analysis_from_file(analysis)?;
+            let settings = AnalysisSettings { full_code: *full_code, min_confidence: *min_confidence };
+            app.analyze_responsibilities(modules, settings, format, save.as_ref()).await?;
+        },
+        Commands::Propose { file, analysis, output_dir, format, .. } => {
+            let modules = match (file, analysis) {
                 (Some(file_path), None) => {
                     println!("Analyzing module: {}", file_path.display());
                     app.parse_module(file_path, format, None).await?
@@ -86,8 +196,12 @@ async fn main() -> Result<(), Error> {
                 }
             };
 
-            println!("Analysis complete. Found {} responsibility clusters.", module.responsibility_clusters.len());
-            app.propose_refactoring(&module, format, output_dir.as_ref()).await?;
+            let clusters: usize = modules.iter().map(|m| m.responsibility_clusters.len()).sum();
+            println!("Analysis complete. Found {} responsibility clusters in {} package(s).", clusters, modules.len());
+            app.propose_refactoring(&modules, format, output_dir.as_ref()).await?;
+        }
+        Commands::Clones { path, min_similarity, output_dir, format } => {
+            app.detect_clones(path, *min_similarity, format, output_dir.as_ref()).await?;
         }
     }
 
@@ -0,0 +1,188 @@
+use crate::domain::models::{CallGraph, ModuleMetrics, NewModuleProposal, PerlModule, ProposalMetrics};
+use crate::parser::build_call_graph;
+
+mod subroutine;
+
+pub use subroutine::{subroutine_metrics, Thresholds};
+
+/// Cohesion and coupling of `module` as it is
+pub fn module_metrics(module: &PerlModule) -> ModuleMetrics {
+    let names: Vec<&str> = module.subroutines.iter().map(|s| s.name.as_str()).collect();
+    let parts = [Part { name: module.name.clone(), subroutines: names }];
+    parts[0].metrics(module, &call_graph(module), &parts)
+}
+
+/// Cohesion and coupling of `module` before and after it is split into
+/// `suggested_modules`. Subroutines that no module takes stay in the
+/// original module, which then comes last in `after`.
+pub fn proposal_metrics(module: &PerlModule, suggested_modules: &[NewModuleProposal]) -> ProposalMetrics {
+    let mut parts: Vec<Part> = suggested_modules
+        .iter()
+        .map(|m| Part { name: m.name.clone(), subroutines: m.subroutines.iter().map(|s| s.name.as_str()).collect() })
+        .collect();
+    let remaining: Vec<&str> = module.subroutines
+        .iter()
+        .map(|s| s.name.as_str())
+        .filter(|name| !parts.iter().any(|p| p.subroutines.contains(name)))
+        .collect();
+    if !remaining.is_empty() {
+        parts.push(Part { name: module.name.clone(), subroutines: remaining });
+    }
+
+    let call_graph = call_graph(module);
+    ProposalMetrics {
+        before: module_metrics(module),
+        after: parts.iter().map(|part| part.metrics(module, &call_graph, &parts)).collect(),
+    }
+}
+
+fn call_graph(module: &PerlModule) -> CallGraph {
+    if module.call_graph.edges.is_empty() {
+        build_call_graph(module)
+    } else {
+        module.call_graph.clone()
+    }
+}
+
+/// One module of a split, by the names of its subroutines
+struct Part<'a> {
+    name: String,
+    subroutines: Vec<&'a str>,
+}
+
+impl Part<'_> {
+    fn contains(&self, name: &str) -> bool {
+        self.subroutines.contains(&name)
+    }
+
+    fn metrics(&self, module: &PerlModule, call_graph: &CallGraph, parts: &[Part]) -> ModuleMetrics {
+        let (mut internal_calls, mut external_calls) = (0, 0);
+        let (mut callers, mut callees): (Vec<&str>, Vec<&str>) = (Vec::new(), Vec::new());
+        for edge in &call_graph.edges {
+            let (from_here, to_here) = (self.contains(&edge.caller), self.contains(&edge.callee));
+            if from_here && to_here {
+                internal_calls += 1;
+            } else if from_here {
+                external_calls += 1;
+                for part in parts.iter().filter(|p| p.contains(&edge.callee)) {
+                    if !callees.contains(&part.name.as_str()) {
+                        callees.push(&part.name);
+                    }
+                }
+            } else if to_here {
+                for part in parts.iter().filter(|p| p.contains(&edge.caller)) {
+                    if !callers.contains(&part.name.as_str()) {
+                        callers.push(&part.name);
+                    }
+                }
+            }
+        }
+
+        let mut dependencies: Vec<&str> = module.subroutines
+            .iter()
+            .filter(|s| self.contains(&s.name))
+            .flat_map(|s| s.dependencies.iter().map(|d| d.as_str()))
+            .collect();
+        dependencies.sort();
+        dependencies.dedup();
+
+        let afferent_coupling = callers.len();
+        let efferent_coupling = callees.len() + dependencies.len();
+        let calls = internal_calls + external_calls;
+        let coupling = afferent_coupling + efferent_coupling;
+        ModuleMetrics {
+            name: self.name.clone(),
+            subroutines: self.subroutines.len(),
+            lcom4: self.lcom4(module, call_graph),
+            internal_calls,
+            external_calls,
+            internal_call_ratio: if calls == 0 { 1.0 } else { internal_calls as f32 / calls as f32 },
+            afferent_coupling,
+            efferent_coupling,
+            instability: if coupling == 0 { 0.0 } else { efferent_coupling as f32 / coupling as f32 },
+        }
+    }
+
+    /// Connected components of the subroutines, linked when one calls the
+    /// other or both use the same shared variable or attribute
+    fn lcom4(&self, module: &PerlModule, call_graph: &CallGraph) -> usize {
+        let uses = |name: &str| -> Vec<&str> {
+            let mut state: Vec<&str> = Vec::new();
+            if let Some(sub) = module.subroutines.iter().find(|s| s.name == name) {
+                state.extend(sub.reads.iter().chain(&sub.writes).map(|v| v.as_str()));
+            }
+            state.extend(module.attributes.iter().filter(|a| a.linked_subroutines().contains(&name)).map(|a| a.name.as_str()));
+            state
+        };
+        let state: Vec<Vec<&str>> = self.subroutines.iter().map(|name| uses(name)).collect();
+        let linked = |i: usize, j: usize| {
+            let (a, b) = (self.subroutines[i], self.subroutines[j]);
+            call_graph.edges.iter().any(|e| (e.caller == a && e.callee == b) || (e.caller == b && e.callee == a))
+                || state[i].iter().any(|v| state[j].contains(v))
+        };
+
+        let mut component: Vec<usize> = (0..self.subroutines.len()).collect();
+        for i in 0..component.len() {
+            for j in i + 1..component.len() {
+                if component[i] != component[j] && linked(i, j) {
+                    let (from, to) = (component[j], component[i]);
+                    for c in component.iter_mut().filter(|c| **c == from) {
+                        *c = to;
+                    }
+                }
+            }
+        }
+        component.sort();
+        component.dedup();
+        component.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use crate::parser::StaticModuleParser;
+    use super::*;
+
+    #[test]
+    fn test_metrics_before_and_after_split() {
+        let content = r#"package Shop;
+use List::Util qw(sum);
+use JSON::PP;
+
+my %cart;
+
+sub add_item { my ($name, $qty) = @_; $cart{$name} += $qty; return total() }
+sub total { return sum(values %cart) }
+sub to_json { return JSON::PP->new->encode({ total => total() }) }
+sub version { return '1.0' }
+
+1;
+"#;
+        let module = StaticModuleParser::new().parse_source(Path::new("Shop.pm"), content).remove(0);
+        let before = module_metrics(&module);
+        assert_eq!((before.subroutines, before.lcom4, before.internal_calls, before.external_calls), (4, 2, 2, 0));
+        assert_eq!(before.internal_call_ratio, 1.0);
+        assert_eq!((before.afferent_coupling, before.efferent_coupling), (0, 2));
+        assert_eq!(before.instability, 1.0);
+
+        let moved = |name: &str, subs: &[&str]| NewModuleProposal {
+            name: name.to_string(),
+            subroutines: module.subroutines.iter().filter(|s| subs.contains(&s.name.as_str())).cloned().collect(),
+            ..Default::default()
+        };
+        let suggested = vec![moved("Shop::Cart", &["add_item", "total"]), moved("Shop::Export", &["to_json"])];
+        let metrics = proposal_metrics(&module, &suggested);
+        assert_eq!(metrics.before, before);
+
+        let names: Vec<&str> = metrics.after.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, vec!["Shop::Cart", "Shop::Export", "Shop"]);
+        let cart = &metrics.after[0];
+        assert_eq!((cart.lcom4, cart.internal_calls, cart.afferent_coupling, cart.efferent_coupling), (1, 1, 1, 1));
+        assert_eq!(cart.instability, 0.5);
+        let export = &metrics.after[1];
+        assert_eq!((export.external_calls, export.internal_call_ratio), (1, 0.0));
+        assert_eq!((export.afferent_coupling, export.efferent_coupling), (0, 2));
+        assert_eq!(metrics.after[2].subroutines, 1);
+    }
+}
@@ -0,0 +1,181 @@
+use crate::domain::models::{Hotspot, Signature, SubroutineMetrics};
+use crate::parser::lexer::{self, Token, TokenKind};
+
+/// Keywords that start a compound statement with a block of its own
+const CONTROL_KEYWORDS: &[&str] = &["if", "elsif", "else", "unless", "while", "until", "for", "foreach"];
+/// Keywords and operators that add a path through the code
+const BRANCH_WORDS: &[&str] = &["if", "elsif", "unless", "while", "until", "for", "foreach", "and", "or", "when"];
+const BRANCH_OPERATORS: &[&str] = &["&&", "||", "//", "?", "&&=", "||=", "//="];
+
+/// Limits above which a subroutine metric is flagged as a hotspot
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Thresholds {
+    pub physical_loc: usize,
+    pub cyclomatic_complexity: usize,
+    pub max_nesting: usize,
+    pub parameters: usize,
+    pub return_points: usize,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self { physical_loc: 60, cyclomatic_complexity: 10, max_nesting: 4, parameters: 5, return_points: 5 }
+    }
+}
+
+impl Thresholds {
+    /// The metrics of `metrics` over these limits
+    pub fn hotspots(&self, metrics: &SubroutineMetrics) -> Vec<Hotspot> {
+        let checks = [
+            (metrics.physical_loc > self.physical_loc, Hotspot::Long),
+            (metrics.cyclomatic_complexity > self.cyclomatic_complexity, Hotspot::Complex),
+            (metrics.max_nesting > self.max_nesting, Hotspot::DeeplyNested),
+            (metrics.parameters > self.parameters, Hotspot::ManyParameters),
+            (metrics.return_points > self.return_points, Hotspot::ManyReturns),
+        ];
+        checks.into_iter().filter(|(over, _)| *over).map(|(_, hotspot)| hotspot).collect()
+    }
+}
+
+/// Size and complexity of the subroutine `code`, from `sub` to its closing
+/// brace, with hotspots flagged at the default thresholds
+pub fn subroutine_metrics(code: &str, signature: &Signature) -> SubroutineMetrics {
+    let tokens = lexer::significant_tokens(code);
+    // The body, between the first brace outside a signature and the last one
+    let mut paren_depth = 0usize;
+    let open = tokens.iter().position(|t| {
+        match t.kind {
+            TokenKind::OpenParen => paren_depth += 1,
+            TokenKind::CloseParen => paren_depth = paren_depth.saturating_sub(1),
+            _ => {}
+        }
+        t.kind == TokenKind::OpenBrace && paren_depth == 0
+    });
+    let body = match open {
+        Some(open) if tokens.last().is_some_and(|t| t.kind == TokenKind::CloseBrace) && open < tokens.len() - 1 => {
+            &tokens[open + 1..tokens.len() - 1]
+        }
+        _ => &[][..],
+    };
+
+    let mut metrics = SubroutineMetrics {
+        physical_loc: code.lines().count(),
+        cyclomatic_complexity: 1,
+        parameters: signature.parameters.len(),
+        ..Default::default()
+    };
+    let mut paren_depth = 0usize;
+    // Whether each open brace belongs to a control block
+    let mut blocks: Vec<bool> = Vec::new();
+    let mut pending_block: Option<usize> = None;
+    for (i, token) in body.iter().enumerate() {
+        let prev = i.checked_sub(1).map(|p| &body[p]);
+        let statement_start = prev.is_none_or(|t| {
+            t.is_op(";") || matches!(t.kind, TokenKind::OpenBrace | TokenKind::CloseBrace)
+        });
+        match token.kind {
+            TokenKind::OpenParen => paren_depth += 1,
+            TokenKind::CloseParen => paren_depth = paren_depth.saturating_sub(1),
+            TokenKind::OpenBrace => {
+                let control = pending_block == Some(paren_depth);
+                if control {
+                    pending_block = None;
+                }
+                blocks.push(control);
+                metrics.max_nesting = metrics.max_nesting.max(blocks.iter().filter(|b| **b).count());
+            }
+            TokenKind::CloseBrace => {
+                let control = blocks.pop() == Some(true);
+                if control && prev.is_some_and(ends_statement) {
+                    metrics.logical_loc += 1;
+                }
+            }
+            TokenKind::Operator if token.text == ";" && paren_depth == 0 => metrics.logical_loc += 1,
+            TokenKind::Operator if BRANCH_OPERATORS.contains(&token.text) => metrics.cyclomatic_complexity += 1,
+            TokenKind::Word if is_keyword(body, i) => {
+                if BRANCH_WORDS.contains(&token.text) {
+                    metrics.cyclomatic_complexity += 1;
+                }
+                if token.text == "return" {
+                    metrics.return_points += 1;
+                }
+                let compound = CONTROL_KEYWORDS.contains(&token.text)
+                    && (statement_start || token.text == "elsif" || token.text == "else");
+                if compound {
+                    pending_block = Some(paren_depth);
+                    if token.text != "elsif" && token.text != "else" {
+                        metrics.logical_loc += 1;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    // The last statement of the body needs no semicolon
+    if body.last().is_some_and(ends_statement) {
+        metrics.logical_loc += 1;
+    }
+
+    metrics.hotspots = Thresholds::default().hotspots(&metrics);
+    metrics
+}
+
+/// A token that can end a statement missing its semicolon
+fn ends_statement(token: &Token) -> bool {
+    !token.is_op(";") && !matches!(token.kind, TokenKind::OpenBrace | TokenKind::CloseBrace)
+}
+
+/// A word used as a keyword, not as a method name or hash key
+fn is_keyword(tokens: &[Token], i: usize) -> bool {
+    let prev = i.checked_sub(1).map(|p| &tokens[p]);
+    let next = tokens.get(i + 1);
+    let after_arrow = prev.is_some_and(|t| t.is_op("->"));
+    let is_key = next.is_some_and(|t| t.is_op("=>"))
+        || (prev.is_some_and(|t| t.kind == TokenKind::OpenBrace) && next.is_some_and(|t| t.kind == TokenKind::CloseBrace));
+    !after_arrow && !is_key
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use crate::parser::StaticModuleParser;
+    use super::*;
+
+    #[test]
+    fn test_subroutine_metrics() {
+        let content = r#"package Catalog;
+sub classify {
+    my ($self, $items, %opts) = @_;
+    return [] unless @$items;
+    my @result;
+    foreach my $item (@$items) {
+        if ($item->{price} > 100 && !$opts{all}) {
+            push @result, 'expensive';
+        } elsif ($item->{price} > 10) {
+            push @result, $item->{sale} ? 'sale' : 'normal';
+        } else {
+            next
+        }
+    }
+    return \@result || undef;
+}
+1;
+"#;
+        let module = StaticModuleParser::new().parse_source(Path::new("Catalog.pm"), content).remove(0);
+        let metrics = module.subroutines[0].metrics.clone();
+        assert_eq!(metrics.physical_loc, 15);
+        assert_eq!(metrics.logical_loc, 9);
+        // unless, foreach, if, &&, elsif, ?:, ||
+        assert_eq!(metrics.cyclomatic_complexity, 8);
+        assert_eq!(metrics.max_nesting, 2);
+        assert_eq!(metrics.parameters, 3);
+        assert_eq!(metrics.return_points, 2);
+        assert!(metrics.hotspots.is_empty());
+
+        let strict = Thresholds { cyclomatic_complexity: 5, parameters: 2, ..Default::default() };
+        assert_eq!(strict.hotspots(&metrics), vec![Hotspot::Complex, Hotspot::ManyParameters]);
+
+        let flat = subroutine_metrics("sub version { '1.0' }", &Signature::default());
+        assert_eq!((flat.logical_loc, flat.cyclomatic_complexity, flat.max_nesting, flat.return_points), (1, 1, 0, 0));
+    }
+}
@@ -0,0 +1,284 @@
+use crate::domain::models::{Attribute, Subroutine};
+use super::call_graph::SELF_INVOCANTS;
+use super::lexer::{self, Token, TokenKind};
+use super::syntax::{items, key_name, pairs, statement, strings};
+
+/// Options whose value names a generated method
+const METHOD_OPTIONS: &[&str] = &["reader", "writer", "accessor", "predicate", "clearer"];
+
+/// Attributes declared with `has` in the package whose tokens satisfy
+/// `in_package`, linked to the subroutines that build, trigger or use them.
+///
+/// Both `has name => (is => 'ro', ...)` and the unparenthesized Moo style are
+/// understood, as are `has [qw(a b)] => ...`, `has '+name' => ...` and
+/// Mojo::Base's `has name => sub { ... }`. Only call this for packages that
+/// use an object system providing `has`.
+pub(crate) fn extract_attributes(
+    content: &str,
+    tokens: &[Token],
+    in_package: impl Fn(usize) -> bool,
+    subroutines: &[Subroutine],
+) -> Vec<Attribute> {
+    let mut attributes = Vec::new();
+
+    for (i, token) in tokens.iter().enumerate() {
+        if !in_package(i) || !token.is_word("has") {
+            continue;
+        }
+        let prev = i.checked_sub(1).map(|p| &tokens[p]);
+        if !prev.is_none_or(|t| t.is_op(";") || t.kind == TokenKind::CloseBrace || t.kind == TokenKind::OpenBrace) {
+            continue;
+        }
+
+        let declaration = statement(tokens, i + 1);
+        let Some(names_item) = items(declaration).first().copied() else {
+            continue;
+        };
+        let options = match &declaration[names_item.len()..] {
+            [separator, rest @ ..] if separator.is_op("=>") || separator.is_op(",") => rest,
+            rest => rest,
+        };
+
+        let last = declaration.last().unwrap_or(token);
+        let end = tokens.get(i + 1 + declaration.len()).filter(|t| t.is_op(";")).unwrap_or(last);
+        let code = &content[token.start..end.end];
+        let line_end = end.line + end.text.matches('\n').count();
+
+        for name in attribute_names(names_item) {
+            let mut attribute = Attribute {
+                name: name.trim_start_matches('+').to_string(),
+                code: code.to_string(),
+                line_start: token.line,
+                line_end,
+                ..Default::default()
+            };
+            apply_options(&mut attribute, content, options);
+            attribute.used_by = subroutines
+                .iter()
+                .filter(|sub| uses_attribute(sub, &attribute))
+                .map(|sub| sub.name.clone())
+                .collect();
+            attributes.push(attribute);
+        }
+    }
+
+    attributes
+}
+
+/// `name`, `'name'`, `'+name'` or `[qw(a b)]`
+fn attribute_names(tokens: &[Token]) -> Vec<String> {
+    match tokens {
+        [word] if word.kind == TokenKind::Word => vec![word.text.to_string()],
+        _ => strings(tokens),
+    }
+}
+
+fn apply_options(attribute: &mut Attribute, content: &str, options: &[Token]) {
+    let option_pairs = pairs(options);
+    if option_pairs.is_empty() {
+        // Mojo::Base: `has name => sub { ... }` or `has name => 42`
+        if let (Some(first), Some(last)) = (options.first(), options.last()) {
+            attribute.default = Some(content[first.start..last.end].to_string());
+        }
+    }
+
+    let mut explicit_accessor = false;
+    for (key, value) in option_pairs {
+        let text = match (value.first(), value.last()) {
+            (Some(first), Some(last)) => &content[first.start..last.end],
+            _ => continue,
+        };
+        let string = match value {
+            [token] if matches!(token.kind, TokenKind::String | TokenKind::InterpolatedString) => lexer::string_contents(token.text),
+            [token] if token.kind == TokenKind::Word => Some(token.text),
+            _ => None,
+        };
+        let enabled = !matches!(text, "0" | "''" | "\"\"" | "undef");
+        let name = attribute.name.clone();
+
+        match key {
+            "is" => attribute.is = string.map(str::to_string),
+            "isa" => attribute.isa = Some(string.unwrap_or(text).to_string()),
+            "required" => attribute.required = enabled,
+            "lazy" => attribute.lazy = enabled,
+            "default" => attribute.default = Some(text.to_string()),
+            "builder" if text == "1" => attribute.builder = Some(format!("_build_{}", name)),
+            "builder" => attribute.builder = string.map(str::to_string),
+            "lazy_build" if enabled => {
+                attribute.lazy = true;
+                attribute.builder = Some(format!("_build_{}", name));
+                attribute.methods.push(format!("clear_{}", name));
+                attribute.methods.push(format!("has_{}", name));
+            }
+            "trigger" => {
+                attribute.trigger = match value {
+                    [reference, code] if reference.is_op("\\") && code.kind == TokenKind::Variable => {
+                        code.text.strip_prefix('&').map(str::to_string)
+                    }
+                    _ => None,
+                };
+            }
+            "predicate" | "clearer" if text == "1" => {
+                let prefix = if key == "predicate" { "has" } else { "clear" };
+                attribute.methods.push(match name.strip_prefix('_') {
+                    Some(private) => format!("_{}_{}", prefix, private),
+                    None => format!("{}_{}", prefix, name),
+                });
+            }
+            key if METHOD_OPTIONS.contains(&key) => {
+                explicit_accessor |= matches!(key, "reader" | "accessor");
+                if let Some(method) = string {
+                    attribute.methods.push(method.to_string());
+                }
+            }
+            "handles" => {
+                let delegated: Vec<String> = match value.first().map(|t| t.kind) {
+                    Some(TokenKind::OpenBrace) => pairs(value).into_iter().map(|(method, _)| method.to_string()).collect(),
+                    Some(TokenKind::OpenBracket) => strings(value),
+                    _ => Vec::new(),
+                };
+                attribute.methods.extend(delegated);
+            }
+            _ => {}
+        }
+    }
+
+    if !explicit_accessor && attribute.is.as_deref() != Some("bare") {
+        attribute.methods.insert(0, attribute.name.clone());
+    }
+    if attribute.is.as_deref() == Some("rwp") {
+        attribute.methods.push(format!("_set_{}", attribute.name));
+    }
+    // Moo: `is => 'lazy'` builds the value with `_build_name` unless it has a default
+    if attribute.is.as_deref() == Some("lazy") {
+        attribute.lazy = true;
+        if attribute.builder.is_none() && attribute.default.is_none() {
+            attribute.builder = Some(format!("_build_{}", attribute.name));
+        }
+    }
+}
+
+/// Whether `sub` calls one of the attribute's methods on its invocant or
+/// accesses `$self->{name}` directly
+fn uses_attribute(sub: &Subroutine, attribute: &Attribute) -> bool {
+    let tokens = lexer::significant_tokens(&sub.code);
+    tokens.windows(3).any(|window| {
+        let [invocant, arrow, member] = window else {
+            return false;
+        };
+        SELF_INVOCANTS.contains(&invocant.text)
+            && arrow.is_op("->")
+            && member.kind == TokenKind::Word
+            && attribute.methods.iter().any(|m| m == member.text)
+    }) || tokens.windows(5).any(|window| {
+        let [invocant, arrow, open, key, close] = window else {
+            return false;
+        };
+        SELF_INVOCANTS.contains(&invocant.text)
+            && arrow.is_op("->")
+            && open.kind == TokenKind::OpenBrace
+            && close.kind == TokenKind::CloseBrace
+            && key_name(std::slice::from_ref(key)) == Some(attribute.name.as_str())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use crate::parser::StaticModuleParser;
+
+    #[test]
+    fn test_moose_attributes() {
+        let content = r#"package Report;
+use Moose;
+
+has title => (is => 'ro', isa => 'Str', required => 1);
+has rows => (
+    is      => 'rw',
+    isa     => 'ArrayRef[HashRef]',
+    lazy    => 1,
+    builder => '_build_rows',
+    trigger => \&_rows_changed,
+    handles => { add_row => 'push' },
+);
+has [qw(width height)] => (is => 'ro', default => 80);
+has _cache => (is => 'bare', predicate => 1, clearer => 'reset_cache');
+
+sub _build_rows { return [] }
+sub _rows_changed { my ($self) = @_; $self->reset_cache }
+sub render { my $self = shift; return join "\n", $self->title, map { $_->{text} } @{ $self->rows } }
+sub size { my $self = shift; return $self->{width} * $self->height }
+
+1;
+"#;
+        let module = StaticModuleParser::new().parse_source(Path::new("Report.pm"), content).remove(0);
+        let attributes = &module.attributes;
+        let names: Vec<_> = attributes.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, vec!["title", "rows", "width", "height", "_cache"]);
+
+        let title = &attributes[0];
+        assert_eq!((title.is.as_deref(), title.isa.as_deref(), title.required), (Some("ro"), Some("Str"), true));
+        assert_eq!((title.line_start, title.line_end), (4, 4));
+        assert_eq!(title.code, "has title => (is => 'ro', isa => 'Str', required => 1);");
+        assert_eq!(title.used_by, vec!["render"]);
+
+        let rows = &attributes[1];
+        assert_eq!((rows.line_start, rows.line_end), (5, 12));
+        assert!(rows.lazy);
+        assert_eq!(rows.isa.as_deref(), Some("ArrayRef[HashRef]"));
+        assert_eq!(rows.builder.as_deref(), Some("_build_rows"));
+        assert_eq!(rows.trigger.as_deref(), Some("_rows_changed"));
+        assert_eq!(rows.methods, vec!["rows", "add_row"]);
+        assert_eq!(rows.linked_subroutines(), vec!["_build_rows", "_rows_changed", "render"]);
+
+        assert_eq!(attributes[2].default.as_deref(), Some("80"));
+        assert_eq!(attributes[2].used_by, vec!["size"]);
+        assert_eq!(attributes[3].used_by, vec!["size"]);
+
+        let cache = &attributes[4];
+        assert_eq!(cache.methods, vec!["_has_cache", "reset_cache"]);
+        assert_eq!(cache.used_by, vec!["_rows_changed"]);
+
+        let owned: Vec<_> = module.attributes_owned_by(&["size", "render"]).iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(owned, vec!["title", "width", "height"]);
+    }
+
+    #[test]
+    fn test_moo_and_mojo_attribute_styles() {
+        let content = r#"package Client;
+use Moo;
+has url => is => 'ro', lazy_build => 1;
+has '+timeout' => (default => 30);
+has agent => (is => 'lazy');
+has retries => (is => 'lazy', default => 3);
+sub fetch { my $self = shift; return $self->url }
+sub _build_agent { return HTTP::Tiny->new }
+
+package App;
+use Mojo::Base -base;
+has log => sub { Mojo::Log->new };
+has 'name';
+
+1;
+"#;
+        let modules = StaticModuleParser::new().parse_source(Path::new("Client.pm"), content);
+
+        let url = &modules[0].attributes[0];
+        assert_eq!((url.name.as_str(), url.is.as_deref(), url.lazy), ("url", Some("ro"), true));
+        assert_eq!(url.builder.as_deref(), Some("_build_url"));
+        assert_eq!(url.methods, vec!["url", "clear_url", "has_url"]);
+        assert_eq!(url.used_by, vec!["fetch"]);
+        assert_eq!(modules[0].attributes[1].name, "timeout");
+
+        let agent = &modules[0].attributes[2];
+        assert_eq!((agent.is.as_deref(), agent.lazy, agent.builder.as_deref()), (Some("lazy"), true, Some("_build_agent")));
+        assert_eq!(agent.linked_subroutines(), vec!["_build_agent"]);
+        let retries = &modules[0].attributes[3];
+        assert_eq!((retries.lazy, retries.builder.as_deref()), (true, None));
+
+        let log = &modules[1].attributes[0];
+        assert_eq!(log.default.as_deref(), Some("sub { Mojo::Log->new }"));
+        assert_eq!(modules[1].attributes[1].name, "name");
+        assert_eq!(modules[1].attributes[1].methods, vec!["name"]);
+    }
+}
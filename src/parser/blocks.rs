@@ -0,0 +1,289 @@
+use std::ops::Range;
+use crate::domain::models::{BlockKind, DataSection, PerlModule, SpecialBlock};
+use super::call_graph::calls_in;
+use super::lexer::{self, Token, TokenKind};
+use super::static_parser::matching_brace;
+use super::syntax::{statement, variable_name};
+use super::variables::{code_usage, METADATA_VARIABLES};
+
+const PHASES: &[(&str, BlockKind)] = &[
+    ("BEGIN", BlockKind::Begin),
+    ("UNITCHECK", BlockKind::Unitcheck),
+    ("CHECK", BlockKind::Check),
+    ("INIT", BlockKind::Init),
+    ("END", BlockKind::End),
+];
+
+/// Statements that declare something other analyses already model
+const DECLARATIONS: &[&str] = &["package", "use", "no", "require", "sub", "has", "extends", "with", "format"];
+
+/// Statements whose body is a block rather than ending at `;`
+const COMPOUND_STATEMENTS: &[&str] = &["if", "unless", "while", "until", "for", "foreach"];
+
+/// Record the special blocks and the other code of the package that runs
+/// when it is loaded.
+///
+/// Consecutive top-level statements form one `TopLevel` block. Declarations
+/// (`use`, `package`, `has`, ...), variables declared without a value,
+/// export lists, `$VERSION` and the final true value are not counted.
+/// Named subroutines and the code in bare blocks around them are looked
+/// into, so `{ my %cache = ...; sub get { ... } }` yields the `%cache`
+/// initialization.
+pub(crate) fn record_special_blocks(
+    module: &mut PerlModule,
+    content: &str,
+    tokens: &[Token],
+    in_package: impl Fn(usize) -> bool,
+    sub_ranges: &[Range<usize>],
+) {
+    let mut ranges: Vec<(BlockKind, Range<usize>)> = Vec::new();
+    let mut contiguous = false;
+    let mut i = 0;
+
+    while i < tokens.len() {
+        let token = &tokens[i];
+        if let Some(sub) = sub_ranges.iter().find(|r| r.start == i) {
+            i = sub.end;
+            contiguous = false;
+            continue;
+        }
+        if !in_package(i) || token.kind == TokenKind::DataSection {
+            i += 1;
+            contiguous = false;
+            continue;
+        }
+        if matches!(token.kind, TokenKind::OpenBrace | TokenKind::CloseBrace) || token.is_op(";") {
+            i += 1;
+            continue;
+        }
+
+        let end = statement_end(tokens, i, sub_ranges);
+        if let Some(kind) = phase(tokens, i) {
+            ranges.push((kind, i..end));
+            contiguous = false;
+        } else if runs_at_load_time(&tokens[i..end]) {
+            match ranges.last_mut() {
+                Some((BlockKind::TopLevel, range)) if contiguous => range.end = end,
+                _ => ranges.push((BlockKind::TopLevel, i..end)),
+            }
+            contiguous = true;
+        } else {
+            contiguous = false;
+        }
+        i = end;
+    }
+
+    let mut blocks = Vec::new();
+    for (kind, range) in ranges {
+        let first = &tokens[range.start];
+        let last = &tokens[range.end - 1];
+        let code = &content[first.start..last.end];
+        let (reads, writes) = code_usage(code, kind != BlockKind::TopLevel, module);
+        let used_by = module.subroutines
+            .iter()
+            .filter(|s| writes.iter().any(|v| s.uses_variable(v)))
+            .map(|s| s.name.clone())
+            .collect();
+        blocks.push(SpecialBlock {
+            kind,
+            code: code.to_string(),
+            line_start: first.line,
+            line_end: last.line + last.text.matches('\n').count(),
+            calls: calls_in(code, module),
+            reads,
+            writes,
+            used_by,
+        });
+    }
+    module.special_blocks = blocks;
+}
+
+/// The `__DATA__` or `__END__` section of the package, with the subroutines
+/// that read it through the `DATA` filehandle
+pub(crate) fn data_section(tokens: &[Token], in_package: impl Fn(usize) -> bool, module: &PerlModule) -> Option<DataSection> {
+    let (_, token) = tokens
+        .iter()
+        .enumerate()
+        .find(|(i, t)| t.kind == TokenKind::DataSection && in_package(*i))?;
+    let (marker, content) = token.text.split_once('\n').unwrap_or((token.text, ""));
+
+    Some(DataSection {
+        marker: marker.trim().to_string(),
+        content: content.to_string(),
+        line: token.line,
+        used_by: module.subroutines
+            .iter()
+            .filter(|s| reads_data(&s.code))
+            .map(|s| s.name.clone())
+            .collect(),
+    })
+}
+
+/// `<DATA>`, `DATA` used as a filehandle or `*DATA`
+fn reads_data(code: &str) -> bool {
+    lexer::significant_tokens(code).iter().any(|t| {
+        let name = match t.kind {
+            TokenKind::Readline => t.text.trim_start_matches('<').trim_end_matches('>'),
+            TokenKind::Word => t.text,
+            TokenKind::Variable => t.text.strip_prefix('*').unwrap_or_default(),
+            _ => return false,
+        };
+        name == "DATA" || name.ends_with("::DATA")
+    })
+}
+
+/// The phase of a `BEGIN { ... }` style block starting at `i`
+fn phase(tokens: &[Token], i: usize) -> Option<BlockKind> {
+    let token = &tokens[i];
+    let opens_block = tokens.get(i + 1).is_some_and(|t| t.kind == TokenKind::OpenBrace);
+    let (_, kind) = PHASES.iter().find(|(name, _)| token.is_word(name) && opens_block)?;
+    Some(*kind)
+}
+
+/// Index one past the end of the statement starting at `i`. Stops before
+/// the next named subroutine.
+fn statement_end(tokens: &[Token], i: usize, sub_ranges: &[Range<usize>]) -> usize {
+    let token = &tokens[i];
+    let end = if token.is_word("package") {
+        // `package Foo;` or the header of `package Foo { ... }`
+        tokens[i..]
+            .iter()
+            .position(|t| t.is_op(";") || t.kind == TokenKind::OpenBrace)
+            .map_or(tokens.len(), |p| i + p)
+    } else if phase(tokens, i).is_some() || (token.kind == TokenKind::Word && COMPOUND_STATEMENTS.contains(&token.text)) {
+        block_end(tokens, i)
+    } else {
+        let len = statement(tokens, i).len();
+        i + len + usize::from(tokens.get(i + len).is_some_and(|t| t.is_op(";")))
+    };
+
+    sub_ranges
+        .iter()
+        .map(|r| r.start)
+        .filter(|&start| start > i && start < end)
+        .min()
+        .unwrap_or(end)
+        .max(i + 1)
+}
+
+/// End of a compound statement: its first block and any `elsif`, `else` or
+/// `continue` blocks that follow
+fn block_end(tokens: &[Token], i: usize) -> usize {
+    let mut from = i;
+    loop {
+        let Some(close) = tokens[from..]
+            .iter()
+            .position(|t| t.kind == TokenKind::OpenBrace)
+            .and_then(|open| matching_brace(tokens, from + open))
+        else {
+            return tokens.len();
+        };
+        match tokens.get(close + 1) {
+            Some(next) if next.is_word("elsif") || next.is_word("else") || next.is_word("continue") => from = close + 1,
+            _ => return close + 1,
+        }
+    }
+}
+
+/// Whether a top-level statement does work when the module is loaded
+fn runs_at_load_time(statement: &[Token]) -> bool {
+    let Some(first) = statement.first() else {
+        return false;
+    };
+    if first.kind == TokenKind::Word && DECLARATIONS.contains(&first.text) {
+        return false;
+    }
+    // The module's true value
+    if matches!(statement, [value] | [value, _] if matches!(value.kind, TokenKind::Number | TokenKind::String) || value.is_word("__PACKAGE__")) {
+        return false;
+    }
+    let declares = first.is_word("my") || first.is_word("our") || first.is_word("state");
+    if declares && !statement.iter().any(|t| t.is_op("=")) {
+        return false;
+    }
+    !statement
+        .iter()
+        .any(|t| t.kind == TokenKind::Variable && METADATA_VARIABLES.contains(&variable_name(t.text).as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use crate::domain::models::BlockKind;
+    use crate::parser::StaticModuleParser;
+
+    #[test]
+    fn test_special_blocks_and_load_time_code() {
+        let content = r#"package Config::Loader;
+use strict;
+our $VERSION = '1.0';
+our @EXPORT_OK = qw(load);
+
+my %defaults;
+my $debug = $ENV{DEBUG} ? 1 : 0;
+register('ini');
+
+BEGIN {
+    my $start = time;
+    %defaults = (format => 'ini');
+}
+
+if ($debug) {
+    print STDERR "loading\n";
+} else {
+    register('json');
+}
+
+sub register { my ($format) = @_; $defaults{$format} = 1 }
+sub load { my ($file) = @_; return $defaults{format} if $debug }
+
+{
+    my %cache = (hits => 0);
+    sub cached { return $cache{hits}++ }
+}
+
+END { register('cleanup') }
+
+1;
+"#;
+        let module = StaticModuleParser::new().parse_source(Path::new("Loader.pm"), content).remove(0);
+        let blocks = &module.special_blocks;
+        let kinds: Vec<_> = blocks.iter().map(|b| (b.kind, b.line_start, b.line_end)).collect();
+        assert_eq!(kinds, vec![
+            (BlockKind::TopLevel, 7, 8),
+            (BlockKind::Begin, 10, 13),
+            (BlockKind::TopLevel, 15, 19),
+            (BlockKind::TopLevel, 25, 25),
+            (BlockKind::End, 29, 29),
+        ]);
+
+        let setup = &blocks[0];
+        assert_eq!(setup.code, "my $debug = $ENV{DEBUG} ? 1 : 0;\nregister('ini');");
+        assert_eq!(setup.calls, vec!["register"]);
+        assert_eq!(setup.reads, vec!["%ENV"]);
+        assert_eq!(setup.writes, vec!["$debug"]);
+        assert_eq!(setup.linked_subroutines(), vec!["register", "load"]);
+
+        let begin = &blocks[1];
+        assert_eq!(begin.writes, vec!["%defaults"]);
+        assert_eq!(begin.used_by, vec!["register", "load"]);
+
+        assert_eq!(blocks[3].code, "my %cache = (hits => 0);");
+        assert_eq!(blocks[3].used_by, vec!["cached"]);
+        assert_eq!(blocks[4].calls, vec!["register"]);
+        assert!(module.data_section.is_none());
+    }
+
+    #[test]
+    fn test_data_section_readers() {
+        let content = "package Templates;\nsub all { local $/; return <DATA> }\nsub rewind { seek(DATA, 0, 0) }\nsub other { return 1 }\n1;\n__DATA__\nhello\nworld\n";
+        let module = StaticModuleParser::new().parse_source(Path::new("Templates.pm"), content).remove(0);
+        let data = module.data_section.expect("a data section");
+
+        assert_eq!(data.marker, "__DATA__");
+        assert_eq!(data.content, "hello\nworld\n");
+        assert_eq!(data.line, 6);
+        assert_eq!(data.used_by, vec!["all", "rewind"]);
+        assert!(module.special_blocks.is_empty());
+    }
+}
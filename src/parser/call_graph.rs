@@ -0,0 +1,148 @@
+use crate::domain::models::{CallEdge, CallGraph, CallKind, PerlModule};
+use super::lexer::{self, Token, TokenKind};
+
+/// Invocants whose method calls resolve to the module itself
+pub(crate) const SELF_INVOCANTS: &[&str] = &["$self", "$class", "$this", "$proto", "shift", "__PACKAGE__"];
+
+/// Build the graph of calls between the subroutines of `module`.
+///
+/// Recognizes `foo(...)` and bare `foo` calls, `&foo`, `Package::foo` for the
+/// module's own package, method calls on `$self`, `$class`, `shift`,
+/// `__PACKAGE__` or the package name, and `\&foo` code references. Calls to
+/// subroutines that are not defined in the module are ignored.
+pub fn build_call_graph(module: &PerlModule) -> CallGraph {
+    let names: Vec<&str> = module.subroutines.iter().map(|s| s.name.as_str()).collect();
+    let mut edges: Vec<CallEdge> = Vec::new();
+
+    for sub in &module.subroutines {
+        let tokens = lexer::significant_tokens(&sub.code);
+        // Skip `sub name` itself
+        let body = tokens.get(2..).unwrap_or_default();
+        for (i, token) in body.iter().enumerate() {
+            let Some((callee, kind)) = call_at(body, i, &module.name, &names) else {
+                continue;
+            };
+            let duplicate = edges
+                .iter()
+                .any(|e| e.caller == sub.name && e.callee == callee && e.kind == kind);
+            if !duplicate {
+                edges.push(CallEdge {
+                    caller: sub.name.clone(),
+                    callee: callee.to_string(),
+                    kind,
+                    line: sub.line_start + token.line - 1,
+                });
+            }
+        }
+    }
+
+    CallGraph { edges }
+}
+
+/// Subroutines of `module` called or referenced in `code`, in order of first use
+pub(crate) fn calls_in(code: &str, module: &PerlModule) -> Vec<String> {
+    let names: Vec<&str> = module.subroutines.iter().map(|s| s.name.as_str()).collect();
+    let tokens = lexer::significant_tokens(code);
+    let mut calls: Vec<String> = Vec::new();
+    for i in 0..tokens.len() {
+        if let Some((callee, _)) = call_at(&tokens, i, &module.name, &names) {
+            if !calls.iter().any(|c| c == callee) {
+                calls.push(callee.to_string());
+            }
+        }
+    }
+    calls
+}
+
+/// The subroutine called (or referenced) by the token at `i`, if any
+fn call_at<'a>(tokens: &[Token<'a>], i: usize, package: &str, names: &[&str]) -> Option<(&'a str, CallKind)> {
+    let token = &tokens[i];
+    let prev = i.checked_sub(1).map(|p| &tokens[p]);
+    let next = tokens.get(i + 1);
+
+    match token.kind {
+        TokenKind::Variable if token.text.starts_with('&') => {
+            let name = local_name(&token.text[1..], package)?;
+            names.contains(&name).then_some(())?;
+            let kind = if prev.is_some_and(|t| t.is_op("\\")) {
+                CallKind::CodeReference
+            } else {
+                CallKind::Function
+            };
+            Some((name, kind))
+        }
+        TokenKind::Word => {
+            if prev.is_some_and(|t| t.is_op("->")) {
+                let invocant = i.checked_sub(2).map(|p| &tokens[p])?;
+                let on_self = SELF_INVOCANTS.contains(&invocant.text) || invocant.text == package;
+                (on_self && names.contains(&token.text)).then_some((token.text, CallKind::Method))
+            } else {
+                let is_hash_key = next.is_some_and(|t| t.is_op("=>"))
+                    || (prev.is_some_and(|t| t.kind == TokenKind::OpenBrace)
+                        && next.is_some_and(|t| t.kind == TokenKind::CloseBrace));
+                let is_class_name = next.is_some_and(|t| t.is_op("->"));
+                if is_hash_key || is_class_name || prev.is_some_and(|t| t.is_word("sub")) {
+                    return None;
+                }
+                let name = local_name(token.text, package)?;
+                names.contains(&name).then_some((name, CallKind::Function))
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Strip the module's own package from a qualified name; other packages yield `None`
+fn local_name<'a>(name: &'a str, package: &str) -> Option<&'a str> {
+    match name.rsplit_once("::") {
+        Some((qualifier, short)) if qualifier == package || qualifier == "__PACKAGE__" => Some(short),
+        Some(_) => None,
+        None => Some(name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use crate::parser::StaticModuleParser;
+
+    #[test]
+    fn test_call_graph_edge_kinds() {
+        let content = r#"package Report;
+
+sub new { my ($class) = @_; my $self = bless {}, $class; $self->_init; return $self }
+sub _init { my $self = shift; $self->{fmt} = \&format_row; return }
+sub render {
+    my ($self) = @_;
+    my @rows = map { format_row($_) } @{ $self->{rows} };
+    return join "\n", @rows, &footer, Report::footer(), __PACKAGE__->title;
+}
+sub format_row { my $row = shift; return "$row" }
+sub footer { 'end' }
+sub title { 'Report' }
+sub unrelated { my %h = (footer => 1); return $h{title} + Other::render() }
+
+1;
+"#;
+        let module = StaticModuleParser::new().parse_source(Path::new("Report.pm"), content).remove(0);
+        let graph = &module.call_graph;
+
+        let edges: Vec<_> = graph.edges.iter()
+            .map(|e| (e.caller.as_str(), e.callee.as_str(), e.kind))
+            .collect();
+        assert_eq!(edges, vec![
+            ("new", "_init", CallKind::Method),
+            ("_init", "format_row", CallKind::CodeReference),
+            ("render", "format_row", CallKind::Function),
+            ("render", "footer", CallKind::Function),
+            ("render", "title", CallKind::Method),
+        ]);
+        assert_eq!(graph.edges[0].line, 3);
+        assert_eq!(graph.edges[2].line, 7);
+
+        assert_eq!(graph.callees("render"), vec!["format_row", "footer", "title"]);
+        assert_eq!(graph.callers("format_row"), vec!["_init", "render"]);
+        assert!(graph.callees("unrelated").is_empty());
+    }
+}
@@ -0,0 +1,157 @@
+use std::path::Path;
+use super::lexer;
+use super::static_parser::package_declaration;
+use super::StaticModuleParser;
+
+/// Token budget for the module content of a single analysis request
+pub const DEFAULT_TOKEN_BUDGET: usize = 12_000;
+
+/// Progress of a chunked analysis, reported through `AIModuleParser::with_progress`
+#[derive(Debug, Clone, PartialEq)]
+pub enum AnalysisProgress {
+    /// Chunk `chunk` of `total` (1-based), covering the given lines, is being analyzed
+    Analyzing { chunk: usize, total: usize, line_start: usize, line_end: usize },
+    /// Chunk `chunk` of `total` could not be analyzed; the other chunks are kept
+    Failed { chunk: usize, total: usize, error: String },
+    /// Clusters of `package` found in several chunks are being merged
+    Merging { package: String },
+}
+
+/// A run of whole lines of a file that is analyzed on its own
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Chunk {
+    pub line_start: usize,
+    pub line_end: usize,
+    /// Package in effect at the first line, if one was declared before it
+    pub package: Option<String>,
+    pub content: String,
+}
+
+/// Rough token count of `text`, about four bytes per token
+pub(crate) fn estimate_tokens(text: &str) -> usize {
+    text.len().div_ceil(4)
+}
+
+/// Split `content` into chunks of at most `budget` estimated tokens.
+///
+/// Chunks only end at subroutine boundaries, and the comment lines directly
+/// above a subroutine stay with it. A subroutine that is larger than the
+/// budget on its own becomes a chunk of its own.
+pub(crate) fn split_into_chunks(path: &Path, content: &str, budget: usize) -> Vec<Chunk> {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    if lines.is_empty() {
+        return Vec::new();
+    }
+
+    let mut subs: Vec<(usize, usize)> = StaticModuleParser::new()
+        .parse_source(path, content)
+        .iter()
+        .flat_map(|m| m.subroutines.iter().map(|s| (s.line_start, s.line_end)))
+        .collect();
+    subs.sort();
+
+    let mut boundaries = vec![1];
+    let mut covered = 0;
+    for (start, end) in subs {
+        // Named subs nested in another sub stay with the outer one
+        if start <= covered {
+            continue;
+        }
+        boundaries.push(leading_comment_start(&lines, start));
+        boundaries.push(end + 1);
+        covered = end;
+    }
+    boundaries.retain(|&line| line <= lines.len());
+    boundaries.sort();
+    boundaries.dedup();
+
+    // (first line, last line, estimated tokens)
+    let mut ranges: Vec<(usize, usize, usize)> = Vec::new();
+    for (k, &start) in boundaries.iter().enumerate() {
+        let end = boundaries.get(k + 1).map_or(lines.len(), |next| next - 1);
+        let tokens = estimate_tokens(&lines[start - 1..end].concat());
+        match ranges.last_mut() {
+            Some(range) if range.2 + tokens <= budget => {
+                range.1 = end;
+                range.2 += tokens;
+            }
+            _ => ranges.push((start, end, tokens)),
+        }
+    }
+
+    let packages = package_lines(content);
+    ranges
+        .into_iter()
+        .map(|(line_start, line_end, _)| Chunk {
+            line_start,
+            line_end,
+            package: packages
+                .iter()
+                .take_while(|(line, _)| *line < line_start)
+                .last()
+                .map(|(_, name)| name.clone()),
+            content: lines[line_start - 1..line_end].concat(),
+        })
+        .collect()
+}
+
+/// First line of the comment block directly above `line`
+fn leading_comment_start(lines: &[&str], line: usize) -> usize {
+    let mut start = line;
+    while start > 1 && lines[start - 2].trim_start().starts_with('#') {
+        start -= 1;
+    }
+    start
+}
+
+/// Line and name of every `package` statement, in source order
+fn package_lines(content: &str) -> Vec<(usize, String)> {
+    let tokens = lexer::significant_tokens(content);
+    (0..tokens.len())
+        .filter_map(|i| package_declaration(&tokens, i).map(|name| (tokens[i].line, name.to_string())))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTENT: &str = r#"package Big;
+use strict;
+
+# Add two numbers
+sub add {
+    my ($a, $b) = @_;
+    return $a + $b;
+}
+
+sub subtract {
+    my ($a, $b) = @_;
+    return $a - $b;
+}
+
+package Big::Helper;
+
+sub help { return 1 }
+
+1;
+"#;
+
+    #[test]
+    fn test_chunks_end_at_subroutine_boundaries() {
+        let chunks = split_into_chunks(Path::new("Big.pm"), CONTENT, 20);
+        let ranges: Vec<_> = chunks.iter().map(|c| (c.line_start, c.line_end)).collect();
+        assert_eq!(ranges, vec![(1, 3), (4, 9), (10, 13), (14, 19)]);
+
+        assert_eq!(chunks[0].package, None);
+        assert!(chunks[1].content.starts_with("# Add two numbers\nsub add {\n"));
+        assert_eq!(chunks[1].package.as_deref(), Some("Big"));
+        assert!(chunks[2].content.starts_with("sub subtract {\n"));
+        assert!(chunks[3].content.starts_with("\npackage Big::Helper;\n"));
+        assert_eq!(chunks[3].package.as_deref(), Some("Big"));
+
+        let whole = split_into_chunks(Path::new("Big.pm"), CONTENT, DEFAULT_TOKEN_BUDGET);
+        assert_eq!(whole.len(), 1);
+        assert_eq!(whole[0].content, CONTENT);
+    }
+}
@@ -0,0 +1,228 @@
+use crate::domain::models::{ClassInfo, ParameterStyle, Signature, Subroutine, SubroutineKind};
+use super::exports::EXPORTERS;
+use super::lexer::{self, Token, TokenKind};
+use super::syntax::{is_pushed, items, statement, strings, variable_name};
+
+/// Modules that turn a package into a class or role: (module, object system, is role)
+const OBJECT_SYSTEMS: &[(&str, &str, bool)] = &[
+    ("Moose", "Moose", false),
+    ("Moose::Role", "Moose", true),
+    ("Moo", "Moo", false),
+    ("Moo::Role", "Moo", true),
+    ("Mouse", "Mouse", false),
+    ("Mouse::Role", "Mouse", true),
+    ("Role::Tiny", "Role::Tiny", true),
+    ("Class::Tiny", "Class::Tiny", false),
+];
+
+/// Object systems that provide `new` for their classes
+const PROVIDES_NEW: &[&str] = &["Moose", "Moo", "Mouse", "Mojo::Base", "Class::Tiny"];
+
+const INSTANCE_INVOCANTS: &[&str] = &["$self", "$this", "$me"];
+const CLASS_INVOCANTS: &[&str] = &["$class", "$proto", "$pkg", "$package"];
+
+/// Object-oriented structure of the package whose tokens satisfy `in_package`.
+///
+/// Superclasses come from `use parent`, `use base`, `@ISA` and `extends`
+/// (exporter modules are not counted), roles from `with`. `subroutines` must
+/// already have their `kind` set. Returns `None` for packages that are
+/// neither classes nor roles.
+pub(crate) fn extract_class(
+    tokens: &[Token],
+    in_package: impl Fn(usize) -> bool,
+    subroutines: &[Subroutine],
+) -> Option<ClassInfo> {
+    let mut class = ClassInfo::default();
+
+    for (i, token) in tokens.iter().enumerate() {
+        if !in_package(i) {
+            continue;
+        }
+        let prev = i.checked_sub(1).map(|p| &tokens[p]);
+        let starts_statement = prev.is_none_or(|t| t.is_op(";") || t.kind == TokenKind::CloseBrace || t.kind == TokenKind::OpenBrace);
+
+        match token.kind {
+            TokenKind::Word if token.text == "use" => {
+                let Some(module) = tokens.get(i + 1).filter(|t| t.kind == TokenKind::Word) else {
+                    continue;
+                };
+                let args = statement(tokens, i + 2);
+                match module.text {
+                    "parent" | "base" => add_superclasses(&mut class, strings(args)),
+                    "Mojo::Base" => {
+                        // `use Mojo::Base -strict` only enables pragmas
+                        let flags: Vec<&str> = args
+                            .windows(2)
+                            .filter(|pair| pair[0].is_op("-") && pair[1].kind == TokenKind::Word)
+                            .map(|pair| pair[1].text)
+                            .collect();
+                        let parents = strings(args);
+                        if flags.contains(&"base") || flags.contains(&"role") || !parents.is_empty() {
+                            class.object_system.get_or_insert_with(|| module.text.to_string());
+                            class.is_role |= flags.contains(&"role");
+                            add_superclasses(&mut class, parents);
+                        }
+                    }
+                    name => {
+                        if let Some((_, system, is_role)) = OBJECT_SYSTEMS.iter().find(|(m, _, _)| *m == name) {
+                            class.object_system.get_or_insert_with(|| system.to_string());
+                            class.is_role |= is_role;
+                        }
+                    }
+                }
+            }
+            TokenKind::Word if starts_statement && (token.text == "extends" || token.text == "with") => {
+                let names = top_level_strings(statement(tokens, i + 1));
+                if token.text == "extends" {
+                    add_superclasses(&mut class, names);
+                } else {
+                    for role in names {
+                        if !class.roles.contains(&role) {
+                            class.roles.push(role);
+                        }
+                    }
+                }
+            }
+            TokenKind::Variable if variable_name(token.text) == "@ISA" => {
+                if tokens.get(i + 1).is_some_and(|t| t.is_op("=")) {
+                    add_superclasses(&mut class, strings(statement(tokens, i + 2)));
+                } else if is_pushed(tokens, i) {
+                    add_superclasses(&mut class, strings(statement(tokens, i + 1)));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let blesses = |sub: &&Subroutine| lexer::significant_tokens(&sub.code).iter().any(|t| t.is_word("bless"));
+    class.constructor = subroutines
+        .iter()
+        .filter(blesses)
+        .find(|s| s.name == "new")
+        .or_else(|| subroutines.iter().find(blesses))
+        .map(|s| s.name.clone());
+    let provides_new = class.object_system.as_deref().is_some_and(|s| PROVIDES_NEW.contains(&s));
+    if class.constructor.is_none() && provides_new && !class.is_role {
+        class.constructor = Some("new".to_string());
+    }
+
+    let is_class = class.object_system.is_some()
+        || !class.superclasses.is_empty()
+        || !class.roles.is_empty()
+        || class.constructor.is_some()
+        || subroutines.iter().any(|s| s.kind != SubroutineKind::Function);
+    is_class.then_some(class)
+}
+
+/// Whether a subroutine is a method, judging by the name of its first
+/// parameter: a signature, `my $self = shift` or `my ($self, ...) = @_`
+pub(crate) fn subroutine_kind(signature: &Signature) -> SubroutineKind {
+    if signature.style == ParameterStyle::Direct {
+        return SubroutineKind::Function;
+    }
+    match signature.parameters.first().map(|p| p.name.as_str()) {
+        Some(name) if INSTANCE_INVOCANTS.contains(&name) => SubroutineKind::InstanceMethod,
+        Some(name) if CLASS_INVOCANTS.contains(&name) => SubroutineKind::ClassMethod,
+        _ => SubroutineKind::Function,
+    }
+}
+
+fn add_superclasses(class: &mut ClassInfo, names: Vec<String>) {
+    for name in names {
+        if !EXPORTERS.contains(&name.as_str()) && !class.superclasses.contains(&name) {
+            class.superclasses.push(name);
+        }
+    }
+}
+
+/// Names listed directly in an `extends`/`with` statement, ignoring option
+/// hashes such as `with 'Role' => { -excludes => 'method' }`
+fn top_level_strings(tokens: &[Token]) -> Vec<String> {
+    let tokens = match tokens {
+        [open, inner @ .., close] if open.kind == TokenKind::OpenParen && close.kind == TokenKind::CloseParen => inner,
+        _ => tokens,
+    };
+    items(tokens)
+        .into_iter()
+        .filter(|item| matches!(item, [t] if matches!(t.kind, TokenKind::String | TokenKind::InterpolatedString | TokenKind::QuoteWords)))
+        .flat_map(strings)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use crate::domain::models::SubroutineKind;
+    use crate::parser::StaticModuleParser;
+
+    #[test]
+    fn test_plain_perl_class() {
+        let content = r#"package Animal::Dog;
+use strict;
+use parent -norequire, 'Animal';
+use Exporter 'import';
+our @ISA;
+push @ISA, 'Pet';
+
+sub new {
+    my ($class, %args) = @_;
+    return bless { %args }, $class;
+}
+sub speak { my $self = shift; return $self->{sound} }
+sub create { my $proto = shift; return $proto->new(@_) }
+sub helper { my ($x) = @_; return $x }
+
+1;
+"#;
+        let module = StaticModuleParser::new().parse_source(Path::new("Dog.pm"), content).remove(0);
+        let class = module.class.expect("a class");
+
+        assert_eq!(class.object_system, None);
+        assert!(!class.is_role);
+        assert_eq!(class.superclasses, vec!["Animal", "Pet"]);
+        assert_eq!(class.constructor.as_deref(), Some("new"));
+
+        let kinds: Vec<_> = module.subroutines.iter().map(|s| s.kind).collect();
+        assert_eq!(kinds, vec![
+            SubroutineKind::ClassMethod,
+            SubroutineKind::InstanceMethod,
+            SubroutineKind::ClassMethod,
+            SubroutineKind::Function,
+        ]);
+    }
+
+    #[test]
+    fn test_moose_class_and_role() {
+        let content = r#"package Shape;
+use Moose;
+extends 'Base::Shape', 'Drawable';
+with 'Role::Area' => { -excludes => 'perimeter' }, 'Role::Print';
+
+sub area ($self) { return 0 }
+
+package Role::Print;
+use Moo::Role;
+sub print_me { my $self = shift; print $self->describe }
+
+package Plain;
+sub add { return $_[0] + $_[1] }
+
+1;
+"#;
+        let modules = StaticModuleParser::new().parse_source(Path::new("Shape.pm"), content);
+
+        let shape = modules[0].class.as_ref().expect("a class");
+        assert_eq!(shape.object_system.as_deref(), Some("Moose"));
+        assert_eq!(shape.superclasses, vec!["Base::Shape", "Drawable"]);
+        assert_eq!(shape.roles, vec!["Role::Area", "Role::Print"]);
+        assert_eq!(shape.constructor.as_deref(), Some("new"));
+        assert_eq!(modules[0].subroutines[0].kind, SubroutineKind::InstanceMethod);
+
+        let role = modules[1].class.as_ref().expect("a role");
+        assert!(role.is_role);
+        assert_eq!(role.object_system.as_deref(), Some("Moo"));
+        assert_eq!(role.constructor, None);
+
+        assert!(modules[2].class.is_none());
+    }
+}
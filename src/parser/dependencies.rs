@@ -0,0 +1,249 @@
+use crate::domain::models::{Dependency, LoadPhase};
+use super::lexer::{self, Token, TokenKind};
+use super::syntax::{items, statement, strings};
+
+/// Blocks whose body only runs under a condition
+const CONDITIONS: &[&str] = &["if", "elsif", "unless"];
+
+/// The dependencies loaded by the statement starting at `i`: `use`, `no`,
+/// `require`, `use if COND, Module => ...` and `eval "use Module"`.
+///
+/// `require` is a runtime dependency, or a conditional one under an
+/// `if`/`unless` modifier or inside an `if`, `unless`, `else` or `eval`
+/// block. Anything loaded by a string `eval` or `use if` is conditional.
+pub(crate) fn loaded_dependencies(tokens: &[Token], i: usize) -> Vec<Dependency> {
+    let token = &tokens[i];
+    if token.is_word("eval") {
+        return evaluated(tokens, i);
+    }
+    if !(token.is_word("use") || token.is_word("no") || token.is_word("require")) {
+        return Vec::new();
+    }
+    // Only barewords name a module: `use 5.010` and `require "file.pl"` do not
+    let Some(module) = tokens.get(i + 1).filter(|t| t.kind == TokenKind::Word) else {
+        return Vec::new();
+    };
+    let mut args = statement(tokens, i + 2);
+    let mut dependency = Dependency::new(module.text);
+
+    if token.is_word("require") {
+        dependency.phase = if is_conditional(tokens, i) { LoadPhase::Conditional } else { LoadPhase::Runtime };
+        return vec![dependency];
+    }
+    if module.text == "if" && token.is_word("use") {
+        // `use if $] < 5.010, 'MRO::Compat';`
+        let list = items(args);
+        let Some(name) = list.get(1).and_then(|item| strings(item).into_iter().next().or_else(|| key(item))) else {
+            return Vec::new();
+        };
+        let mut dependency = Dependency::new(name);
+        dependency.phase = LoadPhase::Conditional;
+        dependency.imports = list.get(2..).and_then(|rest| import_list(&rest.concat()));
+        return vec![dependency];
+    }
+
+    dependency.unimport = token.is_word("no");
+    if let [version, rest @ ..] = args {
+        let is_argument = rest.first().is_some_and(|t| t.is_op(",") || t.is_op("=>"));
+        if is_version(version) && !is_argument {
+            dependency.version = Some(version.text.to_string());
+            args = rest;
+        }
+    }
+    dependency.imports = import_list(args);
+    vec![dependency]
+}
+
+/// `eval "use Foo; 1"`, `eval q{require Foo}` or `eval("...")`
+fn evaluated(tokens: &[Token], i: usize) -> Vec<Dependency> {
+    let code = match tokens.get(i + 1..).unwrap_or_default() {
+        [open, code, ..] if open.kind == TokenKind::OpenParen => code,
+        [code, ..] => code,
+        [] => return Vec::new(),
+    };
+    if !matches!(code.kind, TokenKind::String | TokenKind::InterpolatedString) {
+        return Vec::new();
+    }
+    let Some(code) = lexer::string_contents(code.text) else {
+        return Vec::new();
+    };
+    let inner = lexer::significant_tokens(code);
+    (0..inner.len())
+        .flat_map(|k| loaded_dependencies(&inner, k))
+        .map(|dependency| Dependency { phase: LoadPhase::Conditional, ..dependency })
+        .collect()
+}
+
+/// A bareword item such as `MRO::Compat` in `use if COND, MRO::Compat => ...`
+fn key(item: &[Token]) -> Option<String> {
+    match item {
+        [word] if word.kind == TokenKind::Word => Some(word.text.to_string()),
+        _ => None,
+    }
+}
+
+/// `1.6`, `5.010_001` or `v1.2.3`
+fn is_version(token: &Token) -> bool {
+    match token.kind {
+        TokenKind::Number => true,
+        TokenKind::Word => token.text.strip_prefix('v').is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit())),
+        _ => false,
+    }
+}
+
+/// The import list of a `use` statement if it is a plain list of strings,
+/// quote-words and `-options`; `()` is an empty list
+fn import_list(args: &[Token]) -> Option<Vec<String>> {
+    if args.is_empty() {
+        return None;
+    }
+    let mut imports = Vec::new();
+    let mut k = 0;
+    while k < args.len() {
+        let token = &args[k];
+        match token.kind {
+            TokenKind::QuoteWords => imports.extend(lexer::quote_words(token.text)),
+            TokenKind::String | TokenKind::InterpolatedString => imports.extend(lexer::string_contents(token.text).map(str::to_string)),
+            TokenKind::OpenParen | TokenKind::CloseParen => {}
+            TokenKind::Operator if token.is_op(",") || token.is_op("=>") => {}
+            TokenKind::Operator if token.is_op("-") => {
+                let option = args.get(k + 1).filter(|t| t.kind == TokenKind::Word)?;
+                imports.push(format!("-{}", option.text));
+                k += 1;
+            }
+            _ => return None,
+        }
+        k += 1;
+    }
+    Some(imports)
+}
+
+/// Whether the statement at `i` only runs under a condition
+fn is_conditional(tokens: &[Token], i: usize) -> bool {
+    // `require Foo if ...;` or `eval { ... } or require Foo;`
+    let start = tokens[..i]
+        .iter()
+        .rposition(|t| t.is_op(";") || matches!(t.kind, TokenKind::OpenBrace | TokenKind::CloseBrace))
+        .map_or(0, |p| p + 1);
+    let guarded = tokens[start..i]
+        .iter()
+        .chain(statement(tokens, i))
+        .any(|t| t.is_word("if") || t.is_word("unless") || t.is_word("or") || t.is_op("||"));
+    if guarded {
+        return true;
+    }
+
+    let mut depth = 0usize;
+    for open in (0..i).rev() {
+        match tokens[open].kind {
+            TokenKind::CloseBrace => depth += 1,
+            TokenKind::OpenBrace if depth > 0 => depth -= 1,
+            TokenKind::OpenBrace if opens_conditional_block(tokens, open) => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// `eval {`, `else {` or `if (...) {` at the brace `open`
+fn opens_conditional_block(tokens: &[Token], open: usize) -> bool {
+    let Some(prev) = open.checked_sub(1).map(|p| &tokens[p]) else {
+        return false;
+    };
+    if prev.is_word("eval") || prev.is_word("else") {
+        return true;
+    }
+    if prev.kind != TokenKind::CloseParen {
+        return false;
+    }
+    let mut depth = 0usize;
+    for k in (0..open).rev() {
+        match tokens[k].kind {
+            TokenKind::CloseParen => depth += 1,
+            TokenKind::OpenParen => {
+                depth -= 1;
+                if depth == 0 {
+                    return k > 0 && CONDITIONS.iter().any(|w| tokens[k - 1].is_word(w));
+                }
+            }
+            _ => {}
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use crate::domain::models::{DependencyKind, LoadPhase};
+    use crate::parser::StaticModuleParser;
+
+    #[test]
+    fn test_structured_dependencies() {
+        let content = r#"package Report;
+use strict;
+use warnings;
+no warnings 'redefine';
+use DBI 1.6;
+use List::Util 1.45 qw(sum max);
+use POSIX ();
+use parent -norequire, 'Base';
+use if $] < 5.010, 'MRO::Compat';
+
+sub load {
+    require JSON::PP;
+    if ($ENV{YAML}) {
+        require YAML;
+    }
+    eval { require Cpanel::JSON::XS; 1 };
+    eval "use Text::CSV_XS; 1" or require Text::CSV;
+    require Data::Dumper if $ENV{DEBUG};
+}
+
+1;
+"#;
+        let module = StaticModuleParser::new().parse_source(Path::new("Report.pm"), content).remove(0);
+        let declarations: Vec<_> = module.dependencies.iter().map(|d| d.declaration()).collect();
+        assert_eq!(declarations, vec![
+            "use strict;",
+            "use warnings;",
+            "no warnings qw(redefine);",
+            "use DBI 1.6;",
+            "use List::Util 1.45 qw(sum max);",
+            "use POSIX ();",
+            "use parent qw(-norequire Base);",
+            "require MRO::Compat;",
+            "require JSON::PP;",
+            "require YAML;",
+            "require Cpanel::JSON::XS;",
+            "require Text::CSV_XS;",
+            "require Text::CSV;",
+            "require Data::Dumper;",
+        ]);
+
+        let phases: Vec<_> = module.dependencies.iter().map(|d| (d.name.as_str(), d.phase)).skip(7).collect();
+        assert_eq!(phases, vec![
+            ("MRO::Compat", LoadPhase::Conditional),
+            ("JSON::PP", LoadPhase::Runtime),
+            ("YAML", LoadPhase::Conditional),
+            ("Cpanel::JSON::XS", LoadPhase::Conditional),
+            ("Text::CSV_XS", LoadPhase::Conditional),
+            ("Text::CSV", LoadPhase::Conditional),
+            ("Data::Dumper", LoadPhase::Conditional),
+        ]);
+        assert_eq!(module.dependencies[0].kind, DependencyKind::Pragma);
+        assert_eq!(module.dependencies[3].kind, DependencyKind::Module);
+        assert!(module.depends_on("warnings"));
+        assert!(!module.depends_on("Moose"));
+    }
+
+    #[test]
+    fn test_dependencies_of_some_subroutines() {
+        let content = "package Stats;\nuse strict;\nuse List::Util qw(sum max first);\nuse POSIX ();\nuse Carp;\nsub total { return sum(@_) }\nsub rounded { return POSIX::floor($_[0]) }\n1;\n";
+        let module = StaticModuleParser::new().parse_source(Path::new("Stats.pm"), content).remove(0);
+        let declarations = |names: &[&str]| -> Vec<String> { module.dependencies_of(names).iter().map(|d| d.declaration()).collect() };
+
+        assert_eq!(declarations(&["total"]), vec!["use strict;", "use List::Util qw(sum);", "use Carp;"]);
+        assert_eq!(declarations(&["rounded"]), vec!["use strict;", "use POSIX ();", "use Carp;"]);
+    }
+}
@@ -0,0 +1,203 @@
+use std::path::Path;
+use tokio::fs;
+use crate::{
+    domain::models::{Charset, Diagnostic, DiagnosticKind, LineEnding, PerlModule, Severity, SourceEncoding},
+    error::Error,
+};
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Characters Windows-1252 puts at 0x80-0x9F. The five unassigned bytes
+/// keep their Latin-1 meaning, so every byte decodes and encodes back.
+const CP1252_HIGH: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8D}', 'Ž', '\u{8F}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9D}', 'ž', 'Ÿ',
+];
+
+/// Read a Perl source file and decode it with `decode_source`
+pub async fn read_source(path: &Path) -> Result<(String, SourceEncoding), Error> {
+    let bytes = fs::read(path).await.map_err(Error::IOError)?;
+    Ok(decode_source(&bytes))
+}
+
+/// Decode Perl source for analysis, with LF line endings.
+///
+/// A byte order mark means UTF-8. Otherwise `use encoding 'latin1'` (or
+/// `'cp1252'`) is honored, valid UTF-8 is taken as UTF-8, and anything else
+/// is a legacy single-byte file: Windows-1252 if it uses the characters
+/// that set adds, Latin-1 if not. Both decode every byte, so this never
+/// fails.
+pub fn decode_source(bytes: &[u8]) -> (String, SourceEncoding) {
+    let (bom, bytes) = match bytes.strip_prefix(UTF8_BOM) {
+        Some(rest) => (true, rest),
+        None => (false, bytes),
+    };
+    let declared = declared_charset(&String::from_utf8_lossy(bytes));
+    let utf8 = std::str::from_utf8(bytes).ok();
+
+    let (charset, text) = match (utf8, declared) {
+        (Some(text), _) if bom => (Charset::Utf8, text.to_string()),
+        (_, Some(charset @ (Charset::Latin1 | Charset::Cp1252))) if !bom => (charset, decode_single_byte(bytes, charset)),
+        (Some(text), _) => (Charset::Utf8, text.to_string()),
+        (None, _) => {
+            let charset = if bytes.iter().any(|&b| is_cp1252_only(b)) { Charset::Cp1252 } else { Charset::Latin1 };
+            (charset, decode_single_byte(bytes, charset))
+        }
+    };
+
+    let crlf = text.matches("\r\n").count();
+    let line_ending = if crlf > 0 && crlf * 2 >= text.matches('\n').count() { LineEnding::CrLf } else { LineEnding::Lf };
+    let encoding = SourceEncoding { charset, bom, line_ending, declared };
+    (text.replace("\r\n", "\n"), encoding)
+}
+
+/// Encode text for a file in `encoding`, converting its line endings.
+/// Fails if a character does not exist in a single-byte charset.
+pub fn encode_source(text: &str, encoding: &SourceEncoding) -> Result<Vec<u8>, Error> {
+    let text = text.replace("\r\n", "\n");
+    let text = match encoding.line_ending {
+        LineEnding::Lf => text,
+        LineEnding::CrLf => text.replace('\n', "\r\n"),
+    };
+
+    let mut bytes = Vec::with_capacity(text.len() + UTF8_BOM.len());
+    if encoding.charset == Charset::Utf8 {
+        if encoding.bom {
+            bytes.extend_from_slice(UTF8_BOM);
+        }
+        bytes.extend_from_slice(text.as_bytes());
+        return Ok(bytes);
+    }
+
+    let mut line = 1;
+    for c in text.chars() {
+        let byte = match encoding.charset {
+            Charset::Cp1252 => CP1252_HIGH.iter().position(|&h| h == c).map(|p| 0x80 + p as u8),
+            _ => None,
+        };
+        let byte = byte.or_else(|| {
+            let byte = u8::try_from(c).ok()?;
+            let reassigned = encoding.charset == Charset::Cp1252 && is_cp1252_only(byte);
+            (!reassigned).then_some(byte)
+        });
+        let Some(byte) = byte else {
+            return Err(Error::EncodingError(format!(
+                "'{}' on line {} cannot be written as {}",
+                c, line, encoding.charset.label()
+            )));
+        };
+        if c == '\n' {
+            line += 1;
+        }
+        bytes.push(byte);
+    }
+    Ok(bytes)
+}
+
+/// Record the encoding on every module of the file, and warn on the first
+/// if the file is not in the encoding it declares
+pub(crate) fn apply_encoding(modules: &mut [PerlModule], encoding: &SourceEncoding) {
+    for module in modules.iter_mut() {
+        module.encoding = encoding.clone();
+    }
+    let Some(declared) = encoding.declared.filter(|&d| d != encoding.charset) else {
+        return;
+    };
+    if let Some(module) = modules.first_mut() {
+        module.diagnostics.push(Diagnostic {
+            severity: Severity::Warning,
+            kind: DiagnosticKind::EncodingMismatch,
+            message: format!("The file declares {} but was read as {}", declared.label(), encoding.charset.label()),
+            subroutine: None,
+            span: None,
+        });
+    }
+}
+
+/// The charset declared by `use utf8` or `use encoding '...'` before the
+/// end of the code
+fn declared_charset(text: &str) -> Option<Charset> {
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with("__END__") || line.starts_with("__DATA__") {
+            break;
+        }
+        if line.starts_with("use utf8") {
+            return Some(Charset::Utf8);
+        }
+        let Some(name) = line.strip_prefix("use encoding") else {
+            continue;
+        };
+        let name: String = name
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_ascii_lowercase();
+        return match name.as_str() {
+            "utf8" => Some(Charset::Utf8),
+            "latin1" | "iso88591" => Some(Charset::Latin1),
+            "cp1252" | "windows1252" => Some(Charset::Cp1252),
+            _ => None,
+        };
+    }
+    None
+}
+
+fn decode_single_byte(bytes: &[u8], charset: Charset) -> String {
+    bytes
+        .iter()
+        .map(|&b| match (charset, b) {
+            (Charset::Cp1252, 0x80..=0x9F) => CP1252_HIGH[usize::from(b - 0x80)],
+            _ => char::from(b),
+        })
+        .collect()
+}
+
+/// A byte Windows-1252 assigns a different character than Latin-1
+fn is_cp1252_only(byte: u8) -> bool {
+    (0x80..=0x9F).contains(&byte) && !matches!(byte, 0x81 | 0x8D | 0x8F | 0x90 | 0x9D)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_and_round_trip_encodings() {
+        let utf8 = "package Caf\u{e9};\r\nuse utf8;\r\nsub name { 'caf\u{e9}' }\r\n1;\r\n";
+        let mut bom = UTF8_BOM.to_vec();
+        bom.extend_from_slice(utf8.as_bytes());
+        let (text, encoding) = decode_source(&bom);
+        assert_eq!(text, "package Caf\u{e9};\nuse utf8;\nsub name { 'caf\u{e9}' }\n1;\n");
+        assert_eq!((encoding.charset, encoding.bom, encoding.line_ending), (Charset::Utf8, true, LineEnding::CrLf));
+        assert_eq!(encoding.declared, Some(Charset::Utf8));
+        assert_eq!(encode_source(&text, &encoding).unwrap(), bom);
+
+        let latin1 = b"package Legacy;\nmy $name = 'Jos\xe9';\n1;\n";
+        let (text, encoding) = decode_source(latin1);
+        assert_eq!(encoding.charset, Charset::Latin1);
+        assert!(text.contains("Jos\u{e9}"));
+        assert_eq!(encode_source(&text, &encoding).unwrap(), latin1.to_vec());
+        assert!(encode_source("my $price = '\u{20ac}';\n", &encoding).is_err());
+
+        let cp1252 = b"package Quotes;\nmy $q = \x93quoted\x94 . \x80;\n";
+        let (text, encoding) = decode_source(cp1252);
+        assert_eq!(encoding.charset, Charset::Cp1252);
+        assert!(text.contains("\u{201c}quoted\u{201d} . \u{20ac}"));
+        assert_eq!(encode_source(&text, &encoding).unwrap(), cp1252.to_vec());
+    }
+
+    #[test]
+    fn test_declared_encoding_wins() {
+        let (text, encoding) = decode_source(b"use encoding 'latin1';\nmy $s = '\xc3\xa9';\n");
+        assert_eq!(encoding.charset, Charset::Latin1);
+        assert!(text.contains("\u{c3}\u{a9}"));
+
+        // `use utf8` on a file that is not UTF-8
+        let mut modules = vec![PerlModule::default()];
+        let (_, encoding) = decode_source(b"use utf8;\nmy $s = 'caf\xe9';\n");
+        apply_encoding(&mut modules, &encoding);
+        assert_eq!(modules[0].encoding.charset, Charset::Latin1);
+        assert_eq!(modules[0].diagnostics[0].kind, DiagnosticKind::EncodingMismatch);
+    }
+}
@@ -0,0 +1,288 @@
+use crate::domain::models::{ExportTag, Exports};
+use super::lexer::{self, Token, TokenKind};
+use super::syntax::{is_pushed, items, key_name, pairs, statement, variable_name};
+
+/// Modules that provide an `import` method driven by export lists
+pub(crate) const EXPORTERS: &[&str] = &["Exporter", "Exporter::Tiny", "Exporter::Shiny", "Sub::Exporter", "Sub::Exporter::Progressive"];
+
+/// Extract the public interface of the package whose tokens satisfy `in_package`.
+///
+/// Understands `@EXPORT`, `@EXPORT_OK` and `%EXPORT_TAGS` (assigned or
+/// pushed to), `use Exporter::Shiny qw(...)` and `use Sub::Exporter -setup
+/// => ...` with `exports` and `groups`. The exporter is taken from `use`,
+/// `use parent`/`use base` or `@ISA`.
+pub(crate) fn extract_exports(tokens: &[Token], in_package: impl Fn(usize) -> bool) -> Exports {
+    let mut exports = Exports::default();
+
+    for (i, token) in tokens.iter().enumerate() {
+        if !in_package(i) {
+            continue;
+        }
+        let next = tokens.get(i + 1);
+        let assigned = next.is_some_and(|t| t.is_op("="));
+        let pushed = is_pushed(tokens, i);
+
+        match token.kind {
+            TokenKind::Word if token.text == "use" || token.text == "require" => {
+                let Some(module) = next.filter(|t| t.kind == TokenKind::Word) else {
+                    continue;
+                };
+                let args = statement(tokens, i + 2);
+                match module.text {
+                    "parent" | "base" => {
+                        if let Some(exporter) = list_values(args, &exports).into_iter().find(|v| EXPORTERS.contains(&v.as_str())) {
+                            exports.exporter.get_or_insert(exporter);
+                        }
+                    }
+                    "Exporter::Shiny" => {
+                        exports.exporter.get_or_insert(module.text.to_string());
+                        let symbols = list_values(args, &exports);
+                        extend_unique(&mut exports.optional, symbols);
+                    }
+                    "Sub::Exporter" | "Sub::Exporter::Progressive" => {
+                        exports.exporter.get_or_insert(module.text.to_string());
+                        sub_exporter_setup(args, &mut exports);
+                    }
+                    name if EXPORTERS.contains(&name) => {
+                        exports.exporter.get_or_insert(name.to_string());
+                    }
+                    _ => {}
+                }
+            }
+            TokenKind::Variable if assigned || pushed => {
+                let from = if assigned { i + 2 } else { i + 1 };
+                let values = statement(tokens, from);
+                match variable_name(token.text).as_str() {
+                    "@ISA" => {
+                        if let Some(exporter) = list_values(values, &exports).into_iter().find(|v| EXPORTERS.contains(&v.as_str())) {
+                            exports.exporter.get_or_insert(exporter);
+                        }
+                    }
+                    "@EXPORT" => {
+                        let symbols = list_values(values, &exports);
+                        if assigned {
+                            exports.default.clear();
+                        }
+                        extend_unique(&mut exports.default, symbols);
+                    }
+                    "@EXPORT_OK" => {
+                        let symbols = list_values(values, &exports);
+                        if assigned {
+                            exports.optional.clear();
+                        }
+                        extend_unique(&mut exports.optional, symbols);
+                    }
+                    "%EXPORT_TAGS" if assigned => {
+                        exports.tags.clear();
+                        for (name, value) in pairs(values) {
+                            let symbols = list_values(value, &exports);
+                            set_tag(&mut exports, name, symbols);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            // $EXPORT_TAGS{name} = [...]
+            TokenKind::Variable if variable_name(token.text) == "$EXPORT_TAGS" => {
+                let Some(close) = tokens[i + 1..].iter().position(|t| t.kind == TokenKind::CloseBrace).map(|p| i + 1 + p) else {
+                    continue;
+                };
+                let Some(name) = key_name(&tokens[i + 2..close]) else {
+                    continue;
+                };
+                if tokens.get(close + 1).is_some_and(|t| t.is_op("=")) {
+                    let values = statement(tokens, close + 2);
+                    let symbols = list_values(values, &exports);
+                    set_tag(&mut exports, name, symbols);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    exports
+}
+
+/// Parse the argument of `-setup`: either a list of exports or a hash with
+/// `exports` and `groups`
+fn sub_exporter_setup(args: &[Token], exports: &mut Exports) {
+    let Some(setup) = pairs(args).into_iter().find(|(key, _)| *key == "setup").map(|(_, value)| value) else {
+        return;
+    };
+    match setup.first().map(|t| t.kind) {
+        Some(TokenKind::OpenBracket) => extend_unique(&mut exports.optional, plain_names(setup)),
+        Some(TokenKind::OpenBrace) => {
+            for (key, value) in pairs(setup) {
+                match key {
+                    "exports" => extend_unique(&mut exports.optional, plain_names(value)),
+                    "groups" => {
+                        for (group, members) in pairs(value) {
+                            let symbols = plain_names(members);
+                            if group == "default" {
+                                extend_unique(&mut exports.default, symbols);
+                            } else {
+                                set_tag(exports, group, symbols);
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn set_tag(exports: &mut Exports, name: &str, symbols: Vec<String>) {
+    match exports.tags.iter_mut().find(|t| t.name == name) {
+        Some(tag) => tag.symbols = symbols,
+        None => exports.tags.push(ExportTag { name: name.to_string(), symbols }),
+    }
+}
+
+fn extend_unique(list: &mut Vec<String>, values: Vec<String>) {
+    for value in values {
+        if !list.contains(&value) {
+            list.push(value);
+        }
+    }
+}
+
+/// Symbols listed in `tokens`: quote-words, strings and the current contents
+/// of `@EXPORT` and `@EXPORT_OK`. A leading `&` is dropped.
+fn list_values(tokens: &[Token], exports: &Exports) -> Vec<String> {
+    let mut values = Vec::new();
+    for token in tokens {
+        let symbols = match token.kind {
+            TokenKind::QuoteWords => lexer::quote_words(token.text),
+            TokenKind::String | TokenKind::InterpolatedString => {
+                lexer::string_contents(token.text).map(str::to_string).into_iter().collect()
+            }
+            TokenKind::Variable => match variable_name(token.text).as_str() {
+                "@EXPORT" => exports.default.clone(),
+                "@EXPORT_OK" => exports.optional.clone(),
+                _ => Vec::new(),
+            },
+            _ => Vec::new(),
+        };
+        for symbol in symbols {
+            let symbol = symbol.strip_prefix('&').unwrap_or(&symbol).to_string();
+            if !values.contains(&symbol) {
+                values.push(symbol);
+            }
+        }
+    }
+    values
+}
+
+/// Names listed in a Sub::Exporter array, skipping generators (`name => \&gen`)
+fn plain_names(tokens: &[Token]) -> Vec<String> {
+    let inner = match (tokens.first(), tokens.last()) {
+        (Some(first), Some(_)) if first.kind == TokenKind::OpenBracket => &tokens[1..tokens.len() - 1],
+        _ => tokens,
+    };
+    let mut names = Vec::new();
+    for item in items(inner) {
+        let symbols = match item {
+            [token] if token.kind == TokenKind::QuoteWords => lexer::quote_words(token.text),
+            [token] if token.kind == TokenKind::Word => vec![token.text.to_string()],
+            [token] if matches!(token.kind, TokenKind::String | TokenKind::InterpolatedString) => {
+                lexer::string_contents(token.text).map(str::to_string).into_iter().collect()
+            }
+            _ => Vec::new(),
+        };
+        extend_unique(&mut names, symbols);
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use crate::domain::models::ExportTag;
+    use crate::parser::StaticModuleParser;
+
+    fn tag(name: &str, symbols: &[&str]) -> ExportTag {
+        ExportTag { name: name.to_string(), symbols: symbols.iter().map(|s| s.to_string()).collect() }
+    }
+
+    #[test]
+    fn test_exporter_lists_and_tags() {
+        let content = r#"package Text::Util;
+use strict;
+use parent 'Exporter';
+
+our @EXPORT = qw(trim);
+our @EXPORT_OK = (@EXPORT, qw(&pad center), 'wrap');
+our %EXPORT_TAGS = (
+    all   => [@EXPORT_OK],
+    'fmt' => [qw(pad center)],
+);
+push @EXPORT_OK, 'indent';
+$EXPORT_TAGS{space} = ['trim', 'indent'];
+
+sub trim { }
+sub pad { }
+sub center { }
+sub wrap { }
+sub indent { }
+
+1;
+"#;
+        let module = StaticModuleParser::new().parse_source(Path::new("Util.pm"), content).remove(0);
+        let exports = &module.exports;
+
+        assert_eq!(exports.exporter.as_deref(), Some("Exporter"));
+        assert_eq!(exports.default, vec!["trim"]);
+        assert_eq!(exports.optional, vec!["trim", "pad", "center", "wrap", "indent"]);
+        assert_eq!(exports.tags, vec![
+            tag("all", &["trim", "pad", "center", "wrap"]),
+            tag("fmt", &["pad", "center"]),
+            tag("space", &["trim", "indent"]),
+        ]);
+
+        let restricted = exports.restricted_to(&["pad", "indent"]);
+        assert!(restricted.default.is_empty());
+        assert_eq!(restricted.optional, vec!["pad", "indent"]);
+        assert_eq!(restricted.tags, vec![tag("all", &["pad"]), tag("fmt", &["pad"]), tag("space", &["indent"])]);
+    }
+
+    #[test]
+    fn test_sub_exporter_and_exporter_tiny() {
+        let content = r#"package Shapes;
+use Sub::Exporter -setup => {
+    exports => [ qw(area), perimeter => \&_build_perimeter, 'volume' ],
+    groups  => { default => [qw(area)], solid => [ 'volume' ] },
+};
+sub area { }
+sub volume { }
+
+package Colors;
+use Exporter::Shiny qw(red green);
+sub red { }
+sub green { }
+
+package Tiny;
+our @ISA = ('Exporter::Tiny');
+our @EXPORT_OK = qw(tiny);
+sub tiny { }
+
+1;
+"#;
+        let modules = StaticModuleParser::new().parse_source(Path::new("Shapes.pm"), content);
+
+        let shapes = &modules[0].exports;
+        assert_eq!(shapes.exporter.as_deref(), Some("Sub::Exporter"));
+        assert_eq!(shapes.optional, vec!["area", "perimeter", "volume"]);
+        assert_eq!(shapes.default, vec!["area"]);
+        assert_eq!(shapes.tags, vec![tag("solid", &["volume"])]);
+
+        let colors = &modules[1].exports;
+        assert_eq!(colors.exporter.as_deref(), Some("Exporter::Shiny"));
+        assert_eq!(colors.optional, vec!["red", "green"]);
+
+        let tiny = &modules[2].exports;
+        assert_eq!(tiny.exporter.as_deref(), Some("Exporter::Tiny"));
+        assert_eq!(tiny.optional, vec!["tiny"]);
+    }
+}
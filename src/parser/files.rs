@@ -0,0 +1,31 @@
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use crate::error::Error;
+
+const PERL_EXTENSIONS: &[&str] = &["pm", "pl", "t", "cgi"];
+
+/// The `.pm`, `.pl`, `.t` and `.cgi` files under `root`, in sorted order,
+/// skipping hidden directories and build output
+pub async fn perl_files(root: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut files = Vec::new();
+    let mut directories = vec![root.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        let mut entries = fs::read_dir(&directory).await.map_err(Error::IOError)?;
+        let mut paths = Vec::new();
+        while let Some(entry) = entries.next_entry().await.map_err(Error::IOError)? {
+            paths.push(entry.path());
+        }
+        for path in paths {
+            let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+            if path.is_dir() {
+                if !name.starts_with('.') && name != "blib" && name != "target" {
+                    directories.push(path);
+                }
+            } else if path.extension().and_then(|e| e.to_str()).is_some_and(|e| PERL_EXTENSIONS.contains(&e)) {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
@@ -0,0 +1,147 @@
+use crate::domain::models::{Hazard, HazardKind, ResponsibilityCluster, Subroutine};
+use super::lexer::{Token, TokenKind};
+use super::static_parser::matching_brace;
+
+/// How much a cluster's confidence drops for each kind of hazard among its
+/// subroutines
+const HAZARD_PENALTY: f32 = 0.85;
+
+/// Dynamic code among the tokens for which `include` holds: string `eval`,
+/// glob assignments, dynamic method calls and symbolic code references,
+/// `sub AUTOLOAD` and `caller`. Lines are taken from the tokens, so pass
+/// tokens of the whole file to get file line numbers.
+pub(crate) fn find_hazards(content: &str, tokens: &[Token], include: impl Fn(usize) -> bool) -> Vec<Hazard> {
+    let lines: Vec<&str> = content.lines().collect();
+    let mut hazards = Vec::new();
+
+    for (i, token) in tokens.iter().enumerate() {
+        if !include(i) {
+            continue;
+        }
+        let Some(kind) = hazard_at(tokens, i) else {
+            continue;
+        };
+        hazards.push(Hazard {
+            kind,
+            line: token.line,
+            code: lines.get(token.line - 1).map_or("", |l| l.trim()).to_string(),
+            subroutine: None,
+        });
+    }
+    hazards
+}
+
+fn hazard_at(tokens: &[Token], i: usize) -> Option<HazardKind> {
+    let token = &tokens[i];
+    let prev = i.checked_sub(1).map(|p| &tokens[p]);
+    let next = tokens.get(i + 1);
+    let after_arrow = prev.is_some_and(|t| t.is_op("->"));
+    let is_hash_key = next.is_some_and(|t| t.is_op("=>"))
+        || (prev.is_some_and(|t| t.kind == TokenKind::OpenBrace) && next.is_some_and(|t| t.kind == TokenKind::CloseBrace));
+
+    match token.kind {
+        TokenKind::Word if after_arrow || is_hash_key => {
+            (after_arrow && token.text == "can").then_some(HazardKind::DynamicDispatch)
+        }
+        TokenKind::Word if token.text == "eval" => {
+            let block = next.is_some_and(|t| t.kind == TokenKind::OpenBrace);
+            let ends = next.is_none_or(|t| t.is_op(";"));
+            (!block && !ends).then_some(HazardKind::StringEval)
+        }
+        TokenKind::Word if token.text == "caller" => Some(HazardKind::Caller),
+        TokenKind::Word if token.text == "sub" && next.is_some_and(|t| t.is_word("AUTOLOAD")) => Some(HazardKind::Autoload),
+        // `$obj->$method` or `$obj->$method()`
+        TokenKind::Variable if after_arrow && token.text.starts_with('$') => Some(HazardKind::DynamicDispatch),
+        TokenKind::Variable if token.text.starts_with('*') => {
+            next.is_some_and(|t| t.is_op("=")).then_some(HazardKind::GlobAssignment)
+        }
+        TokenKind::Cast if token.text == "*" && next.is_some_and(|t| t.kind == TokenKind::OpenBrace) => {
+            let close = matching_brace(tokens, i + 1)?;
+            tokens.get(close + 1).is_some_and(|t| t.is_op("=")).then_some(HazardKind::GlobAssignment)
+        }
+        // `&{"name"}` or `&{"${class}::$name"}`
+        TokenKind::Cast if token.text == "&" && next.is_some_and(|t| t.kind == TokenKind::OpenBrace) => {
+            let name = tokens.get(i + 2)?;
+            matches!(name.kind, TokenKind::String | TokenKind::InterpolatedString).then_some(HazardKind::DynamicDispatch)
+        }
+        _ => None,
+    }
+}
+
+/// Lower the confidence of every cluster for each kind of hazard found in
+/// its subroutines: code resolved at runtime may not survive the split the
+/// cluster suggests
+pub(crate) fn lower_confidence(subroutines: &[Subroutine], clusters: &mut [ResponsibilityCluster]) {
+    for cluster in clusters {
+        let mut kinds: Vec<HazardKind> = subroutines
+            .iter()
+            .filter(|s| cluster.related_subroutines.contains(&s.name))
+            .flat_map(|s| s.hazards.iter().map(|h| h.kind))
+            .collect();
+        kinds.sort();
+        kinds.dedup();
+        cluster.confidence *= HAZARD_PENALTY.powi(kinds.len() as i32);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use crate::domain::models::HazardKind;
+    use crate::parser::StaticModuleParser;
+    use super::*;
+
+    #[test]
+    fn test_hazards_in_subroutines_and_top_level() {
+        let content = r#"package Dynamic;
+use strict;
+
+for my $name (qw(red green)) {
+    no strict 'refs';
+    *{"Dynamic::$name"} = sub { return $name };
+}
+*colour = \&red;
+
+sub run {
+    my ($self, $method, @args) = @_;
+    return $self->$method(@args) if $self->can($method);
+    my $code = eval "sub { $args[0] }";
+    eval { die "ignored" };
+    return $code;
+}
+
+sub whoami { return (caller(1))[3] }
+sub call { no strict 'refs'; return &{"Dynamic::$_[0]"}() }
+
+our $AUTOLOAD;
+sub AUTOLOAD { my $name = $AUTOLOAD; return }
+
+1;
+"#;
+        let mut module = StaticModuleParser::new().parse_source(Path::new("Dynamic.pm"), content).remove(0);
+        let found: Vec<_> = module.hazards.iter().map(|h| (h.kind, h.line, h.subroutine.as_deref())).collect();
+        assert_eq!(found, vec![
+            (HazardKind::GlobAssignment, 6, None),
+            (HazardKind::GlobAssignment, 8, None),
+            (HazardKind::DynamicDispatch, 12, Some("run")),
+            (HazardKind::DynamicDispatch, 12, Some("run")),
+            (HazardKind::StringEval, 13, Some("run")),
+            (HazardKind::Caller, 18, Some("whoami")),
+            (HazardKind::DynamicDispatch, 19, Some("call")),
+            (HazardKind::Autoload, 22, Some("AUTOLOAD")),
+        ]);
+        assert_eq!(module.hazards[0].code, r#"*{"Dynamic::$name"} = sub { return $name };"#);
+        assert_eq!(module.subroutines[0].hazards.len(), 3);
+
+        module.responsibility_clusters = vec![ResponsibilityCluster {
+            name: "Dispatch".to_string(),
+            description: String::new(),
+            related_subroutines: vec!["run".to_string(), "whoami".to_string()],
+            suggested_module_name: None,
+            confidence: 1.0,
+        }];
+        lower_confidence(&module.subroutines, &mut module.responsibility_clusters);
+        let expected = HAZARD_PENALTY.powi(3);
+        assert!((module.responsibility_clusters[0].confidence - expected).abs() < 1e-6);
+    }
+}
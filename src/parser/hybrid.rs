@@ -0,0 +1,351 @@
+use std::error::Error as _;
+use std::path::Path;
+use async_trait::async_trait;
+use rig::{
+    agent::{Agent, AgentBuilder},
+    completion::{CompletionModel, Prompt},
+};
+use serde::{Deserialize, Serialize};
+use crate::{
+    cancel::Cancellation,
+    domain::{
+        models::{HazardKind, PerlModule, ResponsibilityCluster, Subroutine, SubroutineKind},
+        traits::ModuleParser,
+    },
+    error::Error,
+};
+use super::encoding::{apply_encoding, read_source};
+use super::hazards::lower_confidence;
+use super::StaticModuleParser;
+
+const CLUSTERING_PROMPT: &str = "Identify the responsibilities of this Perl module. Its structure has already been extracted; you are given the package name, its dependencies and a short summary of each subroutine, including which other subroutines of the module it calls. Return ONLY a raw JSON object (no markdown formatting, no code blocks) containing:
+            - responsibility_clusters: Array of objects, each containing:
+                - name: A descriptive name for this responsibility cluster
+                - description: A clear explanation of what this group of subroutines does
+                - related_subroutines: Array of subroutine names that belong to this cluster
+                - suggested_module_name: A suggested name for a new module if this cluster were extracted
+                - confidence: A float between 0.0 and 1.0 indicating confidence in this grouping
+
+            IMPORTANT:
+            1. Only use subroutine names from the list below.
+            2. When identifying responsibility clusters:
+               - Look for groups of subroutines that work together on a specific task
+               - Consider shared dependencies between subroutines
+               - Consider which subroutines call each other
+               - Keep subroutines that modify the same shared variables together
+               - Keep an attribute's builder, trigger and the methods using it together
+               - Instance methods of a class only belong to a separate cluster if they could be extracted into a role
+               - Consider semantic relationships in subroutine names and purposes
+               - Each subroutine can belong to multiple clusters if it serves multiple purposes
+               - Only create clusters when there's a clear cohesive responsibility
+               - Assign high confidence (>0.8) only when the relationship is very clear
+
+            Module summary:
+            {}
+
+            ";
+
+/// Longest leading comment kept in a subroutine summary
+const MAX_COMMENT_LEN: usize = 200;
+
+/// What the model gets to see about a subroutine in hybrid mode
+#[derive(Debug, Serialize)]
+pub(crate) struct SubroutineSummary {
+    pub name: String,
+    pub signature: String,
+    pub summary: String,
+    pub calls: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ModuleSummary<'a> {
+    package_name: &'a str,
+    /// `use`/`require` statements, so runtime and conditional loads stand out
+    dependencies: Vec<String>,
+    subroutines: Vec<SubroutineSummary>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct ClusterResponse {
+    pub responsibility_clusters: Vec<ResponsibilityCluster>,
+}
+
+impl ClusterResponse {
+    /// The clusters with names that are not among `subroutines` (or repeat)
+    /// dropped, confidence clamped to 0.0-1.0, and empty clusters left out
+    pub fn into_clusters(self, subroutines: &[Subroutine]) -> Vec<ResponsibilityCluster> {
+        let mut clusters = self.responsibility_clusters;
+        for cluster in &mut clusters {
+            let mut seen: Vec<String> = Vec::new();
+            cluster.related_subroutines.retain(|name| {
+                let keep = subroutines.iter().any(|s| &s.name == name) && !seen.contains(name);
+                seen.push(name.clone());
+                keep
+            });
+            cluster.confidence = cluster.confidence.clamp(0.0, 1.0);
+        }
+        clusters.retain(|c| !c.related_subroutines.is_empty());
+        clusters
+    }
+}
+
+/// Hybrid implementation of the ModuleParser trait.
+///
+/// Subroutines and dependencies are extracted locally by the
+/// `StaticModuleParser`; the model only receives subroutine names, signatures
+/// and short summaries, and returns the responsibility clusters.
+pub struct HybridModuleParser<M: CompletionModel> {
+    agent: Agent<M>,
+    static_parser: StaticModuleParser,
+    cancellation: Cancellation,
+}
+
+impl<M: CompletionModel> HybridModuleParser<M> {
+    pub fn new(agent_builder: AgentBuilder<M>) -> Self {
+        Self {
+            agent: agent_builder
+                .preamble("You are a Perl code analyzer. You will group the subroutines of a Perl module into cohesive responsibilities.")
+                .build(),
+            static_parser: StaticModuleParser::new(),
+            cancellation: Cancellation::new(),
+        }
+    }
+
+    /// Stop the analysis with `Error::Cancelled` once `cancellation` is cancelled
+    pub fn with_cancellation(mut self, cancellation: Cancellation) -> Self {
+        self.cancellation = cancellation;
+        self
+    }
+
+    async fn identify_clusters(&self, module: &PerlModule) -> Result<Vec<ResponsibilityCluster>, Error> {
+        let summary = ModuleSummary {
+            package_name: &module.name,
+            dependencies: module.dependencies.iter().map(|d| d.declaration()).collect(),
+            subroutines: summarize_subroutines(module),
+        };
+        let prompt = CLUSTERING_PROMPT.replace("{}", &serde_json::to_string_pretty(&summary)?);
+
+        let response = self
+            .cancellation
+            .run(self.agent.prompt(prompt.as_str()))
+            .await?
+            .map_err(|e| {
+                if let Some(source) = e.source() {
+                    eprintln!("{}", source);
+                }
+                Error::AIError(e.to_string())
+            })?;
+
+        let clusters = serde_json::from_str::<ClusterResponse>(&response)
+            .map_err(|e| {
+                eprintln!("Failed to parse response content: {}", response);
+                Error::ParseError(format!("Failed to parse AI response: {}", e))
+            })?;
+
+        Ok(clusters.into_clusters(&module.subroutines))
+    }
+}
+
+#[async_trait]
+impl<M: CompletionModel> ModuleParser for HybridModuleParser<M> {
+    async fn parse_packages(&self, path: impl AsRef<Path> + Send) -> Result<Vec<PerlModule>, Error> {
+        let (content, encoding) = read_source(path.as_ref()).await?;
+
+        let mut modules = self.static_parser.parse_source(path.as_ref(), &content);
+        for module in &mut modules {
+            if !module.subroutines.is_empty() {
+                module.responsibility_clusters = self.identify_clusters(module).await?;
+                lower_confidence(&module.subroutines, &mut module.responsibility_clusters);
+            }
+        }
+        apply_encoding(&mut modules, &encoding);
+
+        Ok(modules)
+    }
+}
+
+/// Summaries of every subroutine in the module, in source order
+pub(crate) fn summarize_subroutines(module: &PerlModule) -> Vec<SubroutineSummary> {
+    module.subroutines
+        .iter()
+        .map(|sub| SubroutineSummary {
+            name: sub.name.clone(),
+            signature: sub.signature.declaration(),
+            summary: summary(module, sub),
+            calls: module.call_graph.callees(&sub.name).into_iter().map(str::to_string).collect(),
+        })
+        .collect()
+}
+
+/// Leading comment (if any), kind, size, exports, external dependencies and shared state of a subroutine
+fn summary(module: &PerlModule, sub: &Subroutine) -> String {
+    let lines: Vec<&str> = module.content.lines().collect();
+    let mut comment = Vec::new();
+    let mut line = sub.line_start.saturating_sub(1);
+    while line > 0 {
+        let text = lines.get(line - 1).map(|l| l.trim()).unwrap_or_default();
+        match text.strip_prefix('#') {
+            Some(text) => comment.push(text.trim_start_matches('#').trim()),
+            None => break,
+        }
+        line -= 1;
+    }
+    comment.reverse();
+
+    let mut parts = Vec::new();
+    let comment = comment.join(" ");
+    if !comment.is_empty() {
+        let mut short: String = comment.chars().take(MAX_COMMENT_LEN).collect();
+        if short.len() < comment.len() {
+            short.push_str("...");
+        }
+        parts.push(short);
+    }
+    match sub.kind {
+        SubroutineKind::InstanceMethod => parts.push("instance method".to_string()),
+        SubroutineKind::ClassMethod => parts.push("class method".to_string()),
+        SubroutineKind::Function => {}
+    }
+    parts.push(format!("{} lines", sub.line_end.saturating_sub(sub.line_start) + 1));
+    if module.exports.default.contains(&sub.name) {
+        parts.push("exported by default".to_string());
+    } else if module.exports.is_exported(&sub.name) {
+        parts.push("exported on request".to_string());
+    }
+    if !sub.dependencies.is_empty() {
+        parts.push(format!("uses {}", sub.dependencies.join(", ")));
+    }
+    if !sub.reads.is_empty() {
+        parts.push(format!("reads {}", sub.reads.join(", ")));
+    }
+    if !sub.writes.is_empty() {
+        parts.push(format!("writes {}", sub.writes.join(", ")));
+    }
+    if module.data_section.as_ref().is_some_and(|d| d.used_by.contains(&sub.name)) {
+        parts.push("reads DATA".to_string());
+    }
+    let attributes: Vec<&str> = module.attributes
+        .iter()
+        .filter(|a| a.linked_subroutines().contains(&sub.name.as_str()))
+        .map(|a| a.name.as_str())
+        .collect();
+    if !attributes.is_empty() {
+        parts.push(format!("attributes {}", attributes.join(", ")));
+    }
+    let mut kinds: Vec<HazardKind> = sub.hazards.iter().map(|h| h.kind).collect();
+    kinds.sort_unstable();
+    kinds.dedup();
+    if !kinds.is_empty() {
+        let hazards: Vec<&str> = kinds.iter().map(|k| k.label()).collect();
+        parts.push(format!("hazards {}", hazards.join(", ")));
+    }
+    parts.join("; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use rig::providers::azure::Client;
+    use serde_json::json;
+    use tempfile::NamedTempFile;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const TEST_MODULE_CONTENT: &str = r#"package Greeter;
+use strict;
+
+# Build a greeting for the given name
+sub greet {
+    my ($name) = @_;
+    return "Hello, $name";
+}
+
+sub shout {
+    my $text = shift;
+    return uc $text;
+}
+
+1;
+"#;
+
+    #[test]
+    fn test_summaries_do_not_contain_bodies() {
+        let module = StaticModuleParser::new().parse_source(Path::new("Greeter.pm"), TEST_MODULE_CONTENT).remove(0);
+        let summaries = summarize_subroutines(&module);
+
+        assert_eq!(summaries[0].name, "greet");
+        assert_eq!(summaries[0].signature, "($name)");
+        assert_eq!(summaries[0].summary, "Build a greeting for the given name; 4 lines");
+        assert_eq!(summaries[1].signature, "($text)");
+        assert_eq!(summaries[1].summary, "4 lines");
+        assert!(summaries[0].calls.is_empty());
+
+        let content = "package Runner;\nsub run {\n    my ($self, $m) = @_;\n    $self->$m();\n    eval \"require $m\";\n    return $self->$m();\n}\n1;\n";
+        let module = StaticModuleParser::new().parse_source(Path::new("Runner.pm"), content).remove(0);
+        let summary = &summarize_subroutines(&module)[0].summary;
+        assert!(summary.ends_with("; hazards string eval, dynamic dispatch"), "{}", summary);
+    }
+
+    #[tokio::test]
+    async fn test_hybrid_only_asks_for_clusters() -> Result<(), Box<dyn std::error::Error>> {
+        let mock_server = MockServer::start().await;
+
+        let mock_response = ClusterResponse {
+            responsibility_clusters: vec![ResponsibilityCluster {
+                name: "Greeting".to_string(),
+                description: "Formats greetings".to_string(),
+                related_subroutines: vec!["greet".to_string(), "shout".to_string(), "whisper".to_string()],
+                suggested_module_name: Some("Greeter::Format".to_string()),
+                confidence: 1.3,
+            }],
+        };
+
+        Mock::given(method("POST"))
+            .and(path("/openai/deployments/gpt-4o-2024-08-06/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "test-id",
+                "object": "chat.completion",
+                "created": 1234567890,
+                "model": "gpt-4o-2024-08-06",
+                "choices": [{
+                    "index": 0,
+                    "message": {
+                        "role": "assistant",
+                        "content": serde_json::to_string(&mock_response)?
+                    },
+                    "finish_reason": "stop"
+                }],
+                "usage": {
+                    "prompt_tokens": 100,
+                    "completion_tokens": 50,
+                    "total_tokens": 150
+                }
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mut temp_file = NamedTempFile::new()?;
+        write!(temp_file, "{}", TEST_MODULE_CONTENT)?;
+
+        let client = Client::from_api_key("test-key", "test-version", mock_server.uri().as_str());
+        let parser = HybridModuleParser::new(AgentBuilder::new(client.completion_model("gpt-4o-2024-08-06")));
+        let module = parser.parse_module(temp_file.path()).await?;
+
+        assert_eq!(module.name, "Greeter");
+        assert_eq!(module.subroutines.len(), 2);
+        assert_eq!(module.subroutines[0].line_start, 5);
+        assert_eq!(module.responsibility_clusters.len(), 1);
+        // Subroutines the model made up are dropped, and confidence is clamped
+        assert_eq!(module.responsibility_clusters[0].related_subroutines, vec!["greet", "shout"]);
+        assert_eq!(module.responsibility_clusters[0].confidence, 1.0);
+
+        let requests = mock_server.received_requests().await.unwrap_or_default();
+        let body = String::from_utf8_lossy(&requests[0].body);
+        assert!(body.contains("greet"));
+        assert!(!body.contains("Hello, $name"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,876 @@
+//! A small, lossless tokenizer for Perl source.
+//!
+//! Perl cannot be fully tokenized without running it, so the lexer relies on
+//! the usual heuristics: it tracks whether the next token is expected to be a
+//! term or an operator to tell regexes from division, hashes from modulus and
+//! heredocs from left shifts. Every token carries its byte range in the
+//! source, so slicing the original text with it is always exact.
+
+use crate::domain::models::{FileId, Span};
+
+/// The kind of a lexical token
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    /// Identifiers, keywords and package names (`sub`, `Foo::Bar`)
+    Word,
+    /// Sigiled variables and code references (`$x`, `@ARGV`, `%h`, `&foo`, `*glob`)
+    Variable,
+    /// A bare sigil used as a dereference (`@{...}`, `$$ref`)
+    Cast,
+    /// Numeric literal
+    Number,
+    /// Non-interpolating string (`'...'`, `q{...}`)
+    String,
+    /// Interpolating string (`"..."`, `qq{...}`, `` `...` ``)
+    InterpolatedString,
+    /// Quote-words list (`qw(...)`)
+    QuoteWords,
+    /// Match or compiled regex (`/.../`, `m{...}`, `qr/.../`)
+    Regex,
+    /// Substitution or transliteration (`s/.../.../`, `tr/.../.../`)
+    Substitution,
+    /// Heredoc introducer (`<<"EOF"`)
+    Heredoc,
+    /// Heredoc body, including the terminator line
+    HeredocBody,
+    /// Readline or glob operator (`<FH>`, `<$fh>`, `<*.txt>`)
+    Readline,
+    /// `#` comment up to the end of the line
+    Comment,
+    /// POD block from `=command` up to and including `=cut`
+    Pod,
+    /// Everything after `__END__` or `__DATA__`
+    DataSection,
+    /// Punctuation and operators (`->`, `=>`, `;`, `=`, ...)
+    Operator,
+    OpenBrace,
+    CloseBrace,
+    OpenParen,
+    CloseParen,
+    OpenBracket,
+    CloseBracket,
+    /// Spaces, tabs and newlines
+    Whitespace,
+}
+
+/// A token with its exact location in the source
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token<'a> {
+    pub kind: TokenKind,
+    pub text: &'a str,
+    /// Byte offset of the first character
+    pub start: usize,
+    /// Byte offset one past the last character
+    pub end: usize,
+    /// 1-based line of the first character
+    pub line: usize,
+}
+
+impl Token<'_> {
+    /// Whether the token carries meaning for the parser (not whitespace, comments or POD)
+    pub fn is_significant(&self) -> bool {
+        !matches!(
+            self.kind,
+            TokenKind::Whitespace | TokenKind::Comment | TokenKind::Pod | TokenKind::HeredocBody
+        )
+    }
+
+    pub fn is_word(&self, word: &str) -> bool {
+        self.kind == TokenKind::Word && self.text == word
+    }
+
+    pub fn is_op(&self, op: &str) -> bool {
+        self.kind == TokenKind::Operator && self.text == op
+    }
+}
+
+/// Maps byte offsets to 1-based line and column numbers
+#[derive(Debug, Clone)]
+pub struct LineIndex {
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(source: &str) -> Self {
+        let mut line_starts = vec![0];
+        line_starts.extend(source.match_indices('\n').map(|(i, _)| i + 1));
+        Self { line_starts }
+    }
+
+    /// 1-based line containing the given byte offset
+    pub fn line(&self, offset: usize) -> usize {
+        match self.line_starts.binary_search(&offset) {
+            Ok(i) => i + 1,
+            Err(i) => i,
+        }
+    }
+
+    /// 1-based line and column (in characters) of the given byte offset
+    pub fn line_col(&self, source: &str, offset: usize) -> (usize, usize) {
+        let line = self.line(offset);
+        let line_start = self.line_starts[line - 1];
+        let column = source[line_start..offset].chars().count() + 1;
+        (line, column)
+    }
+
+    /// Byte offset of the start of the given 1-based line
+    pub fn line_start(&self, line: usize) -> Option<usize> {
+        self.line_starts.get(line.checked_sub(1)?).copied()
+    }
+
+    /// The span of `source[start..end]` in the file `file`
+    pub fn span(&self, source: &str, file: FileId, start: usize, end: usize) -> Span {
+        let (line_start, column_start) = self.line_col(source, start);
+        let (line_end, column_end) = self.line_col(source, end);
+        Span { file, start, end, line_start, column_start, line_end, column_end }
+    }
+}
+
+/// Words after which a term (not an operator) is expected
+const TERM_EXPECTING_WORDS: &[&str] = &[
+    "and", "or", "not", "xor", "if", "unless", "while", "until", "elsif", "foreach", "for",
+    "return", "split", "grep", "map", "join", "push", "unshift", "print", "printf", "say", "die",
+    "warn", "defined", "ref", "scalar", "keys", "values", "each", "delete", "exists", "my", "our",
+    "local", "state", "lc", "uc", "lcfirst", "ucfirst", "x", "lt", "gt", "le", "ge", "eq", "ne",
+    "cmp", "when", "wantarray", "sort", "reverse", "undef", "eval", "do", "open", "close",
+    "chomp", "chop", "chdir", "splice", "bless", "sprintf", "length", "exit", "croak", "confess",
+    "carp", "cluck", "qw",
+];
+
+/// Words whose following `{` opens a block rather than an anonymous hash
+const BLOCK_WORDS: &[&str] = &[
+    "sub", "do", "eval", "map", "grep", "sort", "else", "BEGIN", "END", "INIT", "CHECK",
+    "UNITCHECK", "try", "catch", "finally", "default", "package", "continue", "defer",
+];
+
+const QUOTE_OPERATORS: &[&str] = &["q", "qq", "qw", "qr", "m", "s", "tr", "y"];
+
+/// Whether the `{` at the top of the brace stack opened a value (subscript,
+/// anonymous hash or dereference block) or a code block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BraceRole {
+    Value,
+    Block,
+}
+
+/// Tokenize Perl source. The returned tokens cover the whole input without gaps.
+pub fn tokenize(source: &str) -> Vec<Token<'_>> {
+    Lexer::new(source).run()
+}
+
+/// Tokenize and keep only significant tokens
+pub fn significant_tokens(source: &str) -> Vec<Token<'_>> {
+    tokenize(source).into_iter().filter(|t| t.is_significant()).collect()
+}
+
+struct Lexer<'a> {
+    src: &'a str,
+    bytes: &'a [u8],
+    pos: usize,
+    line: usize,
+    tokens: Vec<Token<'a>>,
+    pending_heredocs: Vec<(String, bool)>,
+    braces: Vec<BraceRole>,
+    last_closed_brace: Option<BraceRole>,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(src: &'a str) -> Self {
+        Self {
+            src,
+            bytes: src.as_bytes(),
+            pos: 0,
+            line: 1,
+            tokens: Vec::new(),
+            pending_heredocs: Vec::new(),
+            braces: Vec::new(),
+            last_closed_brace: None,
+        }
+    }
+
+    fn run(mut self) -> Vec<Token<'a>> {
+        while self.pos < self.bytes.len() {
+            let start = self.pos;
+            let kind = self.next_kind();
+            // Never loop without progress, even on unexpected input
+            if self.pos == start {
+                self.pos += self.char_len(start);
+            }
+            self.push(kind, start);
+        }
+        self.tokens
+    }
+
+    fn push(&mut self, kind: TokenKind, start: usize) {
+        let text = &self.src[start..self.pos];
+        self.tokens.push(Token { kind, text, start, end: self.pos, line: self.line });
+        self.line += text.matches('\n').count();
+    }
+
+    fn peek(&self, offset: usize) -> Option<u8> {
+        self.bytes.get(self.pos + offset).copied()
+    }
+
+    fn char_len(&self, at: usize) -> usize {
+        self.src[at..].chars().next().map(|c| c.len_utf8()).unwrap_or(1)
+    }
+
+    fn at_line_start(&self) -> bool {
+        self.pos == 0 || self.bytes[self.pos - 1] == b'\n'
+    }
+
+    fn prev_significant(&self) -> Option<&Token<'a>> {
+        self.tokens.iter().rev().find(|t| t.is_significant())
+    }
+
+    fn prev_significant_before(&self, n: usize) -> Option<&Token<'a>> {
+        self.tokens.iter().rev().filter(|t| t.is_significant()).nth(n)
+    }
+
+    /// Whether the parser is positioned where a term (value) may begin
+    fn expect_term(&self) -> bool {
+        let Some(prev) = self.prev_significant() else {
+            return true;
+        };
+        match prev.kind {
+            TokenKind::Variable
+            | TokenKind::Number
+            | TokenKind::String
+            | TokenKind::InterpolatedString
+            | TokenKind::QuoteWords
+            | TokenKind::Regex
+            | TokenKind::Substitution
+            | TokenKind::Heredoc
+            | TokenKind::Readline
+            | TokenKind::CloseParen
+            | TokenKind::CloseBracket => false,
+            TokenKind::CloseBrace => self.last_closed_brace == Some(BraceRole::Block),
+            TokenKind::Word => {
+                let after_arrow = self
+                    .prev_significant_before(1)
+                    .is_some_and(|t| t.is_op("->"));
+                !after_arrow && TERM_EXPECTING_WORDS.contains(&prev.text)
+            }
+            _ => true,
+        }
+    }
+
+    /// Whether a `{` at the current position opens a code block
+    fn brace_opens_block(&self) -> bool {
+        let Some(prev) = self.prev_significant() else {
+            return true;
+        };
+        match prev.kind {
+            TokenKind::Operator => prev.text == ";",
+            TokenKind::OpenBrace | TokenKind::CloseParen => true,
+            TokenKind::CloseBrace => self.last_closed_brace == Some(BraceRole::Block),
+            TokenKind::Word => {
+                let after_arrow = self
+                    .prev_significant_before(1)
+                    .is_some_and(|t| t.is_op("->"));
+                !after_arrow
+                    && (BLOCK_WORDS.contains(&prev.text)
+                        || !TERM_EXPECTING_WORDS.contains(&prev.text))
+            }
+            _ => false,
+        }
+    }
+
+    fn next_kind(&mut self) -> TokenKind {
+        let c = self.bytes[self.pos];
+
+        if c == b'\n' {
+            self.pos += 1;
+            if !self.pending_heredocs.is_empty() {
+                // The newline is emitted on its own; heredoc bodies follow it
+                return TokenKind::Whitespace;
+            }
+            self.skip_inline_whitespace();
+            return TokenKind::Whitespace;
+        }
+
+        if !self.pending_heredocs.is_empty() && self.at_line_start() {
+            self.lex_heredoc_body();
+            return TokenKind::HeredocBody;
+        }
+
+        if c == b' ' || c == b'\t' || c == b'\r' || c == b'\x0c' {
+            self.skip_inline_whitespace();
+            return TokenKind::Whitespace;
+        }
+
+        if c == b'=' && self.at_line_start() && self.peek(1).is_some_and(|b| b.is_ascii_alphabetic()) {
+            self.lex_pod();
+            return TokenKind::Pod;
+        }
+
+        if c == b'#' {
+            self.skip_to_line_end();
+            return TokenKind::Comment;
+        }
+
+        if c.is_ascii_alphabetic() || c == b'_' || c >= 0x80 {
+            return self.lex_word();
+        }
+
+        if c.is_ascii_digit() {
+            self.lex_number();
+            return TokenKind::Number;
+        }
+
+        match c {
+            b'$' | b'@' => self.lex_variable(),
+            b'%' | b'&' | b'*' if self.expect_term() => self.lex_variable(),
+            b'\'' => {
+                self.pos += 1;
+                self.skip_delimited(b'\'');
+                TokenKind::String
+            }
+            b'"' | b'`' => {
+                self.pos += 1;
+                self.skip_delimited(c);
+                TokenKind::InterpolatedString
+            }
+            b'/' if self.expect_term() => {
+                self.pos += 1;
+                self.skip_delimited(b'/');
+                self.skip_modifiers();
+                TokenKind::Regex
+            }
+            b'<' => self.lex_angle(),
+            b'{' => {
+                let role = if self.brace_opens_block() { BraceRole::Block } else { BraceRole::Value };
+                self.braces.push(role);
+                self.pos += 1;
+                TokenKind::OpenBrace
+            }
+            b'}' => {
+                self.last_closed_brace = Some(self.braces.pop().unwrap_or(BraceRole::Block));
+                self.pos += 1;
+                TokenKind::CloseBrace
+            }
+            b'(' => {
+                self.pos += 1;
+                TokenKind::OpenParen
+            }
+            b')' => {
+                self.pos += 1;
+                TokenKind::CloseParen
+            }
+            b'[' => {
+                self.pos += 1;
+                TokenKind::OpenBracket
+            }
+            b']' => {
+                self.pos += 1;
+                TokenKind::CloseBracket
+            }
+            b'-' if self.expect_term()
+                && self.peek(1).is_some_and(|b| b.is_ascii_alphabetic())
+                && !self.peek(2).is_some_and(|b| b.is_ascii_alphanumeric() || b == b'_') =>
+            {
+                // File test operator such as `-e $file` or `-d`
+                self.pos += 2;
+                TokenKind::Operator
+            }
+            _ => {
+                self.lex_operator();
+                TokenKind::Operator
+            }
+        }
+    }
+
+    fn skip_inline_whitespace(&mut self) {
+        while let Some(b) = self.peek(0) {
+            let newline = b == b'\n' && self.pending_heredocs.is_empty();
+            if b == b' ' || b == b'\t' || b == b'\r' || b == b'\x0c' || newline {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn skip_to_line_end(&mut self) {
+        while let Some(b) = self.peek(0) {
+            if b == b'\n' {
+                break;
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn skip_line(&mut self) {
+        self.skip_to_line_end();
+        if self.peek(0) == Some(b'\n') {
+            self.pos += 1;
+        }
+    }
+
+    fn lex_pod(&mut self) {
+        loop {
+            let line_start = self.pos;
+            self.skip_line();
+            if self.src[line_start..self.pos].starts_with("=cut") || self.pos >= self.bytes.len() {
+                break;
+            }
+        }
+    }
+
+    fn lex_heredoc_body(&mut self) {
+        let (terminator, indented) = self.pending_heredocs.remove(0);
+        while self.pos < self.bytes.len() {
+            let line_start = self.pos;
+            self.skip_line();
+            let line = self.src[line_start..self.pos].trim_end_matches(['\n', '\r']);
+            let line = if indented { line.trim_start() } else { line };
+            if line == terminator {
+                break;
+            }
+        }
+    }
+
+    fn lex_word(&mut self) -> TokenKind {
+        let start = self.pos;
+        self.take_identifier();
+        let word = &self.src[start..self.pos];
+
+        if (word == "__END__" || word == "__DATA__") && (start == 0 || self.bytes[start - 1] == b'\n') {
+            self.pos = self.bytes.len();
+            return TokenKind::DataSection;
+        }
+
+        if QUOTE_OPERATORS.contains(&word) && self.is_quote_operator() {
+            return self.lex_quote_like(word);
+        }
+
+        // A `::` directly after a word is part of a package name (`Foo::`)
+        TokenKind::Word
+    }
+
+    /// Consume an identifier, including package separators (`Foo::Bar::baz`)
+    fn take_identifier(&mut self) {
+        loop {
+            while let Some(b) = self.peek(0) {
+                if b.is_ascii_alphanumeric() || b == b'_' || b >= 0x80 {
+                    self.pos += 1;
+                } else {
+                    break;
+                }
+            }
+            if self.peek(0) == Some(b':') && self.peek(1) == Some(b':') {
+                self.pos += 2;
+                continue;
+            }
+            break;
+        }
+    }
+
+    /// Decide whether a just-read `q`/`s`/`tr`/... word starts a quote-like construct
+    fn is_quote_operator(&self) -> bool {
+        if let Some(prev) = self.prev_significant() {
+            if prev.is_op("->") || prev.is_word("sub") {
+                return false;
+            }
+        }
+        let mut i = self.pos;
+        while i < self.bytes.len() && (self.bytes[i] == b' ' || self.bytes[i] == b'\t') {
+            i += 1;
+        }
+        let Some(&next) = self.bytes.get(i) else {
+            return false;
+        };
+        let followed_by_space = i > self.pos;
+        match next {
+            b'=' => self.bytes.get(i + 1) != Some(&b'>') && !followed_by_space,
+            b',' | b';' | b')' | b']' | b'}' | b'\n' | b'\r' => false,
+            b'#' => !followed_by_space,
+            b if b.is_ascii_alphanumeric() || b == b'_' => false,
+            _ => true,
+        }
+    }
+
+    fn lex_quote_like(&mut self, op: &str) -> TokenKind {
+        self.skip_whitespace();
+        let Some(open) = self.peek(0) else {
+            return TokenKind::Word;
+        };
+        self.pos += 1;
+        let close = closing_delimiter(open);
+        self.skip_delimited_pair(open, close);
+
+        if matches!(op, "s" | "tr" | "y") {
+            if open != close {
+                // s{...}{...} form: the replacement has its own delimiters
+                self.skip_whitespace();
+                if let Some(open2) = self.peek(0) {
+                    self.pos += 1;
+                    self.skip_delimited_pair(open2, closing_delimiter(open2));
+                }
+            } else {
+                self.skip_delimited(close);
+            }
+        }
+        if matches!(op, "m" | "qr" | "s" | "tr" | "y") {
+            self.skip_modifiers();
+        }
+
+        match op {
+            "q" => TokenKind::String,
+            "qq" => TokenKind::InterpolatedString,
+            "qw" => TokenKind::QuoteWords,
+            "m" | "qr" => TokenKind::Regex,
+            _ => TokenKind::Substitution,
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b) = self.peek(0) {
+            if b.is_ascii_whitespace() {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn skip_modifiers(&mut self) {
+        while self.peek(0).is_some_and(|b| b.is_ascii_alphabetic()) {
+            self.pos += 1;
+        }
+    }
+
+    /// Skip to just after the closing delimiter, honouring backslash escapes
+    fn skip_delimited(&mut self, close: u8) {
+        while let Some(b) = self.peek(0) {
+            self.pos += 1;
+            if b == b'\\' {
+                self.pos = (self.pos + 1).min(self.bytes.len());
+            } else if b == close {
+                return;
+            }
+        }
+    }
+
+    /// Like `skip_delimited`, but nests bracketing delimiters
+    fn skip_delimited_pair(&mut self, open: u8, close: u8) {
+        if open == close {
+            self.skip_delimited(close);
+            return;
+        }
+        let mut depth = 1;
+        while let Some(b) = self.peek(0) {
+            self.pos += 1;
+            if b == b'\\' {
+                self.pos = (self.pos + 1).min(self.bytes.len());
+            } else if b == open {
+                depth += 1;
+            } else if b == close {
+                depth -= 1;
+                if depth == 0 {
+                    return;
+                }
+            }
+        }
+    }
+
+    fn lex_number(&mut self) {
+        if self.peek(0) == Some(b'0') && matches!(self.peek(1), Some(b'x' | b'X' | b'b' | b'B')) {
+            self.pos += 2;
+            while self.peek(0).is_some_and(|b| b.is_ascii_hexdigit() || b == b'_') {
+                self.pos += 1;
+            }
+            return;
+        }
+        while self.peek(0).is_some_and(|b| b.is_ascii_digit() || b == b'_') {
+            self.pos += 1;
+        }
+        if self.peek(0) == Some(b'.') && self.peek(1).is_some_and(|b| b.is_ascii_digit()) {
+            self.pos += 1;
+            while self.peek(0).is_some_and(|b| b.is_ascii_digit() || b == b'_') {
+                self.pos += 1;
+            }
+            // Version strings such as 5.010.001
+            while self.peek(0) == Some(b'.') && self.peek(1).is_some_and(|b| b.is_ascii_digit()) {
+                self.pos += 1;
+                while self.peek(0).is_some_and(|b| b.is_ascii_digit()) {
+                    self.pos += 1;
+                }
+            }
+        }
+        if matches!(self.peek(0), Some(b'e' | b'E'))
+            && (self.peek(1).is_some_and(|b| b.is_ascii_digit())
+                || (matches!(self.peek(1), Some(b'+' | b'-')) && self.peek(2).is_some_and(|b| b.is_ascii_digit())))
+        {
+            self.pos += 2;
+            while self.peek(0).is_some_and(|b| b.is_ascii_digit()) {
+                self.pos += 1;
+            }
+        }
+    }
+
+    fn lex_variable(&mut self) -> TokenKind {
+        let sigil = self.bytes[self.pos];
+        self.pos += 1;
+
+        // Postfix dereference: ->@*, ->%*, ->$#*; `->$method` is a dynamic method call
+        let method_name = sigil == b'$' && self.peek(0).is_some_and(|b| b.is_ascii_alphabetic() || b == b'_');
+        if !method_name && self.prev_significant().is_some_and(|t| t.is_op("->")) {
+            if self.peek(0) == Some(b'#') {
+                self.pos += 1;
+            }
+            if self.peek(0) == Some(b'*') {
+                self.pos += 1;
+            }
+            return TokenKind::Operator;
+        }
+
+        if sigil == b'&' && self.peek(0) == Some(b'&') {
+            self.pos += 1;
+            return TokenKind::Operator;
+        }
+        if sigil == b'*' && self.peek(0) == Some(b'*') {
+            self.pos += 1;
+            return TokenKind::Operator;
+        }
+
+        // $#array and $#{expr} / $#$ref
+        if sigil == b'$' && self.peek(0) == Some(b'#') {
+            match self.peek(1) {
+                Some(b'{') | Some(b'$') => {
+                    self.pos += 1;
+                    return TokenKind::Cast;
+                }
+                Some(b) if b.is_ascii_alphabetic() || b == b'_' => {
+                    self.pos += 1;
+                    self.take_identifier();
+                    return TokenKind::Variable;
+                }
+                _ => {}
+            }
+        }
+
+        match self.peek(0) {
+            Some(b) if b.is_ascii_alphabetic() || b == b'_' => {
+                self.take_identifier();
+                TokenKind::Variable
+            }
+            Some(b':') if self.peek(1) == Some(b':') => {
+                // $::foo is $main::foo
+                self.take_identifier();
+                TokenKind::Variable
+            }
+            Some(b'{') => TokenKind::Cast,
+            Some(b'$') => {
+                // $$ alone is the process id; $$name and @$ref are dereferences
+                let deref = self
+                    .peek(1)
+                    .is_some_and(|b| b.is_ascii_alphabetic() || b == b'_' || b == b'{' || b == b'$' || b == b':');
+                if sigil == b'$' && !deref {
+                    self.pos += 1;
+                    TokenKind::Variable
+                } else {
+                    TokenKind::Cast
+                }
+            }
+            Some(b'^') if sigil == b'$' && self.peek(1).is_some_and(|b| b.is_ascii_uppercase()) => {
+                self.pos += 2;
+                TokenKind::Variable
+            }
+            Some(b) if b.is_ascii_digit() && sigil == b'$' => {
+                while self.peek(0).is_some_and(|b| b.is_ascii_digit()) {
+                    self.pos += 1;
+                }
+                TokenKind::Variable
+            }
+            Some(b) if sigil == b'$' && b"&`'+!@/\\,;.0<>[]_?|~=-\"".contains(&b) => {
+                self.pos += 1;
+                TokenKind::Variable
+            }
+            Some(b) if sigil == b'@' && (b == b'_' || b == b'-' || b == b'+') => {
+                self.pos += 1;
+                TokenKind::Variable
+            }
+            Some(b) if sigil == b'%' && (b == b'-' || b == b'+' || b == b'!') => {
+                self.pos += 1;
+                TokenKind::Variable
+            }
+            _ => {
+                if sigil == b'@' || sigil == b'$' {
+                    TokenKind::Variable
+                } else {
+                    TokenKind::Operator
+                }
+            }
+        }
+    }
+
+    fn lex_angle(&mut self) -> TokenKind {
+        // Heredoc: <<"EOF", <<'EOF', <<~EOF, <<EOF
+        if self.peek(1) == Some(b'<') {
+            let mut i = self.pos + 2;
+            let indented = self.bytes.get(i) == Some(&b'~');
+            if indented {
+                i += 1;
+            }
+            match self.bytes.get(i) {
+                Some(&q @ (b'"' | b'\'' | b'`')) => {
+                    let body_start = i + 1;
+                    if let Some(len) = self.src[body_start..].find(q as char) {
+                        let terminator = self.src[body_start..body_start + len].to_string();
+                        self.pos = body_start + len + 1;
+                        self.pending_heredocs.push((terminator, indented));
+                        return TokenKind::Heredoc;
+                    }
+                }
+                Some(b) if b.is_ascii_alphabetic() || *b == b'_' => {
+                    self.pos = i;
+                    let start = self.pos;
+                    while self.peek(0).is_some_and(|b| b.is_ascii_alphanumeric() || b == b'_') {
+                        self.pos += 1;
+                    }
+                    let terminator = self.src[start..self.pos].to_string();
+                    self.pending_heredocs.push((terminator, indented));
+                    return TokenKind::Heredoc;
+                }
+                _ => {}
+            }
+        }
+
+        // Readline / glob: <FH>, <$fh>, <STDIN>, <>
+        if self.expect_term() {
+            let rest = &self.src[self.pos + 1..];
+            if let Some(len) = rest.find('>') {
+                let inner = &rest[..len];
+                let plausible = !inner.contains('\n')
+                    && !inner.contains(' ')
+                    && !inner.contains('<')
+                    && !inner.contains(';');
+                if plausible {
+                    self.pos += len + 2;
+                    return TokenKind::Readline;
+                }
+            }
+        }
+
+        self.lex_operator();
+        TokenKind::Operator
+    }
+
+    fn lex_operator(&mut self) {
+        const OPERATORS: &[&str] = &[
+            "<=>", "**=", "||=", "&&=", "//=", "...", "<<=", ">>=", "->", "=>", "::", "==", "!=",
+            "<=", ">=", "=~", "!~", "&&", "||", "//", "++", "--", "**", "+=", "-=", "*=", "/=",
+            ".=", "%=", "x=", "|=", "&=", "^=", "<<", ">>", "..",
+        ];
+        let rest = &self.src[self.pos..];
+        for op in OPERATORS {
+            if rest.starts_with(op) {
+                self.pos += op.len();
+                return;
+            }
+        }
+        self.pos += self.char_len(self.pos);
+    }
+}
+
+fn closing_delimiter(open: u8) -> u8 {
+    match open {
+        b'(' => b')',
+        b'[' => b']',
+        b'{' => b'}',
+        b'<' => b'>',
+        other => other,
+    }
+}
+
+/// Extract the individual words of a `qw(...)` token
+pub fn quote_words(text: &str) -> Vec<String> {
+    let inner = text.trim_start_matches("qw").trim_start();
+    if inner.len() < 2 {
+        return Vec::new();
+    }
+    inner[1..inner.len() - 1]
+        .split_whitespace()
+        .map(str::to_string)
+        .collect()
+}
+
+/// The literal contents of a simple string token (`'foo'`, `"foo"`, `q{foo}`)
+pub fn string_contents(text: &str) -> Option<&str> {
+    let inner = text
+        .strip_prefix("qq")
+        .or_else(|| text.strip_prefix('q'))
+        .map(str::trim_start)
+        .unwrap_or(text);
+    if inner.len() < 2 {
+        return None;
+    }
+    Some(&inner[1..inner.len() - 1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(src: &str) -> Vec<(TokenKind, &str)> {
+        significant_tokens(src).into_iter().map(|t| (t.kind, t.text)).collect()
+    }
+
+    #[test]
+    fn test_tokens_cover_source() {
+        let src = "sub foo {\n    my ($x) = @_; # comment\n    return $x / 2;\n}\n";
+        let joined: String = tokenize(src).iter().map(|t| t.text).collect();
+        assert_eq!(joined, src);
+    }
+
+    #[test]
+    fn test_regex_versus_division() {
+        let toks = kinds("my @parts = split /,/, $line; my $half = $total / 2;");
+        assert!(toks.contains(&(TokenKind::Regex, "/,/")));
+        assert!(toks.contains(&(TokenKind::Operator, "/")));
+
+        let toks = kinds("my $avg = $self->{total} / $count;");
+        assert!(toks.contains(&(TokenKind::Operator, "/")));
+    }
+
+    #[test]
+    fn test_braces_inside_strings_and_regexes() {
+        let src = "sub f { my $s = \"}\"; $s =~ s{\\}}{x}g; return q{ {x} };\n}\nsub g { 1 }";
+        let toks = kinds(src);
+        let opens = toks.iter().filter(|(k, _)| *k == TokenKind::OpenBrace).count();
+        let closes = toks.iter().filter(|(k, _)| *k == TokenKind::CloseBrace).count();
+        assert_eq!(opens, 2);
+        assert_eq!(closes, 2);
+    }
+
+    #[test]
+    fn test_heredoc_and_pod() {
+        let src = "my $t = <<\"END\" . 'x';\n}\nEND\n=head1 NAME\n\nsub hidden {}\n\n=cut\nsub visible {}\n";
+        let toks = tokenize(src);
+        let body = toks.iter().find(|t| t.kind == TokenKind::HeredocBody).unwrap();
+        assert_eq!(body.text, "}\nEND\n");
+        let pod = toks.iter().find(|t| t.kind == TokenKind::Pod).unwrap();
+        assert!(pod.text.contains("sub hidden"));
+        let words: Vec<_> = toks.iter().filter(|t| t.kind == TokenKind::Word).map(|t| t.text).collect();
+        assert!(words.contains(&"visible"));
+        assert!(!words.contains(&"hidden"));
+        let sub_tok = toks.iter().find(|t| t.text == "visible").unwrap();
+        assert_eq!(sub_tok.line, 9);
+    }
+
+    #[test]
+    fn test_hash_keys_are_not_quote_operators() {
+        let toks = kinds("my %h = (s => 1, y => 2); print $h{s} + $h{y};");
+        assert!(!toks.iter().any(|(k, _)| *k == TokenKind::Substitution));
+    }
+
+    #[test]
+    fn test_line_index() {
+        let src = "a\nbc\n\nd";
+        let index = LineIndex::new(src);
+        assert_eq!(index.line(0), 1);
+        assert_eq!(index.line(3), 2);
+        assert_eq!(index.line_col(src, 6), (4, 1));
+    }
+}
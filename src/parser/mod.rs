@@ -7,31 +7,66 @@ use rig::{
 };
 use rig::agent::Agent;
 use rig::completion::CompletionModel;
-use serde::{Deserialize, Serialize};
-use tokio::fs;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::json;
 use crate::{
+    cancel::Cancellation,
     domain::{
-        models::{PerlModule, Subroutine, ResponsibilityCluster},
+        models::{Dependency, Diagnostic, DiagnosticKind, FileId, FileKind, PerlModule, ResponsibilityCluster, Severity, Subroutine},
         traits::ModuleParser,
     },
     error::Error as AIError,
 };
+use chunking::Chunk;
+use encoding::apply_encoding;
+pub(crate) use hazards::lower_confidence;
+pub(crate) use hybrid::{summarize_subroutines, ClusterResponse, SubroutineSummary};
+use static_parser::unnamed_package;
 
-const ANALYSIS_PROMPT: &str = "Analyze this Perl module and extract its structure and responsibilities. Return ONLY a raw JSON object (no markdown formatting, no code blocks) containing:
-            - package_name: The name of the Perl package/module
-            - subroutines: Array of objects, each containing:
-                - name: Subroutine name
-                - code: The complete subroutine code including its definition
-                - line_start: Starting line number
-                - line_end: Ending line number
-                - dependencies: Array of module/package names this subroutine depends on
-            - dependencies: Array of ALL module/package dependencies, including pragmas like 'strict' and 'warnings'
-            - responsibility_clusters: Array of objects, each containing:
-                - name: A descriptive name for this responsibility cluster
-                - description: A clear explanation of what this group of subroutines does
-                - related_subroutines: Array of subroutine names that belong to this cluster
-                - suggested_module_name: A suggested name for a new module if this cluster were extracted
-                - confidence: A float between 0.0 and 1.0 indicating confidence in this grouping
+pub mod lexer;
+mod attributes;
+mod blocks;
+mod call_graph;
+mod chunking;
+mod classes;
+mod dependencies;
+mod encoding;
+mod exports;
+mod files;
+mod hazards;
+mod hybrid;
+mod pod;
+mod signatures;
+mod static_parser;
+mod syntax;
+mod test_files;
+mod variables;
+mod verify;
+
+pub use call_graph::build_call_graph;
+pub use chunking::{AnalysisProgress, DEFAULT_TOKEN_BUDGET};
+pub use encoding::{decode_source, encode_source, read_source};
+pub use files::perl_files;
+pub use hybrid::HybridModuleParser;
+pub use static_parser::StaticModuleParser;
+pub use verify::{verify_module, verify_packages};
+
+const ANALYSIS_PROMPT: &str = "Analyze this Perl file and extract the structure and responsibilities of every package it declares. Return ONLY a raw JSON object (no markdown formatting, no code blocks) containing:
+            - packages: Array of objects, one for each package declared in the file (including `package Foo { ... }` blocks and inner helper packages), each containing:
+                - package_name: The name of the Perl package/module
+                - subroutines: Array of objects, each containing:
+                    - name: Subroutine name
+                    - code: The complete subroutine code including its definition
+                    - line_start: Starting line number
+                    - line_end: Ending line number
+                    - dependencies: Array of module/package names this subroutine depends on
+                - dependencies: Array of ALL module/package dependencies of this package, including pragmas like 'strict' and 'warnings'
+                - responsibility_clusters: Array of objects, each containing:
+                    - name: A descriptive name for this responsibility cluster
+                    - description: A clear explanation of what this group of subroutines does
+                    - related_subroutines: Array of subroutine names of this package that belong to this cluster
+                    - suggested_module_name: A suggested name for a new module if this cluster were extracted
+                    - confidence: A float between 0.0 and 1.0 indicating confidence in this grouping
 
             IMPORTANT:
             1. Include ALL dependencies in the dependencies array, including pragmas like 'strict' and 'warnings'
@@ -44,12 +79,31 @@ const ANALYSIS_PROMPT: &str = "Analyze this Perl module and extract its structur
                - Assign high confidence (>0.8) only when the relationship is very clear
             3. Include the complete subroutine code in the response for each subroutine, do not truncate it.
             4. Do not include control characters in the response. If there are newlines in the code, they should be represented as \\n in the response.
+            5. If the file declares no package, return a single entry without a package_name.
 
             Module content:
             {}
 
             ";
 
+const MERGE_PROMPT: &str = "The responsibility clusters below were identified separately in consecutive parts of one Perl package. Merge them into a single set of clusters for the whole package. Return ONLY a raw JSON object (no markdown formatting, no code blocks) containing:
+            - responsibility_clusters: Array of objects, each containing:
+                - name: A descriptive name for this responsibility cluster
+                - description: A clear explanation of what this group of subroutines does
+                - related_subroutines: Array of subroutine names that belong to this cluster
+                - suggested_module_name: A suggested name for a new module if this cluster were extracted
+                - confidence: A float between 0.0 and 1.0 indicating confidence in this grouping
+
+            IMPORTANT:
+            1. Combine clusters that describe the same responsibility, even if their names differ.
+            2. Only use subroutine names that appear in the clusters below.
+            3. Every subroutine that appears below must belong to at least one cluster.
+
+            Clusters:
+            {}
+
+            ";
+
 #[derive(Debug, Serialize, Deserialize)]
 struct ParsedSubroutine {
     name: String,
@@ -60,15 +114,32 @@ struct ParsedSubroutine {
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-struct ParseResponse {
+struct ParsedPackage {
+    package_name: Option<String>,
     subroutines: Vec<ParsedSubroutine>,
     dependencies: Vec<String>,
-    package_name: Option<String>,
     responsibility_clusters: Vec<ResponsibilityCluster>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+struct ParseResponse {
+    packages: Vec<ParsedPackage>,
+}
+
+type ProgressCallback = Box<dyn Fn(&AnalysisProgress) + Send + Sync>;
+
+/// Packages reported for a file, with the problems met along the way
+struct FileAnalysis {
+    packages: Vec<ParsedPackage>,
+    /// Diagnostics for the named package, or the first one
+    diagnostics: Vec<(Option<String>, Diagnostic)>,
+}
+
 pub struct AIModuleParser<M: CompletionModel> {
     agent: Agent<M>,
+    token_budget: usize,
+    progress: Option<ProgressCallback>,
+    cancellation: Cancellation,
 }
 
 impl<M: CompletionModel> AIModuleParser<M> {
@@ -76,25 +147,142 @@ impl<M: CompletionModel> AIModuleParser<M> {
         Self {
             agent: agent_builder
                 .preamble("You are a Perl code analyzer. You will analyze Perl code and extract its structure and identify cohesive responsibilities.")
-                .build()
+                .build(),
+            token_budget: DEFAULT_TOKEN_BUDGET,
+            progress: None,
+            cancellation: Cancellation::new(),
+        }
+    }
+
+    /// Analyze files whose content is estimated at more than `token_budget`
+    /// tokens in several requests, split at subroutine boundaries
+    pub fn with_token_budget(mut self, token_budget: usize) -> Self {
+        self.token_budget = token_budget;
+        self
+    }
+
+    /// Report the progress of chunked analyses to `progress`
+    pub fn with_progress(mut self, progress: impl Fn(&AnalysisProgress) + Send + Sync + 'static) -> Self {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    /// Stop the analysis with `Error::Cancelled` once `cancellation` is cancelled
+    pub fn with_cancellation(mut self, cancellation: Cancellation) -> Self {
+        self.cancellation = cancellation;
+        self
+    }
+
+    fn report(&self, progress: AnalysisProgress) {
+        if let Some(report) = &self.progress {
+            report(&progress);
+        }
+    }
+
+    /// Analyze a file, in chunks if it does not fit the token budget.
+    ///
+    /// A chunk that fails is recorded as a diagnostic and the others are
+    /// kept; only if every chunk fails is the first error returned, and a
+    /// cancellation ends the whole analysis. Clusters
+    /// of a package that were found in several chunks are merged in a final
+    /// request.
+    async fn analyze_file(&self, path: &Path, content: &str) -> Result<FileAnalysis, AIError> {
+        let chunks = chunking::split_into_chunks(path, content, self.token_budget);
+        if chunks.len() <= 1 {
+            let response = self.analyze_code(content).await?;
+            return Ok(FileAnalysis { packages: response.packages, diagnostics: Vec::new() });
+        }
+
+        let total = chunks.len();
+        let index = lexer::LineIndex::new(content);
+        let file = FileId::for_path(path);
+        let mut analyzed = Vec::new();
+        let mut diagnostics = Vec::new();
+        let mut first_error = None;
+        for (i, chunk) in chunks.iter().enumerate() {
+            self.report(AnalysisProgress::Analyzing {
+                chunk: i + 1,
+                total,
+                line_start: chunk.line_start,
+                line_end: chunk.line_end,
+            });
+            let prompt = format!("{}{}", chunk_note(chunk, i + 1, total), ANALYSIS_PROMPT.replace("{}", &chunk.content));
+            match self.prompt_json::<ParseResponse>(&prompt).await {
+                Ok(response) => analyzed.push((chunk.package.clone(), response)),
+                Err(AIError::Cancelled) => return Err(AIError::Cancelled),
+                Err(e) => {
+                    self.report(AnalysisProgress::Failed { chunk: i + 1, total, error: e.to_string() });
+                    diagnostics.push((chunk.package.clone(), Diagnostic {
+                        severity: Severity::Warning,
+                        kind: DiagnosticKind::ChunkFailed,
+                        message: format!("Lines {}-{} could not be analyzed: {}", chunk.line_start, chunk.line_end, e),
+                        subroutine: None,
+                        span: index.line_start(chunk.line_start).map(|start| {
+                            index.span(content, file, start, start + chunk.content.len())
+                        }),
+                    }));
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        if let (true, Some(error)) = (analyzed.is_empty(), first_error) {
+            return Err(error);
+        }
+
+        let mut packages = Vec::new();
+        for (mut package, parts) in merge_chunks(analyzed) {
+            if parts > 1 {
+                self.report(AnalysisProgress::Merging {
+                    package: package.package_name.clone().unwrap_or_else(|| "main".to_string()),
+                });
+                match self.merge_clusters(&package).await {
+                    Ok(clusters) => package.responsibility_clusters = clusters,
+                    Err(AIError::Cancelled) => return Err(AIError::Cancelled),
+                    Err(e) => diagnostics.push((package.package_name.clone(), Diagnostic {
+                        severity: Severity::Warning,
+                        kind: DiagnosticKind::ClustersNotMerged,
+                        message: format!("Clusters found in separate parts of the file could not be merged: {}", e),
+                        subroutine: None,
+                        span: None,
+                    })),
+                }
+            }
+            packages.push(package);
         }
+
+        Ok(FileAnalysis { packages, diagnostics })
     }
 
     async fn analyze_code(&self, content: &str) -> Result<ParseResponse, AIError> {
-        let prompt = ANALYSIS_PROMPT.replace("{}", content);
+        self.prompt_json(&ANALYSIS_PROMPT.replace("{}", content)).await
+    }
 
+    /// Ask the model to consolidate the clusters of a package found in several chunks
+    async fn merge_clusters(&self, package: &ParsedPackage) -> Result<Vec<ResponsibilityCluster>, AIError> {
+        let clusters = json!({
+            "package_name": package.package_name,
+            "responsibility_clusters": package.responsibility_clusters,
+        });
+        let prompt = MERGE_PROMPT.replace("{}", &serde_json::to_string_pretty(&clusters)?);
+        let response: ClusterResponse = self.prompt_json(&prompt).await?;
+        Ok(response.responsibility_clusters)
+    }
+
+    async fn prompt_json<T: DeserializeOwned>(&self, prompt: &str) -> Result<T, AIError> {
         let response = self
-            .agent
-            .prompt(prompt.as_str())
-            .await;
+            .cancellation
+            .run(self.agent.prompt(prompt))
+            .await?;
 
         let response = response
             .map_err(|e| {
-                print!("{}", e.source().unwrap());
+                if let Some(source) = e.source() {
+                    eprintln!("{}", source);
+                }
                 AIError::AIError(e.to_string())
             })?;
 
-        let parse_response = serde_json::from_str::<ParseResponse>(&response)
+        let parse_response = serde_json::from_str::<T>(&response)
             .map_err(|e| {
                 eprintln!("Failed to parse response content: {}", response);
                 AIError::ParseError(format!("Failed to parse AI response: {}", e))
@@ -104,40 +292,104 @@ impl<M: CompletionModel> AIModuleParser<M> {
     }
 }
 
+/// Tells the model which part of the file it is looking at
+fn chunk_note(chunk: &Chunk, part: usize, parts: usize) -> String {
+    let package = chunk
+        .package
+        .as_ref()
+        .map(|name| format!(" Code at the start of this part belongs to package {}.", name))
+        .unwrap_or_default();
+    format!(
+        "This is part {} of {} of a Perl file that is too large to analyze at once, lines {}-{}.{} \
+         Only describe the code in this part and report line numbers for the whole file; the other parts are analyzed separately.\n\n",
+        part, parts, chunk.line_start, chunk.line_end, package
+    )
+}
+
+/// Combine the packages reported for each chunk. Packages without a name
+/// belong to the package in effect where their chunk starts. Each package is
+/// returned with the number of chunks that found clusters in it.
+fn merge_chunks(responses: Vec<(Option<String>, ParseResponse)>) -> Vec<(ParsedPackage, usize)> {
+    let mut merged: Vec<(ParsedPackage, usize)> = Vec::new();
+    for (chunk_package, response) in responses {
+        for mut package in response.packages {
+            if package.package_name.is_none() {
+                package.package_name = chunk_package.clone();
+            }
+            let with_clusters = usize::from(!package.responsibility_clusters.is_empty());
+            let Some((existing, parts)) = merged.iter_mut().find(|(p, _)| p.package_name == package.package_name) else {
+                merged.push((package, with_clusters));
+                continue;
+            };
+
+            for sub in package.subroutines {
+                if !existing.subroutines.iter().any(|s| s.name == sub.name) {
+                    existing.subroutines.push(sub);
+                }
+            }
+            for dependency in package.dependencies {
+                if !existing.dependencies.contains(&dependency) {
+                    existing.dependencies.push(dependency);
+                }
+            }
+            existing.responsibility_clusters.extend(package.responsibility_clusters);
+            *parts += with_clusters;
+        }
+    }
+    merged
+}
+
 #[async_trait]
 impl<M: CompletionModel> ModuleParser for AIModuleParser<M> {
-    async fn parse_module(&self, path: impl AsRef<Path> + Send) -> Result<PerlModule, AIError> {
-        let content = fs::read_to_string(path.as_ref())
-            .await
-            .map_err(|e| AIError::IOError(e))?;
+    async fn parse_packages(&self, path: impl AsRef<Path> + Send) -> Result<Vec<PerlModule>, AIError> {
+        let (content, encoding) = read_source(path.as_ref()).await?;
 
-        let response = self.analyze_code(&content).await?;
+        let analysis = self.analyze_file(path.as_ref(), &content).await?;
 
-        let subroutines = response.subroutines
+        let mut modules: Vec<PerlModule> = analysis.packages
             .into_iter()
-            .map(|s| Subroutine {
-                name: s.name,
-                code: s.code,
-                line_start: s.line_start,
-                line_end: s.line_end,
-                dependencies: s.dependencies,
+            .map(|package| {
+                let subroutines = package.subroutines
+                    .into_iter()
+                    .map(|s| Subroutine {
+                        name: s.name,
+                        code: s.code,
+                        line_start: s.line_start,
+                        line_end: s.line_end,
+                        dependencies: s.dependencies,
+                        ..Default::default()
+                    })
+                    .collect();
+
+                PerlModule {
+                    name: package.package_name.unwrap_or_else(|| {
+                        unnamed_package(path.as_ref(), FileKind::of(path.as_ref(), &content))
+                    }),
+                    path: path.as_ref().to_path_buf(),
+                    content: content.clone(),
+                    subroutines,
+                    dependencies: package.dependencies.into_iter().map(Dependency::new).collect(),
+                    responsibility_clusters: package.responsibility_clusters,
+                    ..Default::default()
+                }
             })
             .collect();
 
-        Ok(PerlModule {
-            name: response.package_name.unwrap_or_else(|| {
-                path.as_ref()
-                    .file_stem()
-                    .and_then(|s| s.to_str())
-                    .unwrap_or("Unknown")
-                    .to_string()
-            }),
-            path: path.as_ref().to_path_buf(),
-            content,
-            subroutines,
-            dependencies: response.dependencies,
-            responsibility_clusters: response.responsibility_clusters,
-        })
+        // The model's code and line numbers are not trusted; check them against the source
+        verify_packages(&mut modules);
+        for module in &mut modules {
+            module.call_graph = build_call_graph(module);
+            lower_confidence(&module.subroutines, &mut module.responsibility_clusters);
+        }
+        for (package, diagnostic) in analysis.diagnostics {
+            let index = modules.iter().position(|m| package.as_ref() == Some(&m.name)).unwrap_or(0);
+            if let Some(module) = modules.get_mut(index) {
+                module.diagnostics.push(diagnostic);
+            }
+        }
+        apply_encoding(&mut modules, &encoding);
+
+        Ok(modules)
     }
 }
 
@@ -146,7 +398,7 @@ mod tests {
     use super::*;
     use tempfile::NamedTempFile;
     use wiremock::{MockServer, Mock, ResponseTemplate};
-    use wiremock::matchers::{body_json, header, method, path, query_param};
+    use wiremock::matchers::{body_json, body_string_contains, header, method, path, query_param};
     use serde_json::json;
     use std::io::Write;
     use rig::providers::azure::Client;
@@ -164,26 +416,28 @@ sub test_sub { };
         let mock_server = MockServer::start().await;
 
         let mock_response = ParseResponse {
-            subroutines: vec![
-                ParsedSubroutine {
-                    name: "test_sub".to_string(),
-                    code: "sub test_sub { };\n".to_string(),
-                    line_start: 1,
-                    line_end: 3,
-                    dependencies: vec!["Test::More".to_string()],
-                },
-            ],
-            dependencies: vec!["strict".to_string(), "warnings".to_string(), "Test::More".to_string()],
-            package_name: Some("TestModule".to_string()),
-            responsibility_clusters: vec![
-                ResponsibilityCluster {
-                    name: "Testing".to_string(),
-                    description: "Basic test functionality".to_string(),
-                    related_subroutines: vec!["test_sub".to_string()],
-                    suggested_module_name: Some("TestModule::Core".to_string()),
-                    confidence: 0.9,
-                },
-            ],
+            packages: vec![ParsedPackage {
+                subroutines: vec![
+                    ParsedSubroutine {
+                        name: "test_sub".to_string(),
+                        code: "sub test_sub { };\n".to_string(),
+                        line_start: 1,
+                        line_end: 3,
+                        dependencies: vec!["Test::More".to_string()],
+                    },
+                ],
+                dependencies: vec!["strict".to_string(), "warnings".to_string(), "Test::More".to_string()],
+                package_name: Some("TestModule".to_string()),
+                responsibility_clusters: vec![
+                    ResponsibilityCluster {
+                        name: "Testing".to_string(),
+                        description: "Basic test functionality".to_string(),
+                        related_subroutines: vec!["test_sub".to_string()],
+                        suggested_module_name: Some("TestModule::Core".to_string()),
+                        confidence: 0.9,
+                    },
+                ],
+            }],
         };
 
         Mock::given(method("POST"))
@@ -248,9 +502,112 @@ sub test_sub { };
 
         assert_eq!(module.name, "TestModule");
         assert_eq!(module.subroutines.len(), 1);
+        assert_eq!(module.subroutines[0].code, "sub test_sub { }");
+        assert_eq!((module.subroutines[0].line_start, module.subroutines[0].line_end), (5, 5));
         assert_eq!(module.dependencies, vec!["strict", "warnings", "Test::More"]);
         assert_eq!(module.responsibility_clusters.len(), 1);
 
         Ok(())
     }
+
+    fn completion(content: String) -> ResponseTemplate {
+        ResponseTemplate::new(200).set_body_json(json!({
+            "id": "test-id",
+            "object": "chat.completion",
+            "created": 1234567890,
+            "model": "gpt-4o-2024-08-06",
+            "choices": [{
+                "index": 0,
+                "message": { "role": "assistant", "content": content },
+                "finish_reason": "stop"
+            }],
+            "usage": { "prompt_tokens": 100, "completion_tokens": 50, "total_tokens": 150 }
+        }))
+    }
+
+    fn cluster(name: &str, subs: &[&str]) -> ResponsibilityCluster {
+        ResponsibilityCluster {
+            name: name.to_string(),
+            description: format!("{} operations", name),
+            related_subroutines: subs.iter().map(|s| s.to_string()).collect(),
+            suggested_module_name: Some(format!("Big::{}", name)),
+            confidence: 0.9,
+        }
+    }
+
+    fn package(name: Option<&str>, subs: &[(&str, usize, usize)], clusters: Vec<ResponsibilityCluster>) -> Result<String, serde_json::Error> {
+        serde_json::to_string(&ParseResponse {
+            packages: vec![ParsedPackage {
+                package_name: name.map(str::to_string),
+                subroutines: subs
+                    .iter()
+                    .map(|(name, line_start, line_end)| ParsedSubroutine {
+                        name: name.to_string(),
+                        code: String::new(),
+                        line_start: *line_start,
+                        line_end: *line_end,
+                        dependencies: Vec::new(),
+                    })
+                    .collect(),
+                dependencies: vec!["strict".to_string()],
+                responsibility_clusters: clusters,
+            }],
+        })
+    }
+
+    #[tokio::test]
+    async fn test_large_module_is_analyzed_in_chunks() -> Result<(), Box<dyn std::error::Error>> {
+        const BIG_MODULE: &str = "package Big;\nuse strict;\n\nsub add {\n    my ($a, $b) = @_;\n    return $a + $b;\n}\n\nsub subtract {\n    my ($a, $b) = @_;\n    return $a - $b;\n}\n\nsub negate {\n    my ($a) = @_;\n    return -$a;\n}\n\n1;\n";
+        let mock_server = MockServer::start().await;
+
+        Mock::given(body_string_contains("part 1 of 3"))
+            .respond_with(completion(package(Some("Big"), &[("add", 4, 7)], vec![cluster("Addition", &["add"])])?))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(body_string_contains("part 2 of 3"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(body_string_contains("part 3 of 3"))
+            .respond_with(completion(package(None, &[("negate", 14, 17)], vec![cluster("Negation", &["negate"])])?))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let merged = ClusterResponse { responsibility_clusters: vec![cluster("Arithmetic", &["add", "negate"])] };
+        Mock::given(body_string_contains("Merge them into a single set of clusters"))
+            .respond_with(completion(serde_json::to_string(&merged)?))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mut temp_file = NamedTempFile::new()?;
+        write!(temp_file, "{}", BIG_MODULE)?;
+
+        let progress = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let reported = progress.clone();
+        let client = Client::from_api_key("test-key", "test-version", mock_server.uri().as_str());
+        let parser = AIModuleParser::new(AgentBuilder::new(client.completion_model("gpt-4o-2024-08-06")))
+            .with_token_budget(25)
+            .with_progress(move |p| reported.lock().unwrap().push(p.clone()));
+
+        let module = parser.parse_module(temp_file.path()).await?;
+
+        assert_eq!(module.name, "Big");
+        let names: Vec<_> = module.subroutines.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["add", "subtract", "negate"]);
+        assert_eq!(module.responsibility_clusters.len(), 1);
+        assert_eq!(module.responsibility_clusters[0].name, "Arithmetic");
+
+        let failure = module.diagnostics.iter().find(|d| d.kind == DiagnosticKind::ChunkFailed).expect("a failed chunk");
+        assert!(failure.message.starts_with("Lines 9-13 could not be analyzed"));
+
+        let progress = progress.lock().unwrap();
+        assert_eq!(progress[0], AnalysisProgress::Analyzing { chunk: 1, total: 3, line_start: 1, line_end: 8 });
+        assert!(matches!(progress[2], AnalysisProgress::Failed { chunk: 2, total: 3, .. }));
+        assert_eq!(progress.last(), Some(&AnalysisProgress::Merging { package: "Big".to_string() }));
+
+        Ok(())
+    }
 } 
\ No newline at end of file
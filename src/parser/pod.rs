@@ -0,0 +1,305 @@
+use crate::domain::models::{PodSection, Span, Subroutine};
+use super::lexer::{self, Token, TokenKind};
+
+/// A line of POD with its 1-based line number and byte offset in the file
+struct PodLine<'a> {
+    line: usize,
+    start: usize,
+    text: &'a str,
+}
+
+/// A heading or item and the range of POD lines it covers
+struct Section<'a> {
+    level: usize,
+    title: &'a str,
+    start: usize,
+    end: usize,
+}
+
+/// Extract the POD of the package whose tokens satisfy `in_package`.
+///
+/// POD blocks belong to the package of the code that follows them (or
+/// precedes them at the end of the file); POD after `__END__` belongs to the
+/// package in scope there. A `=headN` or `=item` whose title names one of
+/// `subroutines` (`foo`, `foo($x)`, `C<< $obj->foo >>`, ...) is stored on that
+/// subroutine together with everything nested under it. The remaining
+/// `=head1` sections are returned as module-level documentation.
+pub(crate) fn extract_pod(
+    content: &str,
+    tokens: &[Token],
+    in_package: impl Fn(usize) -> bool,
+    subroutines: &mut [Subroutine],
+    span: impl Fn(usize, usize) -> Span,
+) -> Vec<PodSection> {
+    let lines = pod_lines(content, tokens, in_package);
+    let sections = sections(&lines);
+    let mut documents_sub = vec![false; lines.len()];
+
+    for section in &sections {
+        if documents_sub[section.start] {
+            continue;
+        }
+        let Some(name) = documented_name(section.title) else {
+            continue;
+        };
+        let Some(sub) = subroutines.iter_mut().find(|s| s.name == name && s.pod.is_none()) else {
+            continue;
+        };
+        sub.pod = Some(join(&lines[section.start..section.end]));
+        sub.pod_span = Some(section_span(&lines[section.start..section.end], &span));
+        documents_sub[section.start..section.end].fill(true);
+    }
+
+    sections
+        .iter()
+        .filter(|s| s.level == 1 && !documents_sub[s.start])
+        .map(|s| {
+            let remaining: Vec<&PodLine> = (s.start..s.end).filter(|&i| !documents_sub[i]).map(|i| &lines[i]).collect();
+            PodSection {
+                heading: s.title.to_string(),
+                text: join(remaining),
+                line_start: lines[s.start].line,
+                line_end: lines[s.end - 1].line,
+                span: Some(section_span(&lines[s.start..s.end], &span)),
+            }
+        })
+        .collect()
+}
+
+/// All POD lines of the package in source order, without `=pod` and `=cut`
+fn pod_lines<'a>(content: &'a str, tokens: &[Token], in_package: impl Fn(usize) -> bool) -> Vec<PodLine<'a>> {
+    let mut lines = Vec::new();
+    for token in lexer::tokenize(content) {
+        let next = tokens.partition_point(|t| t.start < token.start);
+        let owner = if next < tokens.len() { next } else { next.wrapping_sub(1) };
+        if !matches!(token.kind, TokenKind::Pod | TokenKind::DataSection) || owner >= tokens.len() || !in_package(owner) {
+            continue;
+        }
+
+        let mut in_pod = token.kind == TokenKind::Pod;
+        let mut start = token.start;
+        for (offset, text) in token.text.split_inclusive('\n').enumerate() {
+            let command = command(text);
+            // Inside `__END__`/`__DATA__`, POD runs from a command to `=cut`
+            in_pod |= command.is_some();
+            if in_pod && !matches!(command, Some(("pod" | "cut", _))) {
+                lines.push(PodLine { line: token.line + offset, start, text });
+            }
+            if matches!(command, Some(("cut", _))) {
+                in_pod = false;
+            }
+            start += text.len();
+        }
+    }
+    lines
+}
+
+/// Headings and items with the lines they cover: up to the next heading or
+/// item of the same or a higher level, or the `=back` closing an item's list
+fn sections<'a>(lines: &[PodLine<'a>]) -> Vec<Section<'a>> {
+    // (line index, level, title if it starts a section)
+    let mut boundaries: Vec<(usize, usize, Option<&str>)> = Vec::new();
+    let mut depth = 0;
+    for (i, line) in lines.iter().enumerate() {
+        match command(line.text) {
+            Some((head, title)) if head.len() == 5 && head.starts_with("head") => {
+                if let Some(level) = head[4..].parse().ok().filter(|l| (1..=4).contains(l)) {
+                    boundaries.push((i, level, Some(title)));
+                }
+            }
+            Some(("over", _)) => depth += 1,
+            Some(("item", title)) => boundaries.push((i, 4 + depth.max(1), Some(title))),
+            Some(("back", _)) => {
+                boundaries.push((i, 4 + depth.max(1), None));
+                depth = depth.saturating_sub(1);
+            }
+            _ => {}
+        }
+    }
+
+    boundaries
+        .iter()
+        .enumerate()
+        .filter_map(|(k, &(start, level, title))| {
+            let end = boundaries[k + 1..]
+                .iter()
+                .find(|(_, other, _)| *other <= level)
+                .map_or(lines.len(), |(i, _, _)| *i);
+            title.map(|title| Section { level, title, start, end })
+        })
+        .collect()
+}
+
+/// The POD command at the start of `line` and the rest of the line
+fn command(line: &str) -> Option<(&str, &str)> {
+    let rest = line.strip_prefix('=')?;
+    if !rest.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    let (name, title) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    Some((name, title.trim()))
+}
+
+/// The subroutine a heading names: `foo`, `foo(...)`, `$obj->foo`,
+/// `Class->foo`, `Package::foo` or any of these inside formatting codes
+fn documented_name(title: &str) -> Option<String> {
+    let mut plain = String::new();
+    let mut chars = title.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            // Formatting codes: C<...>, C<< ... >>
+            'A'..='Z' if chars.peek() == Some(&'<') => {
+                while chars.peek() == Some(&'<') {
+                    chars.next();
+                }
+            }
+            '>' if !plain.ends_with('-') => {}
+            c => plain.push(c),
+        }
+    }
+
+    let name = match plain.rsplit_once("->") {
+        Some((_, method)) => method.trim_start(),
+        None => plain.trim_start().trim_start_matches(['$', '@', '%', '&', '*', ' ']),
+    };
+    let end = name.find(|c: char| !(c.is_alphanumeric() || c == '_' || c == ':')).unwrap_or(name.len());
+    let name = name[..end].rsplit("::").next().unwrap_or_default();
+    let is_identifier = name.starts_with(|c: char| c.is_alphabetic() || c == '_');
+    is_identifier.then(|| name.to_string())
+}
+
+/// From the first line of `lines` to the end of the last non-blank one,
+/// without the trailing newline
+fn section_span(lines: &[PodLine], span: impl Fn(usize, usize) -> Span) -> Span {
+    let first = &lines[0];
+    let last = lines.iter().rfind(|l| !l.text.trim().is_empty()).unwrap_or(first);
+    span(first.start, last.start + last.text.trim_end_matches('\n').len())
+}
+
+fn join<'a>(lines: impl IntoIterator<Item = &'a PodLine<'a>>) -> String {
+    let text: String = lines.into_iter().map(|l| l.text).collect();
+    format!("{}\n", text.trim_end())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use crate::parser::StaticModuleParser;
+
+    #[test]
+    fn test_module_and_method_documentation_after_end() {
+        let content = r#"package Text::Wrap;
+use strict;
+
+sub new { my ($class, %args) = @_; return bless {%args}, $class }
+sub wrap { my ($self, $text) = @_; return $text }
+sub width { return $_[0]{width} }
+
+1;
+__END__
+
+=head1 NAME
+
+Text::Wrap - wrap text
+
+=head1 SYNOPSIS
+
+  my $wrapper = Text::Wrap->new(width => 72);
+
+=head1 METHODS
+
+All methods croak on error.
+
+=head2 Text::Wrap->new(%args)
+
+Creates a wrapper.
+
+=over 4
+
+=item width
+
+Line width.
+
+=back
+
+=head2 C<< $wrapper->wrap($text) >>
+
+Wraps C<$text>.
+
+=head1 AUTHOR
+
+Someone
+
+=cut
+"#;
+        let module = StaticModuleParser::new().parse_source(Path::new("Wrap.pm"), content).remove(0);
+
+        let headings: Vec<_> = module.documentation.iter().map(|s| s.heading.as_str()).collect();
+        assert_eq!(headings, vec!["NAME", "SYNOPSIS", "METHODS", "AUTHOR"]);
+        assert_eq!(module.documentation[0].text, "=head1 NAME\n\nText::Wrap - wrap text\n");
+        assert_eq!((module.documentation[0].line_start, module.documentation[0].line_end), (11, 14));
+        assert_eq!(module.documentation[2].text, "=head1 METHODS\n\nAll methods croak on error.\n");
+        assert_eq!(module.pod_section("synopsis").map(|s| s.line_start), Some(15));
+
+        assert_eq!(
+            module.subroutines[0].pod.as_deref(),
+            Some("=head2 Text::Wrap->new(%args)\n\nCreates a wrapper.\n\n=over 4\n\n=item width\n\nLine width.\n\n=back\n")
+        );
+        assert_eq!(module.subroutines[1].pod.as_deref(), Some("=head2 C<< $wrapper->wrap($text) >>\n\nWraps C<$text>.\n"));
+        // `=item width` is nested in the documentation of `new`
+        assert_eq!(module.subroutines[2].pod, None);
+    }
+
+    #[test]
+    fn test_inline_pod_belongs_to_its_package() {
+        let content = r#"package Shapes;
+
+=head1 NAME
+
+Shapes - geometry helpers
+
+=head1 FUNCTIONS
+
+=over
+
+=item * C<area($w, $h)>
+
+Area of a rectangle.
+
+=cut
+
+sub area { return $_[0] * $_[1] }
+
+=item * C<perimeter($w, $h)>
+
+=back
+
+=cut
+
+sub perimeter { return 2 * ($_[0] + $_[1]) }
+
+package Colors;
+
+=head1 NAME
+
+Colors - color names
+
+=cut
+
+sub red { 'red' }
+
+1;
+"#;
+        let modules = StaticModuleParser::new().parse_source(Path::new("Shapes.pm"), content);
+
+        let shapes = &modules[0];
+        assert_eq!(shapes.documentation.len(), 2);
+        assert_eq!(shapes.documentation[1].text, "=head1 FUNCTIONS\n\n=over\n\n=back\n");
+        assert_eq!(shapes.subroutines[0].pod.as_deref(), Some("=item * C<area($w, $h)>\n\nArea of a rectangle.\n"));
+        assert_eq!(shapes.subroutines[1].pod.as_deref(), Some("=item * C<perimeter($w, $h)>\n"));
+
+        let colors = &modules[1];
+        assert_eq!(colors.documentation[0].text, "=head1 NAME\n\nColors - color names\n");
+        assert_eq!(colors.subroutines[0].pod, None);
+    }
+}
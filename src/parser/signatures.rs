@@ -0,0 +1,251 @@
+use crate::domain::models::{Parameter, ParameterStyle, Signature};
+use super::lexer::{self, Token, TokenKind};
+use super::syntax::statement;
+
+/// Characters a prototype is made of
+const PROTOTYPE_CHARS: &str = "$@%&*;\\[]+_ \t";
+
+/// Parse how the subroutine in `code`, starting at its `sub` keyword, takes
+/// its arguments.
+///
+/// Parentheses after the name hold a prototype when they only contain
+/// prototype characters (`($$;@)`, `(&@)`, `()`) and a signature otherwise,
+/// unless a `:prototype(...)` attribute already gives the prototype.
+/// Without a signature the parameters come from the unpacking statements at
+/// the start of the body: `my ($self, %args) = @_;`, `my $self = shift;`,
+/// or failing that from `$_[N]` and `@_` used directly.
+pub(crate) fn parse_signature(code: &str) -> Signature {
+    let tokens = lexer::significant_tokens(code);
+    let (Some(name), Some(open)) = (tokens.get(1), tokens.iter().position(|t| t.kind == TokenKind::OpenBrace)) else {
+        return Signature::default();
+    };
+    let mut signature = Signature::default();
+    let header = code.get(name.end..tokens[open].start).unwrap_or_default();
+
+    match parse_header(header, &mut signature) {
+        Some(text) if signature.prototype.is_none() && text.chars().all(|c| PROTOTYPE_CHARS.contains(c)) => {
+            signature.prototype = Some(text.split_whitespace().collect());
+        }
+        Some(text) => {
+            signature.style = ParameterStyle::Signature;
+            signature.parameters = signature_parameters(text);
+        }
+        None => {}
+    }
+    if signature.style == ParameterStyle::None {
+        unpacked_parameters(code, &tokens[open + 1..], &mut signature);
+    }
+    signature
+}
+
+/// Collect the attributes between the name and the body, returning the text
+/// inside the prototype or signature parentheses if there are any
+fn parse_header<'a>(header: &'a str, signature: &mut Signature) -> Option<&'a str> {
+    let mut rest = header.trim_start();
+    let mut parenthesized = None;
+    loop {
+        if let Some(after) = rest.strip_prefix(':') {
+            // `:lvalue :method`, `: lvalue method` or `:prototype($$)`
+            rest = after.trim_start();
+            loop {
+                let len = rest.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(rest.len());
+                if len == 0 {
+                    break;
+                }
+                let attribute = &rest[..len];
+                rest = &rest[len..];
+                let arguments = match balanced(rest) {
+                    Some((inside, after)) => {
+                        rest = after;
+                        Some(inside)
+                    }
+                    None => None,
+                };
+                match arguments {
+                    Some(prototype) if attribute == "prototype" => signature.prototype = Some(prototype.to_string()),
+                    Some(arguments) => signature.attributes.push(format!("{}({})", attribute, arguments)),
+                    None => signature.attributes.push(attribute.to_string()),
+                }
+                rest = rest.trim_start();
+                rest = rest.strip_prefix(':').unwrap_or(rest).trim_start();
+            }
+        } else if let Some((inside, after)) = balanced(rest) {
+            parenthesized = Some(inside);
+            rest = after.trim_start();
+        } else {
+            return parenthesized;
+        }
+    }
+}
+
+/// The text inside the parentheses `text` starts with, and what follows them
+fn balanced(text: &str) -> Option<(&str, &str)> {
+    if !text.starts_with('(') {
+        return None;
+    }
+    let mut depth = 0usize;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some((&text[1..i], &text[i + 1..]));
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// `$x, $y = 1, $z //= 2, @rest` split into parameters
+fn signature_parameters(text: &str) -> Vec<Parameter> {
+    let mut parameters = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in text.char_indices().chain([(text.len(), ',')]) {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                let item = text[start..i].trim();
+                start = i + 1;
+                if item.is_empty() {
+                    continue;
+                }
+                let (name, default) = ["//=", "||=", "="]
+                    .iter()
+                    .find_map(|op| item.split_once(op))
+                    .map_or((item, None), |(name, default)| (name.trim(), Some(default.trim().to_string())));
+                parameters.push(Parameter {
+                    name: name.to_string(),
+                    default: default.filter(|d| !d.is_empty()),
+                });
+            }
+            _ => {}
+        }
+    }
+    parameters
+}
+
+/// Parameters unpacked by the statements that start `body`
+fn unpacked_parameters(code: &str, body: &[Token], signature: &mut Signature) {
+    let mut shifted = false;
+    let mut k = 0;
+    while k < body.len() {
+        let current = statement(body, k);
+        match current {
+            [my, var, assign, shift, rest @ ..]
+                if my.is_word("my") && var.kind == TokenKind::Variable && assign.is_op("=") && shift.is_word("shift") =>
+            {
+                let default = rest
+                    .iter()
+                    .position(|t| t.is_op("//") || t.is_op("||"))
+                    .and_then(|p| Some(&code[rest.get(p + 1)?.start..rest.last()?.end]));
+                signature.parameters.push(Parameter {
+                    name: var.text.to_string(),
+                    default: default.map(str::to_string),
+                });
+                shifted = true;
+            }
+            [my, var, assign, args] if my.is_word("my") && var.kind == TokenKind::Variable && assign.is_op("=") && args.text == "@_" => {
+                signature.parameters.push(Parameter { name: var.text.to_string(), default: None });
+                break;
+            }
+            [my, open, vars @ .., close, assign, args]
+                if my.is_word("my")
+                    && open.kind == TokenKind::OpenParen
+                    && close.kind == TokenKind::CloseParen
+                    && assign.is_op("=")
+                    && args.text == "@_" =>
+            {
+                signature.parameters.extend(
+                    vars.iter()
+                        .filter(|t| t.kind == TokenKind::Variable)
+                        .map(|t| Parameter { name: t.text.to_string(), default: None }),
+                );
+                break;
+            }
+            _ => break,
+        }
+        k += current.len() + 1;
+    }
+
+    signature.style = match (shifted, signature.parameters.is_empty()) {
+        (true, _) => ParameterStyle::Shift,
+        (false, false) => ParameterStyle::List,
+        (false, true) => {
+            signature.parameters = direct_arguments(body);
+            if signature.parameters.is_empty() {
+                ParameterStyle::None
+            } else {
+                ParameterStyle::Direct
+            }
+        }
+    };
+}
+
+/// `$_[0]`, `$_[1]`, ... in order, or `@_` when the body uses the whole list
+fn direct_arguments(body: &[Token]) -> Vec<Parameter> {
+    let mut indices: Vec<usize> = body
+        .windows(3)
+        .filter(|w| w[0].text == "$_" && w[1].kind == TokenKind::OpenBracket && w[2].kind == TokenKind::Number)
+        .filter_map(|w| w[2].text.parse().ok())
+        .collect();
+    indices.sort();
+    indices.dedup();
+
+    let mut names: Vec<String> = indices.iter().map(|i| format!("$_[{}]", i)).collect();
+    if body.iter().any(|t| t.text == "@_") {
+        names.push("@_".to_string());
+    }
+    names.into_iter().map(|name| Parameter { name, default: None }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(signature: &Signature) -> Vec<&str> {
+        signature.parameters.iter().map(|p| p.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_signatures_prototypes_and_attributes() {
+        let signature = parse_signature("sub max_of ($$;@) { my ($x, $y, @rest) = @_; }");
+        assert_eq!(signature.prototype.as_deref(), Some("$$;@"));
+        assert_eq!(signature.style, ParameterStyle::List);
+        assert_eq!(names(&signature), vec!["$x", "$y", "@rest"]);
+
+        let signature = parse_signature("sub greet :method ($self, $name = 'you', %opts) { }");
+        assert_eq!(signature.attributes, vec!["method"]);
+        assert_eq!(signature.style, ParameterStyle::Signature);
+        assert_eq!(signature.parameter_list(), "($self, $name = 'you', %opts)");
+
+        let signature = parse_signature("sub first :prototype(&@) ($code, @list) { }");
+        assert_eq!(signature.prototype.as_deref(), Some("&@"));
+        assert_eq!(names(&signature), vec!["$code", "@list"]);
+
+        let signature = parse_signature("sub count : lvalue Local { $count }");
+        assert_eq!(signature.attributes, vec!["lvalue", "Local"]);
+        assert_eq!(signature.style, ParameterStyle::None);
+
+        assert_eq!(parse_signature("sub PI() { 3.14 }").prototype.as_deref(), Some(""));
+    }
+
+    #[test]
+    fn test_unpacked_parameters() {
+        let signature = parse_signature("sub new {\n    my $class = shift;\n    my $size = shift // 10;\n    my %args = @_;\n    return bless {%args}, $class;\n}");
+        assert_eq!(signature.style, ParameterStyle::Shift);
+        assert_eq!(signature.parameter_list(), "($class, $size = 10, %args)");
+
+        let signature = parse_signature("sub area { return $_[1] * $_[0] }");
+        assert_eq!(signature.style, ParameterStyle::Direct);
+        assert_eq!(names(&signature), vec!["$_[0]", "$_[1]"]);
+
+        let signature = parse_signature("sub log_all { print STDERR @_ }");
+        assert_eq!(names(&signature), vec!["@_"]);
+        assert_eq!(parse_signature("sub now { time }").style, ParameterStyle::None);
+    }
+}
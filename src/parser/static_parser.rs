@@ -0,0 +1,608 @@
+use std::path::Path;
+use async_trait::async_trait;
+use crate::{
+    domain::{
+        models::{Dependency, FileId, FileKind, Hazard, PerlModule, Span, Subroutine},
+        traits::ModuleParser,
+    },
+    error::Error,
+    metrics::subroutine_metrics,
+};
+use super::attributes::extract_attributes;
+use super::blocks::{data_section, record_special_blocks};
+use super::call_graph::build_call_graph;
+use super::classes::{extract_class, subroutine_kind};
+use super::dependencies::loaded_dependencies;
+use super::encoding::{apply_encoding, read_source};
+use super::exports::extract_exports;
+use super::hazards::find_hazards;
+use super::pod::extract_pod;
+use super::signatures::parse_signature;
+use super::test_files::extract_test_suite;
+use super::variables::{declared_variables, record_usage};
+use super::lexer::{self, LineIndex, Token, TokenKind};
+use super::syntax::statement;
+
+/// Package prefixes that never name a real dependency
+const PSEUDO_PACKAGES: &[&str] = &["SUPER", "CORE", "CORE::GLOBAL", "main", "__PACKAGE__", "UNIVERSAL"];
+
+/// Deterministic, offline implementation of the ModuleParser trait.
+///
+/// Tokenizes the Perl source locally and extracts the package name,
+/// subroutines (with exact code, line ranges and POD), dependencies, exports,
+/// class structure, attributes, module-level POD, special blocks, the data
+/// section, dynamic-code hazards and the shared variables each subroutine
+/// reads and writes. It does not identify responsibility clusters;
+/// `responsibility_clusters` is left empty.
+pub struct StaticModuleParser;
+
+impl Default for StaticModuleParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StaticModuleParser {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Parse already loaded Perl source into one module per package.
+    ///
+    /// Subroutines and `use` statements belong to the package in scope where
+    /// they appear, including `package Foo { ... }` blocks. Imports that come
+    /// before the first `package` statement apply to every package. Code
+    /// outside any package becomes a `main` module only if it defines
+    /// subroutines, or always in scripts and test files; a module file
+    /// without any `package` statement yields a single module named after
+    /// the file. `path` is stored on every module.
+    ///
+    /// In `.pl` and `.t` files (see `FileKind::of`) the top-level code is
+    /// the program, recorded as special blocks, and test files also get
+    /// their Test::More structure.
+    pub fn parse_source(&self, path: &Path, content: &str) -> Vec<PerlModule> {
+        let tokens = lexer::significant_tokens(content);
+        let index = LineIndex::new(content);
+        let file = FileId::for_path(path);
+        let span = |start, end| index.span(content, file, start, end);
+        let scopes = PackageScopes::new(&tokens);
+        let imports = collect_uses(&tokens, &scopes, span);
+        let file_kind = FileKind::of(path, content);
+        let sub_ranges = find_subroutines(&tokens);
+
+        let mut modules = Vec::new();
+        for (id, package) in scopes.names.iter().enumerate() {
+            let package_imports: Vec<&Import> = imports
+                .iter()
+                .filter(|import| import.package == id || import.file_level)
+                .collect();
+
+            let mut subroutines: Vec<Subroutine> = sub_ranges
+                .iter()
+                .filter(|range| scopes.owner(&tokens, range.start) == id)
+                .map(|range| build_subroutine(content, &tokens[range.clone()], &package_imports, package, span))
+                .collect();
+
+            let is_main = id == MAIN_PACKAGE;
+            if is_main && subroutines.is_empty() && scopes.names.len() > 1 && file_kind == FileKind::Module {
+                continue;
+            }
+
+            let mut dependencies = Vec::new();
+            for import in &package_imports {
+                merge_dependency(&mut dependencies, &import.dependency);
+            }
+
+            let name = if is_main && scopes.names.len() == 1 {
+                unnamed_package(path, file_kind)
+            } else {
+                package.clone()
+            };
+
+            let in_package = |i: usize| scopes.owners[i] == id;
+            let documentation = extract_pod(content, &tokens, in_package, &mut subroutines, span);
+            let class = extract_class(&tokens, in_package, &subroutines);
+            // `has` only declares attributes where an object system provides it
+            let attributes = match &class {
+                Some(class) if class.object_system.is_some() => extract_attributes(content, &tokens, in_package, &subroutines),
+                _ => Vec::new(),
+            };
+            let mut module = PerlModule {
+                name,
+                path: path.to_path_buf(),
+                content: content.to_string(),
+                subroutines,
+                dependencies,
+                responsibility_clusters: Vec::new(),
+                variables: declared_variables(&tokens, in_package, &sub_ranges, package),
+                exports: extract_exports(&tokens, in_package),
+                class,
+                attributes,
+                documentation,
+                file_kind,
+                ..Default::default()
+            };
+            module.call_graph = build_call_graph(&module);
+            record_usage(&mut module);
+            record_special_blocks(&mut module, content, &tokens, in_package, &sub_ranges);
+            module.data_section = data_section(&tokens, in_package, &module);
+            module.hazards = find_hazards(content, &tokens, |i| in_package(i) && !sub_ranges.iter().any(|r| r.contains(&i)));
+            module.hazards.extend(module.subroutines.iter().flat_map(|s| s.hazards.iter().cloned()));
+            module.hazards.sort_by_key(|h| h.line);
+            if file_kind == FileKind::Test {
+                module.test_suite = extract_test_suite(content, &tokens, in_package, &sub_ranges, &module);
+            }
+            modules.push(module);
+        }
+        modules
+    }
+}
+
+#[async_trait]
+impl ModuleParser for StaticModuleParser {
+    async fn parse_packages(&self, path: impl AsRef<Path> + Send) -> Result<Vec<PerlModule>, Error> {
+        let (content, encoding) = read_source(path.as_ref()).await?;
+
+        let mut modules = self.parse_source(path.as_ref(), &content);
+        apply_encoding(&mut modules, &encoding);
+        Ok(modules)
+    }
+}
+
+/// The name of the package a file without `package` statements declares:
+/// `main` for scripts and test files, the file name for modules
+pub(crate) fn unnamed_package(path: &Path, file_kind: FileKind) -> String {
+    if file_kind != FileKind::Module {
+        return "main".to_string();
+    }
+    path.file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("Unknown")
+        .to_string()
+}
+
+/// Index of the implicit `main` package in `PackageScopes::names`
+const MAIN_PACKAGE: usize = 0;
+
+/// Which package is in scope at every significant token
+struct PackageScopes {
+    /// Package names in order of first declaration, starting with `main`
+    names: Vec<String>,
+    /// Package index for each token
+    owners: Vec<usize>,
+    /// Token index of the first `package` statement, if any
+    first_declaration: Option<usize>,
+}
+
+impl PackageScopes {
+    fn new(tokens: &[Token]) -> Self {
+        let mut names = vec!["main".to_string()];
+        let mut owners = Vec::with_capacity(tokens.len());
+        let mut first_declaration = None;
+        let mut current = MAIN_PACKAGE;
+        let mut enclosing = Vec::new();
+        let mut block_package = None;
+
+        for (i, token) in tokens.iter().enumerate() {
+            match token.kind {
+                TokenKind::OpenBrace => {
+                    enclosing.push(current);
+                    if let Some(package) = block_package.take() {
+                        current = package;
+                    }
+                }
+                TokenKind::CloseBrace => current = enclosing.pop().unwrap_or(MAIN_PACKAGE),
+                _ => {}
+            }
+
+            if let Some(name) = package_declaration(tokens, i) {
+                first_declaration.get_or_insert(i);
+                let id = names.iter().position(|n| n == name).unwrap_or_else(|| {
+                    names.push(name.to_string());
+                    names.len() - 1
+                });
+                // `package Foo 1.0 { ... }` scopes the package to the block
+                let mut next = i + 2;
+                if tokens.get(next).is_some_and(|t| t.kind == TokenKind::Number) {
+                    next += 1;
+                }
+                if tokens.get(next).is_some_and(|t| t.kind == TokenKind::OpenBrace) {
+                    block_package = Some(id);
+                } else {
+                    current = id;
+                }
+            }
+
+            owners.push(current);
+        }
+
+        Self { names, owners, first_declaration }
+    }
+
+    /// Package owning the token at `index`, honouring fully qualified sub names
+    fn owner(&self, tokens: &[Token], index: usize) -> usize {
+        if tokens[index].is_word("sub") {
+            if let Some((package, _)) = tokens.get(index + 1).and_then(|t| t.text.rsplit_once("::")) {
+                if let Some(id) = self.names.iter().position(|n| n == package) {
+                    return id;
+                }
+            }
+        }
+        self.owners[index]
+    }
+}
+
+/// The package name if the token at `index` starts a `package` statement
+pub(crate) fn package_declaration<'a>(tokens: &[Token<'a>], index: usize) -> Option<&'a str> {
+    let token = &tokens[index];
+    let name = tokens.get(index + 1)?;
+    let is_statement = token.is_word("package")
+        && name.kind == TokenKind::Word
+        && !(index > 0 && tokens[index - 1].is_op("->"));
+    is_statement.then_some(name.text)
+}
+
+/// A dependency loaded by a `use`, `no` or `require` statement
+struct Import {
+    dependency: Dependency,
+    /// Package in scope at the statement
+    package: usize,
+    /// Appears before the first `package` statement and applies to all packages
+    file_level: bool,
+}
+
+impl Import {
+    /// Symbols the statement imports by name
+    fn symbols(&self) -> &[String] {
+        match &self.dependency.imports {
+            Some(imports) if !self.dependency.unimport => imports,
+            _ => &[],
+        }
+    }
+}
+
+fn push_unique(list: &mut Vec<String>, value: String) {
+    if !list.contains(&value) {
+        list.push(value);
+    }
+}
+
+/// Add `dependency` to `list`, merging it with an earlier statement loading
+/// the same module: the earliest phase wins and import lists are combined
+fn merge_dependency(list: &mut Vec<Dependency>, dependency: &Dependency) {
+    let Some(existing) = list.iter_mut().find(|d| d.name == dependency.name && d.unimport == dependency.unimport) else {
+        list.push(dependency.clone());
+        return;
+    };
+    existing.phase = existing.phase.min(dependency.phase);
+    if existing.version.is_none() {
+        existing.version = dependency.version.clone();
+    }
+    if let (Some(imports), Some(more)) = (&mut existing.imports, &dependency.imports) {
+        for symbol in more {
+            push_unique(imports, symbol.clone());
+        }
+    }
+}
+
+fn collect_uses(tokens: &[Token], scopes: &PackageScopes, span: impl Fn(usize, usize) -> Span) -> Vec<Import> {
+    let mut imports = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        let package = scopes.owners[i];
+        let file_level = scopes.first_declaration.is_none_or(|first| i < first);
+        let rest = statement(tokens, i + 1);
+        let end = tokens
+            .get(i + 1 + rest.len())
+            .filter(|t| t.is_op(";"))
+            .or(rest.last())
+            .unwrap_or(token)
+            .end;
+        imports.extend(loaded_dependencies(tokens, i).into_iter().map(|dependency| Import {
+            dependency: Dependency { span: Some(span(token.start, end)), ..dependency },
+            package,
+            file_level,
+        }));
+    }
+    imports
+}
+
+/// Token index ranges (from `sub` to the closing brace) of every named subroutine
+fn find_subroutines(tokens: &[Token]) -> Vec<std::ops::Range<usize>> {
+    let mut ranges = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        if !token.is_word("sub") {
+            continue;
+        }
+        // Skip anonymous subs and method calls named `sub`
+        if !tokens.get(i + 1).is_some_and(|t| t.kind == TokenKind::Word) {
+            continue;
+        }
+        if i > 0 && tokens[i - 1].is_op("->") {
+            continue;
+        }
+        if let Some(end) = body_end(tokens, i + 2) {
+            ranges.push(i..end + 1);
+        }
+    }
+    ranges
+}
+
+/// Index of the brace closing the body that starts at or after `from`.
+/// Returns `None` for forward declarations (`sub foo;`). A prototype,
+/// signature or attribute argument list in parentheses is skipped, since
+/// it may contain `;` or `{`.
+fn body_end(tokens: &[Token], from: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    let open = from + tokens[from..].iter().position(|t| {
+        match t.kind {
+            TokenKind::OpenParen => depth += 1,
+            TokenKind::CloseParen => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        depth == 0 && (t.kind == TokenKind::OpenBrace || t.is_op(";"))
+    })?;
+    if tokens[open].kind != TokenKind::OpenBrace {
+        return None;
+    }
+    matching_brace(tokens, open)
+}
+
+/// Index of the brace matching the opening brace at `open`
+pub(crate) fn matching_brace(tokens: &[Token], open: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    for (offset, token) in tokens[open..].iter().enumerate() {
+        match token.kind {
+            TokenKind::OpenBrace => depth += 1,
+            TokenKind::CloseBrace => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(open + offset);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn build_subroutine(
+    content: &str,
+    tokens: &[Token],
+    imports: &[&Import],
+    package: &str,
+    span: impl Fn(usize, usize) -> Span,
+) -> Subroutine {
+    let first = tokens.first().expect("subroutine range is never empty");
+    let last = tokens.last().expect("subroutine range is never empty");
+    let end_line = last.line + last.text.matches('\n').count();
+
+    let name = tokens[1].text;
+    let name = name.rsplit_once("::").map_or(name, |(_, short)| short).to_string();
+    let code = &content[first.start..last.end];
+    let signature = parse_signature(code);
+    let metrics = subroutine_metrics(code, &signature);
+    let hazards = find_hazards(content, tokens, |_| true)
+        .into_iter()
+        .map(|hazard| Hazard { subroutine: Some(name.clone()), ..hazard })
+        .collect();
+    Subroutine {
+        name,
+        code: code.to_string(),
+        line_start: first.line,
+        line_end: end_line,
+        dependencies: subroutine_dependencies(&tokens[2..], imports, package),
+        kind: subroutine_kind(&signature),
+        signature,
+        hazards,
+        span: Some(span(first.start, last.end)),
+        metrics,
+        ..Default::default()
+    }
+}
+
+/// Packages referenced from a subroutine body: class method calls, fully
+/// qualified functions and variables, and functions imported with `use`.
+fn subroutine_dependencies(tokens: &[Token], imports: &[&Import], package: &str) -> Vec<String> {
+    let mut dependencies = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        let prev = i.checked_sub(1).map(|p| &tokens[p]);
+        let next = tokens.get(i + 1);
+        let after_arrow = prev.is_some_and(|t| t.is_op("->"));
+        let is_hash_key = next.is_some_and(|t| t.is_op("=>"))
+            || (prev.is_some_and(|t| t.kind == TokenKind::OpenBrace)
+                && next.is_some_and(|t| t.kind == TokenKind::CloseBrace));
+
+        let referenced = match token.kind {
+            TokenKind::Word if after_arrow || is_hash_key => None,
+            TokenKind::Word if next.is_some_and(|t| t.is_op("->")) => {
+                let is_class = token.text.contains("::")
+                    || token.text.starts_with(|c: char| c.is_ascii_uppercase());
+                is_class.then(|| token.text.to_string())
+            }
+            TokenKind::Word if token.text.contains("::") => {
+                token.text.rsplit_once("::").map(|(pkg, _)| pkg.to_string())
+            }
+            TokenKind::Word => imports
+                .iter()
+                .find(|import| import.symbols().iter().any(|s| s == token.text))
+                .map(|import| import.dependency.name.clone()),
+            TokenKind::Variable if token.text.contains("::") => token.text[1..]
+                .rsplit_once("::")
+                .map(|(pkg, _)| pkg.to_string()),
+            _ => None,
+        };
+
+        if let Some(dep) = referenced {
+            if !dep.is_empty() && dep != package && !PSEUDO_PACKAGES.contains(&dep.as_str()) {
+                push_unique(&mut dependencies, dep);
+            }
+        }
+    }
+    dependencies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn parse(content: &str) -> PerlModule {
+        StaticModuleParser::new().parse_source(Path::new("Test.pm"), content).remove(0)
+    }
+
+    #[tokio::test]
+    async fn test_parse_calculator() -> Result<(), Error> {
+        let module = StaticModuleParser::new()
+            .parse_module(PathBuf::from("tests/data/Calculator.pm"))
+            .await?;
+
+        assert_eq!(module.name, "Calculator");
+        assert_eq!(module.dependencies, vec!["strict", "warnings", "Math::Complex", "List::Util"]);
+        let ranges: Vec<_> = module.subroutines.iter()
+            .map(|s| (s.name.as_str(), s.line_start, s.line_end))
+            .collect();
+        assert_eq!(ranges, vec![("add", 8, 11), ("multiply", 13, 16), ("calculate_average", 18, 22)]);
+
+        let avg = &module.subroutines[2];
+        assert_eq!(avg.dependencies, vec!["List::Util"]);
+        assert!(avg.code.starts_with("sub calculate_average {"));
+        assert!(avg.code.ends_with("scalar(@numbers);\n}"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_parse_order_manager_dependencies() -> Result<(), Error> {
+        let module = StaticModuleParser::new()
+            .parse_module(PathBuf::from("tests/data/OrderManager.pm"))
+            .await?;
+
+        assert_eq!(module.name, "OrderManager");
+        let details = module.subroutines.iter().find(|s| s.name == "get_order_details").unwrap();
+        assert_eq!(details.dependencies, vec!["DBI"]);
+        let page = module.subroutines.iter().find(|s| s.name == "generate_order_page").unwrap();
+        assert_eq!(page.dependencies, vec!["HTML::Template"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_code_is_exact_with_tricky_bodies() {
+        let content = r#"package Tricky;
+
+sub with_heredoc {
+    my $text = <<"END";
+closing } brace in heredoc
+END
+    return $text;
+}
+
+=head1 NOT CODE
+
+sub in_pod { }
+
+=cut
+
+sub with_strings {
+    my $s = '}' . "{" . q{ {nested} };
+    $s =~ s/\}//g; # } in a comment
+    return { key => $s };
+}
+"#;
+        let module = parse(content);
+        let names: Vec<_> = module.subroutines.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["with_heredoc", "with_strings"]);
+
+        let heredoc = &module.subroutines[0];
+        assert_eq!((heredoc.line_start, heredoc.line_end), (3, 8));
+        let lines: Vec<_> = content.lines().collect();
+        assert_eq!(heredoc.code, lines[2..8].join("\n"));
+
+        let strings = &module.subroutines[1];
+        assert_eq!((strings.line_start, strings.line_end), (16, 20));
+        assert!(strings.code.ends_with("return { key => $s };\n}"));
+    }
+
+    #[test]
+    fn test_forward_declarations_and_anonymous_subs_are_skipped() {
+        let module = parse("package P;\nsub later;\nmy $cb = sub { 1 };\nsub later { return $cb->() }\n");
+        let names: Vec<_> = module.subroutines.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["later"]);
+
+        // A `;` in a prototype does not make a forward declaration
+        let module = parse("package P;\nsub proto($$;@) { return 1 }\nsub attr :prototype($;@) { 2 }\nsub sig ($x, $y = {}) { 3 }\nsub other { proto(1, 2) }\n");
+        let names: Vec<_> = module.subroutines.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["proto", "attr", "sig", "other"]);
+        assert_eq!(module.subroutines[0].signature.prototype.as_deref(), Some("$$;@"));
+        assert_eq!(module.subroutines[1].signature.prototype.as_deref(), Some("$;@"));
+    }
+
+    #[test]
+    fn test_multiple_packages_per_file() {
+        let content = r#"use strict;
+use warnings;
+
+package Shop::Cart {
+    use List::Util qw(sum);
+
+    sub total { my ($self) = @_; return sum(map { $_->price } @{ $self->{items} }) }
+}
+
+package Shop::Item;
+use parent -norequire, 'Shop::Base';
+
+sub new { my ($class, %args) = @_; return bless {%args}, $class }
+sub price { $_[0]{price} }
+
+package Shop::Item::Helper;
+
+sub Shop::Item::Helper::format_price { sprintf '%.2f', $_[0] }
+
+1;
+"#;
+        let modules = StaticModuleParser::new().parse_source(Path::new("Shop.pm"), content);
+        let names: Vec<_> = modules.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, vec!["Shop::Cart", "Shop::Item", "Shop::Item::Helper"]);
+
+        let cart = &modules[0];
+        assert_eq!(cart.dependencies, vec!["strict", "warnings", "List::Util"]);
+        assert_eq!(cart.subroutines[0].name, "total");
+        assert_eq!(cart.subroutines[0].dependencies, vec!["List::Util"]);
+
+        let item = &modules[1];
+        assert_eq!(item.dependencies, vec!["strict", "warnings", "parent"]);
+        let subs: Vec<_> = item.subroutines.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(subs, vec!["new", "price"]);
+
+        assert_eq!(modules[2].subroutines[0].name, "format_price");
+    }
+
+    #[test]
+    fn test_script_subroutines_belong_to_main() {
+        let content = "use strict;\nsub helper { 1 }\npackage Inner;\nsub inner { 2 }\n";
+        let modules = StaticModuleParser::new().parse_source(Path::new("run.pl"), content);
+        let names: Vec<_> = modules.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, vec!["main", "Inner"]);
+        assert_eq!(modules[0].subroutines[0].name, "helper");
+    }
+
+    #[test]
+    fn test_spans_of_subroutines_dependencies_and_pod() {
+        let content = "package Greeter;\nuse strict;\n\n=head2 greet\n\nSays hello.\n\n=cut\n\n  sub greet { return \"héllo\" }\nsub bye { 1 }\n1;\n";
+        let module = parse(content);
+        let file = FileId::for_path(Path::new("Test.pm"));
+
+        let greet = module.subroutines[0].span.expect("a span for greet");
+        assert_eq!(greet.text(content), Some("sub greet { return \"héllo\" }"));
+        assert_eq!((greet.file, greet.line_start, greet.column_start), (file, 10, 3));
+        // Columns count characters, not bytes
+        assert_eq!((greet.line_end, greet.column_end), (10, 31));
+        assert!(greet.contains(content.find("return").unwrap()));
+
+        let strict = module.dependencies[0].span.expect("a span for use strict");
+        assert_eq!(strict.text(content), Some("use strict;"));
+        assert_eq!((strict.line_start, strict.column_start), (2, 1));
+
+        let pod = module.subroutines[0].pod_span.expect("a span for the POD of greet");
+        assert_eq!(pod.text(content), Some("=head2 greet\n\nSays hello."));
+        assert_eq!((pod.line_start, pod.line_end), (4, 6));
+    }
+}
@@ -0,0 +1,121 @@
+//! Helpers for statement-level constructs that several analyses look at:
+//! statement boundaries, comma-separated lists and hash literals.
+
+use super::lexer::{self, Token, TokenKind};
+
+/// `push @ARRAY, ...` or `push(@ARRAY, ...)` at the variable at `i`
+pub(crate) fn is_pushed(tokens: &[Token], i: usize) -> bool {
+    let prev = i.checked_sub(1).map(|p| &tokens[p]);
+    let before = i.checked_sub(2).map(|p| &tokens[p]);
+    let pushed = prev.is_some_and(|t| t.is_word("push"))
+        || (prev.is_some_and(|t| t.kind == TokenKind::OpenParen) && before.is_some_and(|t| t.is_word("push")));
+    pushed && tokens.get(i + 1).is_some_and(|t| t.is_op(","))
+}
+
+/// A variable name without its package qualifier (`@Foo::EXPORT` is `@EXPORT`)
+pub(crate) fn variable_name(text: &str) -> String {
+    match text[1..].rsplit_once("::") {
+        Some((_, name)) => format!("{}{}", &text[..1], name),
+        None => text.to_string(),
+    }
+}
+
+/// The rest of the statement that continues at `from`, up to its `;`
+pub(crate) fn statement<'t, 'a>(tokens: &'t [Token<'a>], from: usize) -> &'t [Token<'a>] {
+    let rest = tokens.get(from..).unwrap_or_default();
+    &rest[..statement_end(rest)]
+}
+
+/// Index of the `;` (or unbalanced closing bracket) ending a statement, or the end of the input
+fn statement_end(tokens: &[Token]) -> usize {
+    let mut depth = 0usize;
+    for (i, token) in tokens.iter().enumerate() {
+        match token.kind {
+            TokenKind::OpenBrace | TokenKind::OpenParen | TokenKind::OpenBracket => depth += 1,
+            TokenKind::CloseBrace | TokenKind::CloseParen | TokenKind::CloseBracket => {
+                if depth == 0 {
+                    return i;
+                }
+                depth -= 1;
+            }
+            TokenKind::Operator if depth == 0 && token.text == ";" => return i,
+            _ => {}
+        }
+    }
+    tokens.len()
+}
+
+/// Plain strings listed in `tokens` (quoted strings and quote-words), skipping
+/// options such as `-norequire`
+pub(crate) fn strings(tokens: &[Token]) -> Vec<String> {
+    let mut values = Vec::new();
+    for token in tokens {
+        match token.kind {
+            TokenKind::QuoteWords => values.extend(lexer::quote_words(token.text)),
+            TokenKind::String | TokenKind::InterpolatedString => {
+                values.extend(lexer::string_contents(token.text).map(str::to_string));
+            }
+            _ => {}
+        }
+    }
+    values.retain(|v| !v.starts_with('-'));
+    values
+}
+
+/// Key/value pairs of a hash literal; the surrounding parens or braces are optional
+pub(crate) fn pairs<'t, 'a>(tokens: &'t [Token<'a>]) -> Vec<(&'a str, &'t [Token<'a>])> {
+    let inner = match (tokens.first(), tokens.last()) {
+        (Some(first), Some(last))
+            if matches!(first.kind, TokenKind::OpenParen | TokenKind::OpenBrace)
+                && matches!(last.kind, TokenKind::CloseParen | TokenKind::CloseBrace) =>
+        {
+            &tokens[1..tokens.len() - 1]
+        }
+        _ => tokens,
+    };
+    let items = items(inner);
+    items
+        .chunks(2)
+        .filter_map(|pair| match pair {
+            [key, value] => key_name(key).map(|name| (name, *value)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// A hash key written as a bareword (optionally `-word`) or a string
+pub(crate) fn key_name<'a>(tokens: &[Token<'a>]) -> Option<&'a str> {
+    let tokens = match tokens {
+        [dash, rest @ ..] if dash.is_op("-") => rest,
+        _ => tokens,
+    };
+    match tokens {
+        [token] if token.kind == TokenKind::Word => Some(token.text),
+        [token] if matches!(token.kind, TokenKind::String | TokenKind::InterpolatedString) => lexer::string_contents(token.text),
+        _ => None,
+    }
+}
+
+/// Split a list at top-level `,` and `=>`
+pub(crate) fn items<'t, 'a>(tokens: &'t [Token<'a>]) -> Vec<&'t [Token<'a>]> {
+    let mut items = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        match token.kind {
+            TokenKind::OpenBrace | TokenKind::OpenParen | TokenKind::OpenBracket => depth += 1,
+            TokenKind::CloseBrace | TokenKind::CloseParen | TokenKind::CloseBracket => depth = depth.saturating_sub(1),
+            TokenKind::Operator if depth == 0 && (token.text == "," || token.text == "=>") => {
+                if i > start {
+                    items.push(&tokens[start..i]);
+                }
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if start < tokens.len() {
+        items.push(&tokens[start..]);
+    }
+    items
+}
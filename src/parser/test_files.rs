@@ -0,0 +1,166 @@
+use std::ops::Range;
+use crate::domain::models::{PerlModule, Subtest, TestPlan, TestSuite};
+use super::call_graph::calls_in;
+use super::lexer::{self, Token, TokenKind};
+use super::static_parser::matching_brace;
+use super::syntax::statement;
+
+/// Functions of Test::More, Test::Exception, Test::Deep, Test::Warn and
+/// Test::Fatal that each make one assertion
+const ASSERTIONS: &[&str] = &[
+    "ok", "is", "isnt", "like", "unlike", "cmp_ok", "is_deeply", "can_ok", "isa_ok", "new_ok", "pass", "fail",
+    "use_ok", "require_ok", "throws_ok", "dies_ok", "lives_ok", "lives_and", "cmp_deeply", "warning_is",
+    "warnings_are", "warning_like", "exception",
+];
+
+/// The Test::More structure of the package: the plan, the subtests with
+/// the helper subroutines they call, and the assertions outside subtests.
+/// `None` unless the package loads a `Test::` or `Test2::` module.
+///
+/// Assertions are counted where they are written, so those inside named
+/// subroutines (the helpers) are not counted at all.
+pub(crate) fn extract_test_suite(
+    content: &str,
+    tokens: &[Token],
+    in_package: impl Fn(usize) -> bool,
+    sub_ranges: &[Range<usize>],
+    module: &PerlModule,
+) -> Option<TestSuite> {
+    let frameworks: Vec<String> = module.dependencies
+        .iter()
+        .filter(|d| d.name.starts_with("Test::") || d.name.starts_with("Test2::"))
+        .map(|d| d.name.clone())
+        .collect();
+    if frameworks.is_empty() {
+        return None;
+    }
+
+    let mut suite = TestSuite { frameworks, ..Default::default() };
+    let mut subtest_ranges: Vec<Range<usize>> = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        if !in_package(i) || sub_ranges.iter().any(|r| r.contains(&i)) || token.kind != TokenKind::Word {
+            continue;
+        }
+        if let Some(plan) = plan_at(tokens, i) {
+            suite.plan = plan;
+        } else if token.text == "done_testing" && suite.plan == TestPlan::None {
+            suite.plan = TestPlan::DoneTesting;
+        } else if token.text == "subtest" {
+            let Some((name, body)) = subtest_at(tokens, i) else {
+                continue;
+            };
+            let (first, last) = (&tokens[body.start], &tokens[body.end - 1]);
+            suite.subtests.push(Subtest {
+                name,
+                line_start: token.line,
+                line_end: last.line,
+                assertions: (body.start..body.end).filter(|&k| is_assertion(tokens, k)).count(),
+                helpers: calls_in(&content[first.start..last.end], module),
+            });
+            subtest_ranges.push(body);
+        } else if is_assertion(tokens, i) && !subtest_ranges.iter().any(|r| r.contains(&i)) {
+            suite.assertions += 1;
+        }
+    }
+    Some(suite)
+}
+
+/// `plan tests => 3`, `plan skip_all => '...'` or `use Test::More tests => 3`
+fn plan_at(tokens: &[Token], i: usize) -> Option<TestPlan> {
+    let token = &tokens[i];
+    let loads_test_module = token.is_word("use") && tokens.get(i + 1).is_some_and(|t| t.text.starts_with("Test"));
+    if !(token.is_word("plan") || loads_test_module) {
+        return None;
+    }
+    let args = statement(tokens, i + 1);
+    if args.iter().any(|t| t.is_word("skip_all") || t.text.contains("skip_all")) {
+        return Some(TestPlan::SkipAll);
+    }
+    let count = args
+        .windows(3)
+        .find(|w| (w[0].is_word("tests") || lexer::string_contents(w[0].text) == Some("tests")) && w[1].is_op("=>"))
+        .and_then(|w| w[2].text.parse().ok())?;
+    Some(TestPlan::Tests(count))
+}
+
+/// The name and body (from the opening to the closing brace) of
+/// `subtest 'name' => sub { ... }` at `i`
+fn subtest_at(tokens: &[Token], i: usize) -> Option<(String, Range<usize>)> {
+    let name = tokens.get(i + 1).filter(|t| t.kind == TokenKind::OpenParen).map_or(i + 1, |_| i + 2);
+    let name = tokens.get(name)?;
+    let name = match name.kind {
+        TokenKind::String | TokenKind::InterpolatedString => lexer::string_contents(name.text)?.to_string(),
+        _ => name.text.to_string(),
+    };
+    let sub = i + tokens[i..].iter().position(|t| t.is_word("sub") || t.is_op(";"))?;
+    if !tokens[sub].is_word("sub") || tokens.get(sub + 1)?.kind != TokenKind::OpenBrace {
+        return None;
+    }
+    let close = matching_brace(tokens, sub + 1)?;
+    Some((name, sub + 1..close + 1))
+}
+
+/// A call of an assertion function, not a method or a hash key
+fn is_assertion(tokens: &[Token], i: usize) -> bool {
+    let token = &tokens[i];
+    if token.kind != TokenKind::Word || !ASSERTIONS.contains(&token.text) {
+        return false;
+    }
+    let after_arrow = i.checked_sub(1).is_some_and(|p| tokens[p].is_op("->"));
+    let is_key = tokens.get(i + 1).is_some_and(|t| t.is_op("=>"));
+    !after_arrow && !is_key
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use crate::domain::models::{FileKind, TestPlan};
+    use crate::parser::StaticModuleParser;
+
+    #[test]
+    fn test_test_file_structure() {
+        let content = r#"use strict;
+use Test::More tests => 5;
+use Test::Exception;
+
+my $parser = new_parser();
+ok($parser, 'created');
+
+subtest 'parsing' => sub {
+    my $result = parse_with($parser, "a=1");
+    is($result->{a}, 1, 'value');
+    is_deeply([keys %$result], ['a']);
+};
+
+subtest errors => sub {
+    dies_ok { parse_with($parser, "=") } 'dies';
+};
+
+sub new_parser { return bless {}, 'Parser' }
+sub parse_with { my ($p, $text) = @_; ok(1); return { split /=/, $text } }
+"#;
+        let module = StaticModuleParser::new().parse_source(Path::new("t/parser.t"), content).remove(0);
+        assert_eq!(module.name, "main");
+        assert_eq!(module.file_kind, FileKind::Test);
+
+        let suite = module.test_suite.expect("a test suite");
+        assert_eq!(suite.frameworks, vec!["Test::More", "Test::Exception"]);
+        assert_eq!(suite.plan, TestPlan::Tests(5));
+        assert_eq!(suite.assertions, 1);
+
+        let subtests: Vec<_> = suite.subtests.iter().map(|s| (s.name.as_str(), s.line_start, s.line_end, s.assertions)).collect();
+        assert_eq!(subtests, vec![("parsing", 8, 12, 2), ("errors", 14, 16, 1)]);
+        assert_eq!(suite.subtests[0].helpers, vec!["parse_with"]);
+    }
+
+    #[test]
+    fn test_scripts_keep_main() {
+        let content = "#!/usr/bin/perl\nuse strict;\nmy @rows = load(shift);\nprint report(@rows);\nsub load { return () }\nsub report { return scalar @_ }\n";
+        let module = StaticModuleParser::new().parse_source(Path::new("bin/monthly-report"), content).remove(0);
+        assert_eq!(module.name, "main");
+        assert_eq!(module.file_kind, FileKind::Script);
+        assert!(module.test_suite.is_none());
+        assert_eq!(module.namespace(), "MonthlyReport");
+        assert_eq!(module.special_blocks[0].calls, vec!["load", "report"]);
+    }
+}
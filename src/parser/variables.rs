@@ -0,0 +1,354 @@
+use std::ops::Range;
+use crate::domain::models::{PerlModule, SharedVariable, VariableScope};
+use super::lexer::{self, Token, TokenKind};
+
+/// Interpreter-wide variables whose state is shared by every module
+const GLOBAL_VARIABLES: &[&str] = &["%ENV", "@ARGV", "%SIG", "@INC", "%INC", "$0", "$/", "$\\", "$,", "$|"];
+
+/// Package variables that describe the module rather than hold its state
+pub(crate) const METADATA_VARIABLES: &[&str] = &["@ISA", "@EXPORT", "@EXPORT_OK", "%EXPORT_TAGS", "$VERSION", "$AUTOLOAD"];
+
+const ASSIGNMENT_OPS: &[&str] = &[
+    "=", "+=", "-=", "*=", "/=", ".=", "%=", "x=", "**=", "||=", "&&=", "//=", "|=", "&=", "^=", "<<=", ">>=",
+];
+
+/// Functions that modify the variable passed to them
+const MUTATORS: &[&str] = &["push", "pop", "shift", "unshift", "splice", "delete", "undef", "chomp", "chop", "local"];
+
+/// Variables of `package` that outlive a single subroutine call.
+///
+/// `my` and `state` declarations outside named subroutines are file-scoped
+/// lexicals (this includes variables closed over by a block of subs); `our`,
+/// `use vars` and variables qualified with the package name are package
+/// variables. `in_package` tells whether a token belongs to the package.
+pub(crate) fn declared_variables(
+    tokens: &[Token],
+    in_package: impl Fn(usize) -> bool,
+    sub_ranges: &[Range<usize>],
+    package: &str,
+) -> Vec<SharedVariable> {
+    let mut variables: Vec<SharedVariable> = Vec::new();
+    let mut declare = |name: String, scope: VariableScope, line: usize| {
+        if !METADATA_VARIABLES.contains(&name.as_str()) && !variables.iter().any(|v| v.name == name) {
+            variables.push(SharedVariable { name, scope, line: Some(line) });
+        }
+    };
+
+    for (i, token) in tokens.iter().enumerate() {
+        if !in_package(i) {
+            continue;
+        }
+        let in_sub = sub_ranges.iter().any(|range| range.contains(&i));
+        let after_loop = i > 0 && (tokens[i - 1].is_word("for") || tokens[i - 1].is_word("foreach"));
+
+        match token.kind {
+            TokenKind::Word if token.text == "our" => {
+                for name in declared_names(tokens, i + 1) {
+                    declare(name.to_string(), VariableScope::Package, token.line);
+                }
+            }
+            TokenKind::Word if (token.text == "my" || token.text == "state") && !in_sub && !after_loop => {
+                for name in declared_names(tokens, i + 1) {
+                    declare(name.to_string(), VariableScope::Lexical, token.line);
+                }
+            }
+            TokenKind::Word if token.text == "vars" && i > 0 && tokens[i - 1].is_word("use") => {
+                for arg in tokens[i + 1..].iter().take_while(|t| !t.is_op(";")) {
+                    if arg.kind == TokenKind::QuoteWords {
+                        for name in lexer::quote_words(arg.text) {
+                            declare(name, VariableScope::Package, token.line);
+                        }
+                    }
+                }
+            }
+            TokenKind::Variable => {
+                if let Some(name) = own_package_variable(token.text, package) {
+                    declare(canonical_name(tokens, i, &name), VariableScope::Package, token.line);
+                }
+            }
+            _ => {}
+        }
+    }
+    variables
+}
+
+/// Record which shared variables each subroutine reads and writes.
+///
+/// Usage is matched against `module.variables`; globals such as `%ENV` are
+/// added to `module.variables` the first time a subroutine uses them.
+/// Variables a subroutine declares itself with `my` or `state` shadow the
+/// shared ones of the same name.
+pub(crate) fn record_usage(module: &mut PerlModule) {
+    for sub in &mut module.subroutines {
+        let tokens = lexer::significant_tokens(&sub.code);
+        let Some(open) = tokens.iter().position(|t| t.kind == TokenKind::OpenBrace) else {
+            continue;
+        };
+        // Signature parameters are locals as well
+        let mut locals: Vec<String> = tokens[..open]
+            .iter()
+            .filter(|t| t.kind == TokenKind::Variable)
+            .map(|t| t.text.to_string())
+            .collect();
+        locals.extend(lexicals(&tokens));
+        (sub.reads, sub.writes) = usage(&tokens[open + 1..], &locals, &module.name, &mut module.variables);
+    }
+}
+
+/// Shared variables read and written by code outside any subroutine.
+///
+/// With `block_scoped`, `my` and `state` variables are local to the code (as
+/// in a `BEGIN` block); otherwise they are the file-scoped lexicals it
+/// declares. Globals are added to `module.variables` as in `record_usage`.
+pub(crate) fn code_usage(code: &str, block_scoped: bool, module: &mut PerlModule) -> (Vec<String>, Vec<String>) {
+    let tokens = lexer::significant_tokens(code);
+    let locals = if block_scoped { lexicals(&tokens) } else { Vec::new() };
+    usage(&tokens, &locals, &module.name, &mut module.variables)
+}
+
+/// Variables declared with `my` or `state` anywhere in `tokens`
+fn lexicals(tokens: &[Token]) -> Vec<String> {
+    let mut locals = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        if token.is_word("my") || token.is_word("state") {
+            locals.extend(declared_names(tokens, i + 1).into_iter().map(str::to_string));
+        }
+    }
+    locals
+}
+
+/// Shared variables of `package` read and written in `tokens`, ignoring `locals`
+fn usage(tokens: &[Token], locals: &[String], package: &str, variables: &mut Vec<SharedVariable>) -> (Vec<String>, Vec<String>) {
+    let mut reads = Vec::new();
+    let mut writes = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        let usages = match token.kind {
+            TokenKind::Variable if !token.text.starts_with(['&', '*']) => {
+                let text = own_package_variable(token.text, package).unwrap_or_else(|| token.text.to_string());
+                let name = canonical_name(tokens, i, &text);
+                let (read, write) = access(tokens, i);
+                vec![(name, read, write)]
+            }
+            TokenKind::InterpolatedString | TokenKind::Regex | TokenKind::Substitution => {
+                interpolated_variables(token.text).into_iter().map(|name| (name, true, false)).collect()
+            }
+            _ => continue,
+        };
+
+        for (name, read, write) in usages {
+            if locals.contains(&name) {
+                continue;
+            }
+            let known = variables.iter().any(|v| v.name == name);
+            if !known && !GLOBAL_VARIABLES.contains(&name.as_str()) {
+                continue;
+            }
+            if !known {
+                variables.push(SharedVariable { name: name.clone(), scope: VariableScope::Global, line: None });
+            }
+            if read && !reads.contains(&name) {
+                reads.push(name.clone());
+            }
+            if write && !writes.contains(&name) {
+                writes.push(name);
+            }
+        }
+    }
+    (reads, writes)
+}
+
+/// Variables declared by `my`, `our` or `state` when the declared list starts at `from`
+fn declared_names<'a>(tokens: &[Token<'a>], from: usize) -> Vec<&'a str> {
+    match tokens.get(from) {
+        Some(t) if t.kind == TokenKind::Variable => vec![t.text],
+        Some(t) if t.kind == TokenKind::OpenParen => tokens[from + 1..]
+            .iter()
+            .take_while(|t| t.kind != TokenKind::CloseParen)
+            .filter(|t| t.kind == TokenKind::Variable)
+            .map(|t| t.text)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// `$Foo::bar` as `$bar` when `Foo` is `package`
+fn own_package_variable(text: &str, package: &str) -> Option<String> {
+    let (sigil, name) = text.split_at(1);
+    let (qualifier, short) = name.rsplit_once("::")?;
+    (qualifier == package && !short.is_empty()).then(|| format!("{}{}", sigil, short))
+}
+
+/// The variable a token refers to: `$x[0]` and `$#x` are `@x`, `$x{k}` and `@x{...}` are `%x`
+fn canonical_name(tokens: &[Token], i: usize, text: &str) -> String {
+    if let Some(name) = text.strip_prefix("$#") {
+        return format!("@{}", name);
+    }
+    let (sigil, name) = text.split_at(1);
+    let next = tokens.get(i + 1).map(|t| t.kind);
+    match (sigil, next) {
+        ("$", Some(TokenKind::OpenBracket)) => format!("@{}", name),
+        ("$" | "@", Some(TokenKind::OpenBrace)) => format!("%{}", name),
+        _ => text.to_string(),
+    }
+}
+
+/// Whether the variable at `i` is read, written or both
+fn access(tokens: &[Token], i: usize) -> (bool, bool) {
+    let end = subscripts_end(tokens, i + 1);
+    let next = tokens.get(end);
+    let prev = i.checked_sub(1).map(|p| &tokens[p]);
+    // `push(@x, ...)` as well as `push @x, ...`
+    let mutator = match prev {
+        Some(t) if t.kind == TokenKind::OpenParen => i.checked_sub(2).map(|p| &tokens[p]),
+        other => other,
+    }
+    .filter(|t| t.kind == TokenKind::Word && MUTATORS.contains(&t.text));
+
+    if let Some(op) = next.filter(|t| t.kind == TokenKind::Operator && ASSIGNMENT_OPS.contains(&t.text)) {
+        return (op.text != "=", true);
+    }
+    if next.is_some_and(|t| t.is_op("++") || t.is_op("--")) || prev.is_some_and(|t| t.is_op("++") || t.is_op("--")) {
+        return (true, true);
+    }
+    if next.is_some_and(|t| t.is_op("=~")) && tokens.get(end + 1).is_some_and(|t| t.kind == TokenKind::Substitution) {
+        return (true, true);
+    }
+    match mutator {
+        Some(word) if matches!(word.text, "local" | "undef") => (false, true),
+        Some(_) => (true, true),
+        None => (true, false),
+    }
+}
+
+/// Index of the first token after the subscripts (`[...]`, `{...}`, `->[...]`) starting at `from`
+fn subscripts_end(tokens: &[Token], mut from: usize) -> usize {
+    loop {
+        let mut open = from;
+        if tokens.get(open).is_some_and(|t| t.is_op("->")) {
+            open += 1;
+        }
+        let close = match tokens.get(open).map(|t| t.kind) {
+            Some(TokenKind::OpenBracket) => TokenKind::CloseBracket,
+            Some(TokenKind::OpenBrace) => TokenKind::CloseBrace,
+            _ => return from,
+        };
+        let mut depth = 0usize;
+        let mut end = None;
+        for (offset, token) in tokens[open..].iter().enumerate() {
+            if token.kind == tokens[open].kind {
+                depth += 1;
+            } else if token.kind == close {
+                depth -= 1;
+                if depth == 0 {
+                    end = Some(open + offset);
+                    break;
+                }
+            }
+        }
+        match end {
+            Some(end) => from = end + 1,
+            None => return tokens.len(),
+        }
+    }
+}
+
+/// Variables interpolated in a string or regex, named as in `canonical_name`
+fn interpolated_variables(text: &str) -> Vec<String> {
+    let bytes = text.as_bytes();
+    let mut names = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let sigil = bytes[i];
+        if sigil == b'\\' {
+            i += 2;
+            continue;
+        }
+        i += 1;
+        if sigil != b'$' && sigil != b'@' {
+            continue;
+        }
+        let braced = bytes.get(i) == Some(&b'{');
+        let start = if braced { i + 1 } else { i };
+        let mut end = start;
+        while end < bytes.len() && (bytes[end].is_ascii_alphanumeric() || bytes[end] == b'_' || text[end..].starts_with("::")) {
+            end += if bytes[end] == b':' { 2 } else { 1 };
+        }
+        if end == start || (braced && bytes.get(end) != Some(&b'}')) {
+            continue;
+        }
+        let name = &text[start..end];
+        i = if braced { end + 1 } else { end };
+        let name = match (sigil, bytes.get(i)) {
+            (b'$', Some(b'[')) => format!("@{}", name),
+            (_, Some(b'{')) => format!("%{}", name),
+            _ => format!("{}{}", sigil as char, name),
+        };
+        names.push(name);
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use crate::domain::models::VariableScope;
+    use crate::parser::StaticModuleParser;
+
+    #[test]
+    fn test_shared_state_reads_and_writes() {
+        let content = r#"package Cache;
+use vars qw($hits);
+
+our $DEBUG = 0;
+my %cache;
+my $dsn = "dbi:SQLite:cache.db";
+{
+    my $counter = 0;
+    sub next_id { return ++$counter }
+}
+
+sub lookup {
+    my ($key) = @_;
+    $hits++ if exists $cache{$key};
+    warn "lookup $key in $dsn\n" if $Cache::DEBUG;
+    return $cache{$key} //= fetch($key);
+}
+
+sub fetch {
+    my ($key) = @_;
+    my %cache = (local => 1);
+    return $ENV{CACHE_DEFAULT} . $cache{local};
+}
+
+sub clear { %cache = (); delete $ENV{CACHE_DEFAULT}; local $/ = undef; return }
+
+1;
+"#;
+        let module = StaticModuleParser::new().parse_source(Path::new("Cache.pm"), content).remove(0);
+
+        let variables: Vec<_> = module.variables.iter()
+            .map(|v| (v.name.as_str(), v.scope, v.line))
+            .collect();
+        assert_eq!(variables, vec![
+            ("$hits", VariableScope::Package, Some(2)),
+            ("$DEBUG", VariableScope::Package, Some(4)),
+            ("%cache", VariableScope::Lexical, Some(5)),
+            ("$dsn", VariableScope::Lexical, Some(6)),
+            ("$counter", VariableScope::Lexical, Some(8)),
+            ("%ENV", VariableScope::Global, None),
+            ("$/", VariableScope::Global, None),
+        ]);
+
+        let sub = |name: &str| module.subroutines.iter().find(|s| s.name == name).unwrap();
+        assert_eq!(sub("next_id").reads, vec!["$counter"]);
+        assert_eq!(sub("next_id").writes, vec!["$counter"]);
+        assert_eq!(sub("lookup").reads, vec!["$hits", "%cache", "$dsn", "$DEBUG"]);
+        assert_eq!(sub("lookup").writes, vec!["$hits", "%cache"]);
+        // The local %cache shadows the file-scoped one
+        assert_eq!(sub("fetch").reads, vec!["%ENV"]);
+        assert!(sub("fetch").writes.is_empty());
+        assert_eq!(sub("clear").writes, vec!["%cache", "%ENV", "$/"]);
+        assert!(sub("clear").reads.contains(&"%ENV".to_string()));
+        assert!(sub("clear").uses_variable("$/"));
+    }
+}
@@ -0,0 +1,273 @@
+use crate::domain::models::{Diagnostic, DiagnosticKind, PerlModule, Severity, Subroutine};
+use super::StaticModuleParser;
+
+/// Check every package reported for a file against its source.
+///
+/// Each module is verified with `verify_module`, and packages found in the
+/// source but missing from `modules` are appended as parsed by the
+/// `StaticModuleParser`.
+pub fn verify_packages(modules: &mut Vec<PerlModule>) {
+    let Some(first) = modules.first() else {
+        return;
+    };
+    let reference = StaticModuleParser::new().parse_source(&first.path, &first.content);
+
+    for module in modules.iter_mut() {
+        verify_against(module, &reference);
+        adopt_static_analysis(module, &reference);
+    }
+
+    for mut package in reference {
+        if modules.iter().any(|m| m.name == package.name) {
+            continue;
+        }
+        package.diagnostics.push(Diagnostic {
+            severity: Severity::Warning,
+            kind: DiagnosticKind::PackageAdded,
+            message: format!("Package '{}' was missing and has been added from the source", package.name),
+            subroutine: None,
+            span: None,
+        });
+        modules.push(package);
+    }
+}
+
+/// Check the subroutines of a module against its source.
+///
+/// Every subroutine is looked up in the matching package of `module.content`:
+/// line ranges are fixed, `code` is replaced with the exact source text,
+/// subroutines that do not exist are dropped (also from responsibility
+/// clusters) and subroutines that were missed are added. Each correction is
+/// recorded in `module.diagnostics`. Exports, class structure, attributes,
+/// documentation, special blocks, the data section, shared variables, the
+/// Test::More structure and each subroutine's kind, POD, reads and writes
+/// are taken from the source as well.
+pub fn verify_module(module: &mut PerlModule) {
+    let reference = StaticModuleParser::new().parse_source(&module.path, &module.content);
+    verify_against(module, &reference);
+    adopt_static_analysis(module, &reference);
+}
+
+/// The package of the source that `module` describes
+fn matching_package<'a>(module: &PerlModule, packages: &'a [PerlModule]) -> Option<&'a PerlModule> {
+    packages
+        .iter()
+        .find(|p| p.name == module.name)
+        .or_else(|| packages.first().filter(|_| packages.len() == 1))
+}
+
+fn verify_against(module: &mut PerlModule, packages: &[PerlModule]) {
+    let reference = matching_package(module, packages)
+        .map(|p| p.subroutines.clone())
+        .unwrap_or_default();
+    let mut unmatched: Vec<Option<Subroutine>> = reference.into_iter().map(Some).collect();
+    let mut verified = Vec::new();
+    let mut diagnostics = Vec::new();
+    let mut removed = Vec::new();
+
+    for mut sub in std::mem::take(&mut module.subroutines) {
+        let Some(actual) = unmatched
+            .iter_mut()
+            .find(|s| s.as_ref().is_some_and(|s| s.name == sub.name))
+            .and_then(Option::take)
+        else {
+            diagnostics.push(diagnostic(
+                Severity::Warning,
+                DiagnosticKind::SubroutineRemoved,
+                format!("Subroutine '{}' was reported but does not exist in the source", sub.name),
+                &sub,
+            ));
+            removed.push(sub.name);
+            continue;
+        };
+
+        if (sub.line_start, sub.line_end) != (actual.line_start, actual.line_end) {
+            diagnostics.push(diagnostic(
+                Severity::Info,
+                DiagnosticKind::LineRangeCorrected,
+                format!(
+                    "Lines of '{}' corrected from {}-{} to {}-{}",
+                    sub.name, sub.line_start, sub.line_end, actual.line_start, actual.line_end
+                ),
+                &actual,
+            ));
+            sub.line_start = actual.line_start;
+            sub.line_end = actual.line_end;
+        }
+
+        if sub.code != actual.code {
+            diagnostics.push(diagnostic(
+                Severity::Info,
+                DiagnosticKind::CodeReplaced,
+                format!("Code of '{}' replaced with the exact source text", sub.name),
+                &actual,
+            ));
+            sub.code = actual.code;
+        }
+        sub.span = actual.span;
+        sub.pod_span = actual.pod_span;
+
+        verified.push(sub);
+    }
+
+    for sub in unmatched.into_iter().flatten() {
+        diagnostics.push(diagnostic(
+            Severity::Warning,
+            DiagnosticKind::SubroutineAdded,
+            format!("Subroutine '{}' was missing and has been added from the source", sub.name),
+            &sub,
+        ));
+        verified.push(sub);
+    }
+
+    verified.sort_by_key(|s| s.line_start);
+
+    if !removed.is_empty() {
+        for cluster in &mut module.responsibility_clusters {
+            cluster.related_subroutines.retain(|name| !removed.contains(name));
+        }
+    }
+
+    module.subroutines = verified;
+    module.diagnostics.extend(diagnostics);
+}
+
+/// Copy what only the static analysis knows from the matching package
+fn adopt_static_analysis(module: &mut PerlModule, packages: &[PerlModule]) {
+    let Some(reference) = matching_package(module, packages) else {
+        return;
+    };
+    module.dependencies = reference.dependencies.clone();
+    module.variables = reference.variables.clone();
+    module.exports = reference.exports.clone();
+    module.class = reference.class.clone();
+    module.attributes = reference.attributes.clone();
+    module.documentation = reference.documentation.clone();
+    module.special_blocks = reference.special_blocks.clone();
+    module.data_section = reference.data_section.clone();
+    module.hazards = reference.hazards.clone();
+    module.file_kind = reference.file_kind;
+    module.test_suite = reference.test_suite.clone();
+    for sub in &mut module.subroutines {
+        if let Some(actual) = reference.subroutines.iter().find(|s| s.name == sub.name) {
+            sub.reads = actual.reads.clone();
+            sub.writes = actual.writes.clone();
+            sub.kind = actual.kind;
+            sub.pod = actual.pod.clone();
+            sub.signature = actual.signature.clone();
+            sub.hazards = actual.hazards.clone();
+            sub.metrics = actual.metrics.clone();
+        }
+    }
+}
+
+fn diagnostic(severity: Severity, kind: DiagnosticKind, message: String, subroutine: &Subroutine) -> Diagnostic {
+    Diagnostic {
+        severity,
+        kind,
+        message,
+        subroutine: Some(subroutine.name.clone()),
+        span: subroutine.span,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::models::ResponsibilityCluster;
+
+    const CONTENT: &str = "package Shapes;\n\nsub area {\n    my ($w, $h) = @_;\n    return $w * $h;\n}\n\nsub perimeter {\n    my ($w, $h) = @_;\n    return 2 * ($w + $h);\n}\n\n1;\n";
+
+    fn reported(name: &str, code: &str, line_start: usize, line_end: usize) -> Subroutine {
+        Subroutine {
+            name: name.to_string(),
+            code: code.to_string(),
+            line_start,
+            line_end,
+            dependencies: vec![],
+            ..Default::default()
+        }
+    }
+
+    fn module(subroutines: Vec<Subroutine>) -> PerlModule {
+        PerlModule {
+            name: "Shapes".to_string(),
+            path: "Shapes.pm".into(),
+            content: CONTENT.to_string(),
+            subroutines,
+            dependencies: vec![],
+            responsibility_clusters: vec![ResponsibilityCluster {
+                name: "Geometry".to_string(),
+                description: "Shape measurements".to_string(),
+                related_subroutines: vec!["area".to_string(), "volume".to_string()],
+                suggested_module_name: None,
+                confidence: 0.9,
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_verify_corrects_lines_and_code() {
+        let mut module = module(vec![reported("area", "sub area {\n    my ($w, $h) = @_;", 2, 4)]);
+        verify_module(&mut module);
+
+        let area = &module.subroutines[0];
+        assert_eq!((area.line_start, area.line_end), (3, 6));
+        assert_eq!(area.code, "sub area {\n    my ($w, $h) = @_;\n    return $w * $h;\n}");
+
+        let kinds: Vec<_> = module.diagnostics.iter().map(|d| d.kind).collect();
+        assert!(kinds.contains(&DiagnosticKind::LineRangeCorrected));
+        assert!(kinds.contains(&DiagnosticKind::CodeReplaced));
+    }
+
+    #[test]
+    fn test_verify_drops_invented_and_adds_missed_subroutines() {
+        let mut module = module(vec![reported("volume", "sub volume { }", 20, 22)]);
+        verify_module(&mut module);
+
+        let names: Vec<_> = module.subroutines.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["area", "perimeter"]);
+        assert_eq!(module.responsibility_clusters[0].related_subroutines, vec!["area"]);
+
+        let removed = module.diagnostics.iter()
+            .filter(|d| d.kind == DiagnosticKind::SubroutineRemoved)
+            .count();
+        let added = module.diagnostics.iter()
+            .filter(|d| d.kind == DiagnosticKind::SubroutineAdded)
+            .count();
+        assert_eq!((removed, added), (1, 2));
+    }
+
+    #[test]
+    fn test_verify_packages_matches_by_name_and_adds_missing() {
+        let content = "package Outer;\nsub outer { 1 }\n\npackage Outer::Helper;\nsub help { 2 }\n1;\n";
+        let mut modules = vec![PerlModule {
+            name: "Outer::Helper".to_string(),
+            path: "Outer.pm".into(),
+            content: content.to_string(),
+            subroutines: vec![reported("help", "sub help { 2 }", 5, 5)],
+            dependencies: vec![],
+            responsibility_clusters: vec![],
+            ..Default::default()
+        }];
+        verify_packages(&mut modules);
+
+        assert!(modules[0].diagnostics.is_empty());
+        assert_eq!(modules[1].name, "Outer");
+        assert_eq!(modules[1].subroutines[0].name, "outer");
+        assert_eq!(modules[1].diagnostics[0].kind, DiagnosticKind::PackageAdded);
+    }
+
+    #[test]
+    fn test_verify_accepts_exact_report() {
+        let mut module = module(vec![
+            reported("area", "sub area {\n    my ($w, $h) = @_;\n    return $w * $h;\n}", 3, 6),
+            reported("perimeter", "sub perimeter {\n    my ($w, $h) = @_;\n    return 2 * ($w + $h);\n}", 8, 11),
+        ]);
+        verify_module(&mut module);
+
+        assert!(module.diagnostics.is_empty());
+        assert_eq!(module.subroutines.len(), 2);
+    }
+}
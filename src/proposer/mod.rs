@@ -1,20 +1,32 @@
 use async_trait::async_trait;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::ops::Range;
 use rig::agent::Agent;
 use rig::agent::AgentBuilder;
 use rig::completion::{CompletionModel, Prompt};
-//use tokio::sync::Mutex;
 
+use crate::analyzer::{helper_module, SharedModule};
 use crate::domain::{
-    models::{PerlModule, ResponsibilityCluster, RefactoringProposal, NewModuleProposal, RefactoringImpact},
+    models::{ClassInfo, CloneKind, Exports, FileKind, LoadPhase, ModuleKind, PerlModule, ResponsibilityCluster, RefactoringProposal, NewModuleProposal, RefactoringImpact, SubroutineKind},
     traits::RefactoringProposer,
 };
+use crate::cancel::Cancellation;
 use crate::error::Error;
+use crate::metrics::proposal_metrics;
+use crate::validator::{
+    attribute_conflicts, class_boundary_conflicts, hazard_conflicts, load_time_conflicts, prototype_conflicts, shared_state_conflicts,
+};
 
 /// Default implementation of the RefactoringProposer trait
 pub struct DefaultRefactoringProposer;
 
+impl Default for DefaultRefactoringProposer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl DefaultRefactoringProposer {
     pub fn new() -> Self {
         Self
@@ -35,8 +47,8 @@ impl RefactoringProposer for DefaultRefactoringProposer {
 /// AI-powered implementation of the RefactoringProposer trait
 pub struct AIRefactoringProposer<M: CompletionModel> {
     agent: Agent<M>,
-    // Cancel channel for future async operation cancellation
-    //cancel: Mutex<Option<tokio::sync::oneshot::Sender<()>>>,
+    cancellation: Cancellation,
+    extract_clones: bool,
 }
 
 impl<M: CompletionModel> AIRefactoringProposer<M> {
@@ -45,29 +57,39 @@ impl<M: CompletionModel> AIRefactoringProposer<M> {
             agent: agent_builder
                 .preamble("You are a Perl refactoring expert. You will generate clean, well-structured Perl modules based on responsibility clusters.")
                 .build(),
-            //cancel: Mutex::new(None),
+            cancellation: Cancellation::new(),
+            extract_clones: false,
         }
     }
 
-    async fn generate_module_code(&self, original_module: &PerlModule, cluster: &ResponsibilityCluster) -> Result<String, Error> {
+    /// Stop generating once `cancellation` is cancelled. Modules generated
+    /// before that are kept in the proposal, which is marked `cancelled`.
+    pub fn with_cancellation(mut self, cancellation: Cancellation) -> Self {
+        self.cancellation = cancellation;
+        self
+    }
+
+    /// Move one copy of every group of cloned subroutines into a shared
+    /// `{namespace}::Shared` module, built before the other modules so that
+    /// they leave it out
+    pub fn with_clone_extraction(mut self, extract_clones: bool) -> Self {
+        self.extract_clones = extract_clones;
+        self
+    }
+
+    async fn generate_module_code(&self, original_module: &PerlModule, cluster: &ResponsibilityCluster, kind: ModuleKind) -> Result<String, Error> {
         // Get all subroutines in this cluster
         let mut subroutines = Vec::new();
-        let mut dependencies = HashSet::new();
 
         for sub_name in &cluster.related_subroutines {
             if let Some(sub) = original_module.subroutines.iter().find(|s| &s.name == sub_name) {
                 subroutines.push(sub);
-                
-                // Collect dependencies from each subroutine
-                for dep in &sub.dependencies {
-                    dependencies.insert(dep.clone());
-                }
             }
         }
 
         // Get module name
         let module_name = cluster.suggested_module_name.clone()
-            .unwrap_or_else(|| format!("{}::{}", original_module.name, cluster.name.replace(" ", "")));
+            .unwrap_or_else(|| format!("{}::{}", original_module.namespace(), cluster.name.replace(" ", "")));
 
         // Format subroutines as text
         let subroutines_text = subroutines.iter()
@@ -75,11 +97,160 @@ impl<M: CompletionModel> AIRefactoringProposer<M> {
             .collect::<Vec<_>>()
             .join("\n\n");
 
+        // Declarations of the shared variables these subroutines use
+        let lines: Vec<&str> = original_module.content.lines().collect();
+        let shared_state_text = original_module.variables.iter()
+            .filter(|v| subroutines.iter().any(|s| s.uses_variable(&v.name)))
+            .map(|v| match v.line.and_then(|line| lines.get(line - 1)) {
+                Some(declaration) => format!("{} (declared as: {})", v.name, declaration.trim()),
+                None => format!("{} (global)", v.name),
+            })
+            .collect::<Vec<_>>();
+        let shared_state_text = if shared_state_text.is_empty() {
+            "None".to_string()
+        } else {
+            shared_state_text.join("\n            ")
+        };
+
+        // The `use` statements these subroutines need, with only the imports they use
+        let dependencies_text = original_module.dependencies_of(&subroutines.iter().map(|s| s.name.as_str()).collect::<Vec<_>>())
+            .iter()
+            .map(|d| d.declaration())
+            .collect::<Vec<_>>();
+        let dependencies_text = if dependencies_text.is_empty() {
+            "None".to_string()
+        } else {
+            dependencies_text.join("\n            ")
+        };
+
+        // The part of the original public interface these subroutines make up
+        let names: Vec<&str> = subroutines.iter().map(|s| s.name.as_str()).collect();
+        let exports_text = if original_module.is_script() {
+            format!(
+                "None: the original is a script that calls them directly. Export all of them with Exporter's @EXPORT_OK, because the script will import them with `use {} qw({})`",
+                module_name, names.join(" ")
+            )
+        } else {
+            exports_text(&original_module.exports.restricted_to(&names))
+        };
+
+        // Attributes that move with these subroutines, and those they use that stay behind
+        let owned = original_module.attributes_owned_by(&names);
+        let mut owned_code: Vec<&str> = Vec::new();
+        for attribute in &owned {
+            if !owned_code.contains(&attribute.code.as_str()) {
+                owned_code.push(&attribute.code);
+            }
+        }
+        let remaining: Vec<&str> = original_module.attributes.iter()
+            .filter(|a| !owned.iter().any(|o| o.name == a.name))
+            .filter(|a| a.linked_subroutines().iter().any(|s| names.contains(s)))
+            .map(|a| a.name.as_str())
+            .collect();
+        let mut attributes_text = Vec::new();
+        if !owned_code.is_empty() {
+            attributes_text.push(format!("Declared in the new module, exactly as in the original:\n            {}", owned_code.join("\n            ")));
+        }
+        if !remaining.is_empty() {
+            attributes_text.push(format!(
+                "Used but staying in {} (use their accessors; a role lists them with `requires`): {}",
+                original_module.name, remaining.join(", ")
+            ));
+        }
+        let attributes_text = if attributes_text.is_empty() {
+            "None".to_string()
+        } else {
+            attributes_text.join("\n            ")
+        };
+
+        // Code that runs at load time and the DATA section: move what only these
+        // subroutines need, leave what others need too
+        let mut load_time_text = Vec::new();
+        let owned_blocks = original_module.blocks_owned_by(&names);
+        if !owned_blocks.is_empty() {
+            let code = owned_blocks.iter().map(|b| b.code.as_str()).collect::<Vec<_>>().join("\n            ");
+            load_time_text.push(format!("Move into the new module, exactly as in the original:\n            {}", code));
+        }
+        let shared_blocks: Vec<String> = original_module.special_blocks.iter()
+            .filter(|b| !owned_blocks.iter().any(|o| o.line_start == b.line_start))
+            .filter(|b| b.linked_subroutines().iter().any(|s| names.contains(s)))
+            .map(|b| format!("{} at lines {}-{}", b.kind.label(), b.line_start, b.line_end))
+            .collect();
+        if !shared_blocks.is_empty() {
+            load_time_text.push(format!(
+                "Stays in {} because other subroutines need it too; do not copy it: {}",
+                original_module.name, shared_blocks.join(", ")
+            ));
+        }
+        if let Some(data) = &original_module.data_section {
+            let readers: Vec<&str> = data.used_by.iter().map(String::as_str).filter(|s| names.contains(s)).collect();
+            if !readers.is_empty() && readers.len() == data.used_by.len() {
+                load_time_text.push(format!(
+                    "{} read from DATA: end the new module with a __DATA__ section containing exactly:\n{}",
+                    readers.join(", "), data.content
+                ));
+            } else if !readers.is_empty() {
+                load_time_text.push(format!(
+                    "{} read from DATA, which stays in {}; read it as {}::DATA instead",
+                    readers.join(", "), original_module.name, original_module.name
+                ));
+            }
+        }
+        let load_time_text = if load_time_text.is_empty() {
+            "None".to_string()
+        } else {
+            load_time_text.join("\n            ")
+        };
+
+        // The original POD that should move with these subroutines
+        let mut documentation: Vec<&str> = ["NAME", "SYNOPSIS", "DESCRIPTION"].iter()
+            .filter_map(|heading| original_module.pod_section(heading))
+            .map(|section| section.text.trim_end())
+            .collect();
+        documentation.extend(subroutines.iter().filter_map(|s| s.pod.as_deref()).map(str::trim_end));
+        let documentation_text = if documentation.is_empty() {
+            "None".to_string()
+        } else {
+            format!("```pod\n{}\n            ```", documentation.join("\n\n"))
+        };
+
+        // Declared parameters, prototypes and attributes, for the POD and the new declarations
+        let signatures: Vec<String> = subroutines.iter()
+            .map(|s| (s, s.signature.declaration()))
+            .filter(|(_, declaration)| !declaration.is_empty())
+            .map(|(s, declaration)| format!("{} {}", s.name, declaration))
+            .collect();
+        let signatures_text = if signatures.is_empty() {
+            "None".to_string()
+        } else {
+            signatures.join("\n            ")
+        };
+
+        // How the new module relates to the original class, if it is one
+        let class_text = match &original_module.class {
+            Some(class) if kind == ModuleKind::Role => format!(
+                "{} is {}. These subroutines are methods that operate on $self, so write the new module as a role using {} that {} consumes with `with`, instead of a standalone module. The role must not define a constructor.",
+                original_module.name, class_description(class), role_system(class), original_module.name
+            ),
+            Some(class) => format!(
+                "{} is {}. These subroutines do not operate on $self, so the new module does not need to be a class.",
+                original_module.name, class_description(class)
+            ),
+            None => "The original module is not a class.".to_string(),
+        };
+
+        // Library modules made from a script are loaded by it, not run
+        let original_text = match original_module.file_kind {
+            _ if !original_module.is_script() => format!("module: {}", original_module.name),
+            FileKind::Test => format!("test file: {} (the new module holds its test helpers)", original_module.path.display()),
+            _ => format!("script: {}", original_module.path.display()),
+        };
+
         // Create prompt
         let prompt = format!(
             r#"You are a Perl refactoring expert. Your task is to create a new Perl module for a specific responsibility cluster.
 
-            Original module: {}
+            Original {}
             New module name: {}
             Responsibility: {} - {}
             Confidence: {}
@@ -88,27 +259,64 @@ impl<M: CompletionModel> AIRefactoringProposer<M> {
             ```perl
             {}
             ```
+
+            Dependencies of these subroutines:
+            {}
+
+            File-scoped variables and globals these subroutines read or write:
+            {}
+
+            Public interface of these subroutines in the original module:
+            {}
+
+            Object-oriented context:
+            {}
+
+            Attributes:
+            {}
+
+            Load-time code (BEGIN/END blocks, top-level statements) and the DATA section:
+            {}
+
+            Original documentation (module-level sections and the POD of these subroutines):
+            {}
+
+            Parameters, prototypes and subroutine attributes:
+            {}
                     
             Based on this information, generate a complete, well-structured Perl module that:
             1. Has proper package declaration
-            2. Includes all necessary pragmas (strict, warnings) and dependencies
+            2. Includes the pragmas (strict, warnings) and the dependencies listed above with the import lists shown, and no other imports
             3. Contains all the subroutines provided, keeping their functionality identical
-            4. Exports the necessary subroutines
-            5. Has appropriate documentation and structure
+            4. Exports exactly the symbols listed in the public interface above, with the same exporter, defaults and tags, and nothing else
+            5. Carries over the original documentation above: each subroutine keeps its POD, NAME names the new module, and SYNOPSIS and DESCRIPTION keep only what applies to these subroutines; write new POD only where there is none, describing the parameters listed above
             6. Makes minimal changes to the actual code of each subroutine
+            7. Keeps the shared variables listed above declared at file scope, exactly as in the original
+            8. Follows the object-oriented context above
+            9. Moves the attributes listed above together with the subroutines that use them
+            10. Handles the load-time code and DATA section exactly as described above
+            11. Keeps the prototypes and subroutine attributes listed above on their subroutines
                     
             Only return the complete Perl module code with no additional explanation.
             "#,
-            original_module.name,
+            original_text,
             module_name,
             cluster.name,
             cluster.description,
             cluster.confidence,
-            subroutines_text
+            subroutines_text,
+            dependencies_text,
+            shared_state_text,
+            exports_text,
+            class_text,
+            attributes_text,
+            load_time_text,
+            documentation_text,
+            signatures_text
         );
 
         // Generate code using AI - pass the prompt as a value
-        let response = self.agent.prompt(prompt).await
+        let response = self.cancellation.run(self.agent.prompt(prompt)).await?
             .map_err(|e| Error::AIError(format!("Failed to generate module code: {}", e)))?;
 
         Ok(response)
@@ -121,9 +329,24 @@ impl<M: CompletionModel> RefactoringProposer for AIRefactoringProposer<M> {
         &self,
         module: &PerlModule,
     ) -> Result<RefactoringProposal, Error> {
-        // Skip low confidence clusters
-        let high_confidence_clusters: Vec<&ResponsibilityCluster> = module.responsibility_clusters.iter()
+        // Cloned subroutines go into a shared module of their own
+        let helper = self.extract_clones
+            .then(|| helper_module(&format!("{}::Shared", module.namespace()), std::slice::from_ref(module), &module.clones))
+            .flatten();
+        let shared = |name: &String| helper.as_ref().is_some_and(|h| h.module.subroutines.iter().any(|s| &s.name == name));
+
+        // Skip low confidence clusters, and leave dead code and shared code out of the new modules
+        let high_confidence_clusters: Vec<ResponsibilityCluster> = module.responsibility_clusters.iter()
             .filter(|c| c.confidence >= 0.7)
+            .map(|c| ResponsibilityCluster {
+                related_subroutines: c.related_subroutines
+                    .iter()
+                    .filter(|s| !module.deletion_candidates.contains(s) && !shared(s))
+                    .cloned()
+                    .collect(),
+                ..c.clone()
+            })
+            .filter(|c| !c.related_subroutines.is_empty())
             .collect();
 
         if high_confidence_clusters.is_empty() {
@@ -133,11 +356,9 @@ impl<M: CompletionModel> RefactoringProposer for AIRefactoringProposer<M> {
         // Generate new modules for each responsibility cluster
         let mut suggested_modules = Vec::new();
         let mut module_sub_map = HashMap::new();
+        let mut cancelled = false;
 
         for cluster in &high_confidence_clusters {
-            // Generate code for this module
-            let code = self.generate_module_code(module, cluster).await?;
-            
             // Get all subroutines in this cluster
             let mut subroutines = Vec::new();
             let mut dependencies = HashSet::new();
@@ -158,28 +379,69 @@ impl<M: CompletionModel> RefactoringProposer for AIRefactoringProposer<M> {
                 }
             }
 
+            // Methods that need `$self` are extracted into a role the class keeps consuming
+            let kind = if module.class.is_some() && subroutines.iter().any(|s| s.kind == SubroutineKind::InstanceMethod) {
+                ModuleKind::Role
+            } else {
+                ModuleKind::Module
+            };
+
+            // Attributes whose builder, trigger and users all move with this cluster
+            let names: Vec<&str> = subroutines.iter().map(|s| s.name.as_str()).collect();
+            let attributes = module.attributes_owned_by(&names).into_iter().cloned().collect();
+
+            // Generate code for this module; keep what is done if cancelled
+            let code = match self.generate_module_code(module, cluster, kind).await {
+                Ok(code) => code,
+                Err(Error::Cancelled) if !suggested_modules.is_empty() => {
+                    for clusters in module_sub_map.values_mut() {
+                        clusters.retain(|name| name != &cluster.name);
+                    }
+                    module_sub_map.retain(|_, clusters| !clusters.is_empty());
+                    cancelled = true;
+                    break;
+                }
+                Err(e) => return Err(e),
+            };
+
             // Determine module name
             let name = cluster.suggested_module_name.clone()
-                .unwrap_or_else(|| format!("{}::{}", module.name, cluster.name.replace(" ", "")));
+                .unwrap_or_else(|| format!("{}::{}", module.namespace(), cluster.name.replace(" ", "")));
 
             // Create new module proposal
             suggested_modules.push(NewModuleProposal {
                 name,
+                kind,
                 responsibility: cluster.description.clone(),
                 subroutines,
+                attributes,
                 dependencies: dependencies.into_iter().collect(),
                 suggested_code: code,
                 confidence: cluster.confidence,
             });
         }
 
+        // The shared module comes last, and is analyzed like the others
+        if let Some(helper) = &helper {
+            for sub in &helper.module.subroutines {
+                module_sub_map.insert(sub.name.clone(), vec![helper.module.name.clone()]);
+            }
+            suggested_modules.push(helper.module.clone());
+        }
+
         // Analyze impact of the refactoring
-        let impact = self.analyze_impact(&suggested_modules, &module_sub_map).await?;
+        let impact = self.analyze_impact(module, &suggested_modules, &module_sub_map, helper.as_ref()).await?;
+
+        // A script keeps its top-level code and imports the moved subroutines
+        let rewritten_script = module.is_script().then(|| rewrite_script(module, &suggested_modules));
 
         Ok(RefactoringProposal {
             original_module: module.clone(),
             suggested_modules,
             impact,
+            rewritten_script,
+            cancelled,
+            deletion_candidates: module.deletion_candidates.clone(),
         })
     }
 }
@@ -187,8 +449,10 @@ impl<M: CompletionModel> RefactoringProposer for AIRefactoringProposer<M> {
 impl<M: CompletionModel> AIRefactoringProposer<M> {
     async fn analyze_impact(
         &self,
+        module: &PerlModule,
         suggested_modules: &[NewModuleProposal],
-        module_sub_map: &HashMap<String, Vec<String>>
+        module_sub_map: &HashMap<String, Vec<String>>,
+        helper: Option<&SharedModule>,
     ) -> Result<RefactoringImpact, Error> {
         // Count duplicated subroutines (appear in multiple modules)
         let duplicated_subs = module_sub_map.iter()
@@ -212,22 +476,373 @@ impl<M: CompletionModel> AIRefactoringProposer<M> {
             risks.push(format!("{} subroutines appear in multiple modules, which may lead to code duplication", duplicated_subs));
         }
         
+        // Calls that will have to cross a module boundary after the split
+        let mut cross_module_calls = 0;
+        let mut calls_into_original = 0;
+        for edge in &module.call_graph.edges {
+            let (Some(caller_modules), callee_modules) = (module_sub_map.get(&edge.caller), module_sub_map.get(&edge.callee)) else {
+                continue;
+            };
+            match callee_modules {
+                Some(callee_modules) if !caller_modules.iter().any(|m| callee_modules.contains(m)) => cross_module_calls += 1,
+                None => calls_into_original += 1,
+                _ => {}
+            }
+        }
+
+        if cross_module_calls > 0 {
+            risks.push(format!("{} calls between subroutines will cross the new module boundaries", cross_module_calls));
+        }
+
+        if calls_into_original > 0 {
+            risks.push(format!("{} calls from moved subroutines target subroutines that stay in {}", calls_into_original, module.name));
+        }
+
+        // Exported subroutines that will be imported from a different module
+        let moved_exports = module.exports.symbols()
+            .into_iter()
+            .filter(|symbol| module_sub_map.contains_key(*symbol))
+            .count();
+        if moved_exports > 0 {
+            risks.push(format!(
+                "{} exported subroutines move out of {}; code that imports them has to be updated, or {} has to re-export them",
+                moved_exports, module.name, module.name
+            ));
+        }
+
+        // Methods and constructors taken away from the class that owns `$self`,
+        // and attributes used on both sides of a new module boundary
+        risks.extend(class_boundary_conflicts(module, suggested_modules));
+        risks.extend(attribute_conflicts(module, suggested_modules));
+        risks.extend(load_time_conflicts(module, suggested_modules));
+        risks.extend(prototype_conflicts(module, suggested_modules));
+        risks.extend(hazard_conflicts(module, suggested_modules));
+
+        // File-scoped variables and globals whose users end up in different modules
+        risks.extend(shared_state_conflicts(module, suggested_modules));
+
+        // Subroutines no cluster took, apart from dead code, stay where they are
+        let unassigned: Vec<&str> = module.subroutines.iter()
+            .map(|s| s.name.as_str())
+            .filter(|name| !module_sub_map.contains_key(*name) && !module.deletion_candidates.iter().any(|c| c == name))
+            .collect();
+        if !unassigned.is_empty() {
+            risks.push(format!(
+                "{} subroutines are in no new module and stay in {}: {}",
+                unassigned.len(), module.name, unassigned.join(", ")
+            ));
+        }
+
         if suggested_modules.len() > 5 {
             risks.push("Large number of modules may increase maintenance overhead".to_string());
         }
         
+        // Subroutines that should be broken up with extract method, before or after the move
+        let hotspots: Vec<String> = suggested_modules.iter()
+            .flat_map(|m| &m.subroutines)
+            .filter(|s| !s.metrics.hotspots.is_empty())
+            .map(|s| format!(
+                "{} ({}; {} lines, complexity {})",
+                s.name,
+                s.metrics.hotspots.iter().map(|h| h.label()).collect::<Vec<_>>().join(", "),
+                s.metrics.physical_loc,
+                s.metrics.cyclomatic_complexity
+            ))
+            .collect();
+        if !hotspots.is_empty() {
+            risks.push(format!(
+                "{} moved subroutines are hotspots that need extract-method work to be maintainable in their new module: {}",
+                hotspots.len(), hotspots.join(", ")
+            ));
+        }
+
+        // New modules that are no more cohesive than the original
+        let metrics = proposal_metrics(module, suggested_modules);
+        for after in metrics.after.iter().take(suggested_modules.len()) {
+            if after.lcom4 > 1 {
+                risks.push(format!(
+                    "{} is not cohesive: its subroutines form {} unrelated groups (LCOM4)",
+                    after.name, after.lcom4
+                ));
+            }
+        }
+
         // Identify benefits
-        let benefits = vec![
+        let mut benefits = vec![
             "Improved code organization with clear separation of responsibilities".to_string(),
             "Better maintainability through modular design".to_string(),
             format!("Reduced cognitive load with {} focused modules", suggested_modules.len()),
         ];
+        if let Some(SharedModule { module: helper, groups }) = helper {
+            benefits.push(format!(
+                "{} group(s) of duplicated subroutines can share {} instead of keeping copies",
+                groups, helper.name
+            ));
+            // Copies that are not exact stay and have to be changed by hand
+            let in_helper = |name: &str| helper.subroutines.iter().any(|s| s.name == name);
+            let mut copies: Vec<(&str, &str)> = module.clones.iter()
+                .filter(|c| c.kind != CloneKind::Exact)
+                .filter_map(|c| match (in_helper(&c.first.subroutine), in_helper(&c.second.subroutine)) {
+                    (true, false) => Some((c.second.subroutine.as_str(), c.first.subroutine.as_str())),
+                    (false, true) => Some((c.first.subroutine.as_str(), c.second.subroutine.as_str())),
+                    _ => None,
+                })
+                .collect();
+            copies.sort_by_key(|(copy, _)| *copy);
+            copies.dedup_by_key(|(copy, _)| *copy);
+            let copies: Vec<String> = copies.iter().map(|(copy, kept)| format!("{} ({})", copy, kept)).collect();
+            if !copies.is_empty() {
+                risks.push(format!(
+                    "{} subroutines are not exact copies of the subroutines in {} and have to be changed to call them: {}",
+                    copies.len(), helper.name, copies.join(", ")
+                ));
+            }
+        }
+        let worst_lcom4 = metrics.after.iter().map(|m| m.lcom4).max().unwrap_or(0);
+        if worst_lcom4 < metrics.before.lcom4 {
+            benefits.push(format!("LCOM4 drops from {} to at most {}", metrics.before.lcom4, worst_lcom4));
+        }
         
         Ok(RefactoringImpact {
             complexity,
             effort,
             risks,
             benefits,
+            metrics,
         })
     }
-} 
\ No newline at end of file
+}
+
+/// The script `module` was parsed from, with the subroutines that move into
+/// `suggested_modules` removed and `use` statements importing them from
+/// their new modules after its last `use`. The modules are saved next to
+/// the script, which finds them through FindBin.
+pub fn rewrite_script(module: &PerlModule, suggested_modules: &[NewModuleProposal]) -> String {
+    let content = &module.content;
+    let mut cuts: Vec<Range<usize>> = suggested_modules.iter()
+        .flat_map(|m| &m.subroutines)
+        .filter_map(|moved| {
+            let sub = module.subroutines.iter().find(|s| s.name == moved.name)?;
+            let start = sub.span.map(|span| span.start).or_else(|| content.find(&sub.code))?;
+            let end = start + sub.code.len();
+            // Take the rest of the line and the blank line after it along
+            let rest = &content[end..];
+            let line_end = rest.find('\n').filter(|&n| rest[..n].trim().is_empty()).map_or(end, |n| end + n + 1);
+            let blank = content[line_end..].find('\n').filter(|&n| content[line_end..line_end + n].trim().is_empty());
+            Some(start..blank.map_or(line_end, |n| line_end + n + 1))
+        })
+        .collect();
+    cuts.sort_by_key(|r| r.start);
+    cuts.dedup();
+
+    let mut imports = Vec::new();
+    if !module.depends_on("FindBin") {
+        imports.push("use FindBin;".to_string());
+    }
+    imports.push("use lib $FindBin::Bin;".to_string());
+    for new_module in suggested_modules {
+        let names: Vec<&str> = new_module.subroutines.iter().map(|s| s.name.as_str()).collect();
+        imports.push(format!("use {} qw({});", new_module.name, names.join(" ")));
+    }
+
+    // After the line of the last `use`, or the `#!` line
+    let after_uses = module.dependencies.iter()
+        .filter(|d| d.phase == LoadPhase::CompileTime)
+        .filter_map(|d| d.span)
+        .map(|span| span.end)
+        .max()
+        .or_else(|| content.starts_with("#!").then_some(0));
+    let insert_at = after_uses.map_or(0, |end| content[end..].find('\n').map_or(content.len(), |n| end + n + 1));
+
+    let mut edits: Vec<(Range<usize>, String)> = cuts.into_iter().map(|cut| (cut, String::new())).collect();
+    edits.push((insert_at..insert_at, format!("{}\n", imports.join("\n"))));
+    edits.sort_by_key(|(range, _)| (range.start, range.end));
+
+    let mut rewritten = String::with_capacity(content.len());
+    let mut copied = 0;
+    for (range, text) in edits {
+        let start = range.start.max(copied);
+        rewritten.push_str(&content[copied..start]);
+        rewritten.push_str(&text);
+        copied = range.end.max(start);
+    }
+    rewritten.push_str(&content[copied..]);
+    rewritten
+}
+
+/// Describe an export list for the generation prompt
+fn exports_text(exports: &Exports) -> String {
+    if exports.is_empty() {
+        return "None: do not export anything".to_string();
+    }
+
+    let mut lines = vec![format!("Exporter: {}", exports.exporter.as_deref().unwrap_or("Exporter"))];
+    if !exports.default.is_empty() {
+        lines.push(format!("Exported by default (@EXPORT): {}", exports.default.join(" ")));
+    }
+    if !exports.optional.is_empty() {
+        lines.push(format!("Exported on request (@EXPORT_OK): {}", exports.optional.join(" ")));
+    }
+    for tag in &exports.tags {
+        lines.push(format!("Tag :{}: {}", tag.name, tag.symbols.join(" ")));
+    }
+    lines.join("\n            ")
+}
+
+/// "a Moose class extending Base, consuming Role" and similar
+fn class_description(class: &ClassInfo) -> String {
+    let mut description = match (&class.object_system, class.is_role) {
+        (Some(system), true) => format!("a {} role", system),
+        (Some(system), false) => format!("a {} class", system),
+        (None, _) => "a class".to_string(),
+    };
+    if !class.superclasses.is_empty() {
+        description.push_str(&format!(" extending {}", class.superclasses.join(", ")));
+    }
+    if !class.roles.is_empty() {
+        description.push_str(&format!(" consuming {}", class.roles.join(", ")));
+    }
+    if let Some(constructor) = &class.constructor {
+        description.push_str(&format!(" with the constructor {}", constructor));
+    }
+    description
+}
+
+/// The role implementation matching the class's object system
+fn role_system(class: &ClassInfo) -> &'static str {
+    match class.object_system.as_deref() {
+        Some("Moose") => "Moose::Role",
+        Some("Moo") => "Moo::Role",
+        Some("Mouse") => "Mouse::Role",
+        Some("Mojo::Base") => "Mojo::Base -role",
+        _ => "Role::Tiny",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use rig::providers::azure::Client;
+    use serde_json::json;
+    use wiremock::matchers::method;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+    use crate::analyzer::{find_clones, DEFAULT_MIN_SIMILARITY};
+    use crate::parser::StaticModuleParser;
+    use super::*;
+
+    #[test]
+    fn test_rewrite_script_uses_new_modules() {
+        let content = "#!/usr/bin/perl\nuse strict;\nuse warnings;\n\nmy @rows = load_rows($ARGV[0]);\nprint format_row($_) for @rows;\n\nsub load_rows {\n    my ($file) = @_;\n    return ();\n}\n\nsub format_row { return \"$_[0]\\n\" }\n\nsub usage { die \"usage: $0 FILE\\n\" }\n";
+        let module = StaticModuleParser::new().parse_source(Path::new("report.pl"), content).remove(0);
+        let moved = |name: &str, subs: &[&str]| NewModuleProposal {
+            name: name.to_string(),
+            subroutines: module.subroutines.iter().filter(|s| subs.contains(&s.name.as_str())).cloned().collect(),
+            ..Default::default()
+        };
+        let suggested = vec![moved("Report::Input", &["load_rows"]), moved("Report::Output", &["format_row"])];
+
+        assert_eq!(module.namespace(), "Report");
+        assert_eq!(
+            rewrite_script(&module, &suggested),
+            "#!/usr/bin/perl\nuse strict;\nuse warnings;\nuse FindBin;\nuse lib $FindBin::Bin;\nuse Report::Input qw(load_rows);\nuse Report::Output qw(format_row);\n\nmy @rows = load_rows($ARGV[0]);\nprint format_row($_) for @rows;\n\nsub usage { die \"usage: $0 FILE\\n\" }\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_clones_are_extracted_into_a_shared_module() -> Result<(), Box<dyn std::error::Error>> {
+        let content = r#"#!/usr/bin/perl
+use strict;
+
+print order_total($_), grand_total($_), invoice_total($_), cart_total($_) for @ARGV;
+
+sub order_total {
+    my ($order) = @_;
+    my $total = 0;
+    foreach my $line (@{ $order->{lines} }) {
+        $total += $line->{price} * $line->{quantity};
+    }
+    return sprintf('%.2f', $total);
+}
+
+sub grand_total {
+    my ($order) = @_;
+    my $total = 0;
+    foreach my $line (@{ $order->{lines} }) {
+        $total += $line->{price} * $line->{quantity};
+    }
+    return sprintf('%.2f', $total);
+}
+
+sub invoice_total {
+    my ($invoice) = @_;
+    my $sum = 0;
+    foreach my $item (@{ $invoice->{items} }) {
+        $sum += $item->{amount} * $item->{count};
+    }
+    return sprintf("%.2f", $sum);
+}
+
+sub cart_total {
+    my ($cart) = @_;
+    my $total = 0;
+    foreach my $line (@{ $cart->{lines} }) {
+        next unless $line->{active};
+        $total += $line->{price} * $line->{quantity};
+    }
+    return sprintf('%.2f', $total);
+}
+"#;
+        let mut module = StaticModuleParser::new().parse_source(Path::new("report.pl"), content).remove(0);
+        module.clones = find_clones(std::slice::from_ref(&module), DEFAULT_MIN_SIMILARITY);
+        module.responsibility_clusters = vec![ResponsibilityCluster {
+            name: "Totals".to_string(),
+            description: "Adds up amounts".to_string(),
+            related_subroutines: ["order_total", "grand_total", "invoice_total", "cart_total"].map(String::from).to_vec(),
+            suggested_module_name: Some("Report::Totals".to_string()),
+            confidence: 0.9,
+        }];
+
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "test-id",
+                "object": "chat.completion",
+                "created": 1234567890,
+                "model": "gpt-4o-2024-08-06",
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": "package Report::Totals;\n1;\n" },
+                    "finish_reason": "stop"
+                }],
+                "usage": { "prompt_tokens": 100, "completion_tokens": 50, "total_tokens": 150 }
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let client = Client::from_api_key("test-key", "test-version", mock_server.uri().as_str());
+        let proposer = AIRefactoringProposer::new(AgentBuilder::new(client.completion_model("gpt-4o-2024-08-06")))
+            .with_clone_extraction(true);
+
+        let proposal = proposer.generate_proposal(&module).await?;
+
+        let modules: Vec<(&str, Vec<&str>)> = proposal.suggested_modules
+            .iter()
+            .map(|m| (m.name.as_str(), m.subroutines.iter().map(|s| s.name.as_str()).collect()))
+            .collect();
+        assert_eq!(modules, vec![
+            ("Report::Totals", vec!["invoice_total", "cart_total"]),
+            ("Report::Shared", vec!["order_total", "grand_total"]),
+        ]);
+        assert_eq!(proposal.impact.complexity, 2);
+        assert_eq!(proposal.impact.metrics.after.iter().map(|m| m.name.as_str()).collect::<Vec<_>>(), vec!["Report::Totals", "Report::Shared"]);
+        assert!(proposal.impact.risks.contains(&"2 subroutines are not exact copies of the subroutines in Report::Shared \
+            and have to be changed to call them: cart_total (order_total), invoice_total (order_total)".to_string()));
+        assert!(proposal.impact.benefits.contains(&"1 group(s) of duplicated subroutines can share Report::Shared instead of keeping copies".to_string()));
+
+        let script = proposal.rewritten_script.expect("a rewritten script");
+        assert!(script.contains("use Report::Totals qw(invoice_total cart_total);\nuse Report::Shared qw(order_total grand_total);\n"));
+        assert!(!script.contains("sub order_total") && !script.contains("sub grand_total"));
+        assert!(proposal.suggested_modules[1].suggested_code.contains("sub grand_total { return order_total(@_) }"));
+        Ok(())
+    }
+}
@@ -1,11 +1,19 @@
+use std::path::Path;
 use crate::domain::{
-    models::{RefactoringProposal, ValidationResult},
+    models::{BlockKind, CallKind, Hazard, HazardKind, ModuleKind, NewModuleProposal, PerlModule, RefactoringProposal, SubroutineKind, ValidationResult, VariableScope},
     traits::DependencyValidator,
 };
 use crate::error::Error;
+use crate::parser::{lexer::{self, TokenKind}, StaticModuleParser};
 
 pub struct DefaultDependencyValidator;
 
+impl Default for DefaultDependencyValidator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl DefaultDependencyValidator {
     pub fn new() -> Self {
         Self
@@ -13,8 +21,568 @@ impl DefaultDependencyValidator {
 }
 
 impl DependencyValidator for DefaultDependencyValidator {
-    fn validate_dependencies(&self, _proposal: &RefactoringProposal) -> Result<ValidationResult, Error> {
-        // TODO: Implement actual validation logic
-        Err(Error::ValidationError("Not implemented".to_string()))
+    /// Subroutines that do not exist in the original module and generated code
+    /// that changes the public interface are issues; shared state split across
+    /// module boundaries, attributes and load-time code used on both sides of
+    /// one, calls that depend on a prototype across one, dynamic code,
+    /// methods taken away from their class and dropped documentation are
+    /// reported as warnings.
+    fn validate_dependencies(&self, proposal: &RefactoringProposal) -> Result<ValidationResult, Error> {
+        let original = &proposal.original_module;
+        let mut issues = Vec::new();
+
+        for module in &proposal.suggested_modules {
+            for sub in &module.subroutines {
+                if !original.subroutines.iter().any(|s| s.name == sub.name) {
+                    issues.push(format!("{} contains '{}', which does not exist in {}", module.name, sub.name, original.name));
+                }
+            }
+            issues.extend(export_mismatches(original, module));
+        }
+
+        let mut warnings = shared_state_conflicts(original, &proposal.suggested_modules);
+        warnings.extend(attribute_conflicts(original, &proposal.suggested_modules));
+        warnings.extend(class_boundary_conflicts(original, &proposal.suggested_modules));
+        warnings.extend(load_time_conflicts(original, &proposal.suggested_modules));
+        warnings.extend(prototype_conflicts(original, &proposal.suggested_modules));
+        warnings.extend(hazard_conflicts(original, &proposal.suggested_modules));
+        for module in &proposal.suggested_modules {
+            warnings.extend(documentation_losses(original, module));
+        }
+
+        Ok(ValidationResult {
+            is_valid: issues.is_empty(),
+            issues,
+            warnings,
+        })
+    }
+}
+
+/// Differences between what the generated code of `module` exports and what
+/// the original module exported for the same subroutines
+pub fn export_mismatches(original: &PerlModule, module: &NewModuleProposal) -> Vec<String> {
+    let names: Vec<&str> = module.subroutines.iter().map(|s| s.name.as_str()).collect();
+    let expected = original.exports.restricted_to(&names);
+    let actual = generated_module(module).map(|m| m.exports).unwrap_or_default();
+
+    let mut mismatches = Vec::new();
+    for symbol in &expected.default {
+        if !actual.default.contains(symbol) {
+            mismatches.push(format!("{} does not export '{}' by default, as {} does", module.name, symbol, original.name));
+        }
+    }
+    for symbol in &expected.optional {
+        if !actual.is_exported(symbol) {
+            mismatches.push(format!("{} does not make '{}' exportable, as {} does", module.name, symbol, original.name));
+        }
+    }
+    for tag in &expected.tags {
+        if !actual.tags.iter().any(|t| t.name == tag.name) {
+            mismatches.push(format!("{} is missing the export tag ':{}'", module.name, tag.name));
+        }
+    }
+    for symbol in actual.symbols() {
+        if !expected.is_exported(symbol) {
+            mismatches.push(format!("{} exports '{}', which is not part of the interface of {}", module.name, symbol, original.name));
+        }
+    }
+    mismatches
+}
+
+/// Subroutines of `module` that were documented in the original module but
+/// have no POD in the generated code
+pub fn documentation_losses(original: &PerlModule, module: &NewModuleProposal) -> Vec<String> {
+    let Some(generated) = generated_module(module) else {
+        return Vec::new();
+    };
+    generated.subroutines
+        .iter()
+        .filter(|sub| sub.pod.is_none())
+        .filter(|sub| original.subroutines.iter().any(|s| s.name == sub.name && s.pod.is_some()))
+        .map(|sub| format!("{} drops the documentation of '{}' from {}", module.name, sub.name, original.name))
+        .collect()
+}
+
+/// The package of the generated code that `module` proposes
+fn generated_module(module: &NewModuleProposal) -> Option<PerlModule> {
+    let mut generated = StaticModuleParser::new().parse_source(Path::new(""), &module.suggested_code);
+    let index = generated.iter().position(|m| m.name == module.name).unwrap_or(0);
+    (index < generated.len()).then(|| generated.swap_remove(index))
+}
+
+/// Describe every shared variable of `module` whose users would end up in
+/// different modules. Subroutines that are not part of any suggested module
+/// stay in the original one.
+pub fn shared_state_conflicts(module: &PerlModule, suggested_modules: &[NewModuleProposal]) -> Vec<String> {
+    let mut conflicts = Vec::new();
+
+    for variable in &module.variables {
+        let subs: Vec<&str> = module.subroutines
+            .iter()
+            .filter(|s| s.uses_variable(&variable.name))
+            .map(|s| s.name.as_str())
+            .collect();
+        let users = placements(module, suggested_modules, &subs);
+        if users.len() < 2 {
+            continue;
+        }
+        let written = module.subroutines.iter().any(|s| s.writes.contains(&variable.name));
+
+        let scope = match variable.scope {
+            VariableScope::Lexical => "file-scoped variable",
+            VariableScope::Package => "package variable",
+            VariableScope::Global => "global variable",
+        };
+        let places = describe_placements(&users);
+        let consequence = if written {
+            "it is modified, so splitting it would give each module its own copy of the state"
+        } else {
+            "its declaration has to be shared or duplicated"
+        };
+        conflicts.push(format!("The {} {} is used in {}; {}", scope, variable.name, places, consequence));
+    }
+
+    conflicts
+}
+
+/// Describe every attribute whose builder, trigger or users would end up in
+/// different modules
+pub fn attribute_conflicts(module: &PerlModule, suggested_modules: &[NewModuleProposal]) -> Vec<String> {
+    module.attributes
+        .iter()
+        .filter_map(|attribute| {
+            let users = placements(module, suggested_modules, &attribute.linked_subroutines());
+            (users.len() > 1).then(|| format!(
+                "The attribute {} is used in {}; only one of them can declare it, the others have to go through its accessors",
+                attribute.name,
+                describe_placements(&users)
+            ))
+        })
+        .collect()
+}
+
+/// Describe special blocks and data sections that the suggested modules
+/// cannot keep working with: a block or `DATA` whose subroutines end up in
+/// different modules, and generated modules that read `DATA` without
+/// having a data section of their own. Top-level code that only initializes
+/// variables is left to `shared_state_conflicts`.
+pub fn load_time_conflicts(module: &PerlModule, suggested_modules: &[NewModuleProposal]) -> Vec<String> {
+    let mut conflicts = Vec::new();
+    for block in &module.special_blocks {
+        // Splitting a plain variable initialization is reported as shared state
+        if block.kind == BlockKind::TopLevel && block.calls.is_empty() {
+            continue;
+        }
+        let users = placements(module, suggested_modules, &block.linked_subroutines());
+        if users.len() > 1 {
+            conflicts.push(format!(
+                "The {} at lines {}-{} is linked to {}; it runs in only one of them",
+                block.kind.label(),
+                block.line_start,
+                block.line_end,
+                describe_placements(&users)
+            ));
+        }
+    }
+
+    let Some(data) = &module.data_section else {
+        return conflicts;
+    };
+    let readers: Vec<&str> = data.used_by.iter().map(String::as_str).collect();
+    let users = placements(module, suggested_modules, &readers);
+    if users.len() > 1 {
+        conflicts.push(format!(
+            "The {} section is read by {}; only one module can own it",
+            data.marker,
+            describe_placements(&users)
+        ));
+    }
+    for suggested in suggested_modules {
+        let reads_data = suggested.subroutines.iter().any(|s| data.used_by.contains(&s.name));
+        let has_data = generated_module(suggested).is_some_and(|m| m.data_section.is_some());
+        if reads_data && !has_data {
+            conflicts.push(format!("{} reads from DATA but has no __DATA__ section", suggested.name));
+        }
+    }
+    conflicts
+}
+
+/// Describe calls to subroutines with a prototype that would cross a module
+/// boundary. Perl only applies a prototype to calls compiled after the
+/// declaration is seen, so the call depends on the new module being loaded
+/// with `use` and importing the subroutine; calls written without
+/// parentheses do not even parse the same way without it. `&foo(...)`
+/// calls and method calls ignore prototypes and are not reported.
+pub fn prototype_conflicts(module: &PerlModule, suggested_modules: &[NewModuleProposal]) -> Vec<String> {
+    let mut conflicts = Vec::new();
+
+    let find = |name: &str| module.subroutines.iter().find(|s| s.name == name);
+    for edge in module.call_graph.edges.iter().filter(|e| e.kind == CallKind::Function) {
+        let (Some(caller), Some(callee)) = (find(&edge.caller), find(&edge.callee)) else {
+            continue;
+        };
+        let Some(prototype) = &callee.signature.prototype else {
+            continue;
+        };
+        let owners = |name| -> Vec<&str> {
+            placements(module, suggested_modules, &[name]).into_iter().map(|(owner, _)| owner).collect()
+        };
+        let callee_owners = owners(callee.name.as_str());
+        let caller_owners = owners(caller.name.as_str());
+        if caller_owners.iter().any(|o| callee_owners.contains(o)) {
+            continue;
+        }
+        let Some(parenthesized) = prototyped_call(&caller.code, &callee.name) else {
+            continue;
+        };
+
+        let target = callee_owners.join(", ");
+        conflicts.push(if parenthesized {
+            format!(
+                "{} calls {} ({}) at line {}; its prototype ({}) only applies if {} is loaded with `use` and imports it",
+                caller.name, callee.name, target, edge.line, prototype, target
+            )
+        } else {
+            format!(
+                "{} calls {} ({}) without parentheses at line {}; the call relies on its prototype ({}) and breaks unless {} is loaded with `use` and imports it",
+                caller.name, callee.name, target, edge.line, prototype, target
+            )
+        });
+    }
+    conflicts
+}
+
+/// Whether `code` calls `name` in a way its prototype applies to, and if so
+/// whether every such call uses parentheses
+fn prototyped_call(code: &str, name: &str) -> Option<bool> {
+    let tokens = lexer::significant_tokens(code);
+    let calls: Vec<bool> = tokens
+        .iter()
+        .enumerate()
+        .skip(2)
+        .filter(|(i, t)| t.is_word(name) && !tokens[i - 1].is_op("->"))
+        .map(|(i, _)| tokens.get(i + 1).is_some_and(|t| t.kind == TokenKind::OpenParen))
+        .collect();
+    (!calls.is_empty()).then(|| calls.iter().all(|&parenthesized| parenthesized))
+}
+
+/// Describe dynamic code that a proposal cannot be checked against:
+/// subroutines that move although they use string `eval`, glob assignments,
+/// dynamic dispatch or `caller`, code outside subroutines doing the same,
+/// and an `AUTOLOAD` left behind that would catch calls to moved subroutines
+pub fn hazard_conflicts(module: &PerlModule, suggested_modules: &[NewModuleProposal]) -> Vec<String> {
+    if suggested_modules.is_empty() {
+        return Vec::new();
+    }
+    let mut conflicts = Vec::new();
+    for suggested in suggested_modules {
+        for sub in &suggested.subroutines {
+            let Some(original) = module.subroutines.iter().find(|s| s.name == sub.name && !s.hazards.is_empty()) else {
+                continue;
+            };
+            conflicts.push(format!(
+                "{} moves to {} but uses {}; names it resolves at runtime may still point into {}",
+                sub.name, suggested.name, describe_hazards(&original.hazards.iter().collect::<Vec<_>>()), module.name
+            ));
+        }
+    }
+
+    let top_level: Vec<&Hazard> = module.hazards.iter().filter(|h| h.subroutine.is_none()).collect();
+    if !top_level.is_empty() {
+        conflicts.push(format!(
+            "{} uses {} outside any subroutine; subroutines it creates or looks up at runtime are not part of the proposal",
+            module.name, describe_hazards(&top_level)
+        ));
+    }
+    let moved = suggested_modules.iter().any(|m| !m.subroutines.is_empty());
+    let autoload_stays = !suggested_modules.iter().any(|m| m.subroutines.iter().any(|s| s.name == "AUTOLOAD"));
+    if let Some(autoload) = module.hazards.iter().find(|h| h.kind == HazardKind::Autoload).filter(|_| moved && autoload_stays) {
+        conflicts.push(format!(
+            "The AUTOLOAD of {} (line {}) will silently handle calls to moved subroutines that are still made on {}",
+            module.name, autoload.line, module.name
+        ));
+    }
+    conflicts
+}
+
+/// "string eval (line 3), dynamic dispatch (lines 5, 9)"
+fn describe_hazards(hazards: &[&Hazard]) -> String {
+    let mut kinds: Vec<(HazardKind, Vec<usize>)> = Vec::new();
+    for hazard in hazards {
+        match kinds.iter_mut().find(|(kind, _)| *kind == hazard.kind) {
+            Some((_, lines)) if lines.contains(&hazard.line) => {}
+            Some((_, lines)) => lines.push(hazard.line),
+            None => kinds.push((hazard.kind, vec![hazard.line])),
+        }
+    }
+    kinds
+        .iter()
+        .map(|(kind, lines)| {
+            let lines: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+            let label = if lines.len() == 1 { "line" } else { "lines" };
+            format!("{} ({} {})", kind.label(), label, lines.join(", "))
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Group `subs` by the module they end up in: every suggested module that
+/// contains them, or the original module for subroutines that stay
+fn placements<'a>(module: &'a PerlModule, suggested_modules: &'a [NewModuleProposal], subs: &[&'a str]) -> Vec<(&'a str, Vec<&'a str>)> {
+    let mut placements: Vec<(&str, Vec<&str>)> = Vec::new();
+    for &sub in subs {
+        let owners: Vec<&str> = suggested_modules
+            .iter()
+            .filter(|m| m.subroutines.iter().any(|s| s.name == sub))
+            .map(|m| m.name.as_str())
+            .collect();
+        let owners = if owners.is_empty() { vec![module.name.as_str()] } else { owners };
+
+        for owner in owners {
+            match placements.iter_mut().find(|(name, _)| *name == owner) {
+                Some((_, subs)) => subs.push(sub),
+                None => placements.push((owner, vec![sub])),
+            }
+        }
+    }
+    placements
+}
+
+/// "A (foo, bar), B (baz)"
+fn describe_placements(placements: &[(&str, Vec<&str>)]) -> String {
+    placements
+        .iter()
+        .map(|(name, subs)| format!("{} ({})", name, subs.join(", ")))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Describe what a proposal takes away from the class that owns `$self`:
+/// instance methods moved into a module that is not a role, and the
+/// constructor moved anywhere.
+pub fn class_boundary_conflicts(module: &PerlModule, suggested_modules: &[NewModuleProposal]) -> Vec<String> {
+    let Some(class) = &module.class else {
+        return Vec::new();
+    };
+    let mut conflicts = Vec::new();
+
+    for suggested in suggested_modules {
+        let methods: Vec<&str> = suggested.subroutines
+            .iter()
+            .filter(|s| {
+                module.subroutines
+                    .iter()
+                    .any(|o| o.name == s.name && o.kind == SubroutineKind::InstanceMethod)
+            })
+            .map(|s| s.name.as_str())
+            .collect();
+        if suggested.kind == ModuleKind::Module && !methods.is_empty() {
+            conflicts.push(format!(
+                "Instance methods of {} ({}) move to {}, which is not a role of {}; they rely on $self being an object of {}",
+                module.name, methods.join(", "), suggested.name, module.name, module.name
+            ));
+        }
+
+        if let Some(constructor) = class.constructor.as_ref().filter(|c| suggested.subroutines.iter().any(|s| &s.name == *c)) {
+            conflicts.push(format!(
+                "The constructor {} of {} moves to {}; {} objects have to keep being created by {}",
+                constructor, module.name, suggested.name, module.name, module.name
+            ));
+        }
+    }
+
+    conflicts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::models::{RefactoringImpact, Subroutine};
+
+    const CONTENT: &str = r#"package Counter;
+
+my %counts;
+my $label = "count";
+
+sub increment { my ($key) = @_; return ++$counts{$key} }
+sub total { my $sum = 0; $sum += $_ for values %counts; return $sum }
+sub describe { my ($key) = @_; return "$label: " . ($counts{$key} // 0) }
+sub title { return ucfirst $label }
+
+1;
+"#;
+
+    /// A suggested module taking the subroutines named `subs`
+    fn suggested(name: &str, kind: ModuleKind, subs: &[&str]) -> NewModuleProposal {
+        NewModuleProposal {
+            name: name.to_string(),
+            kind,
+            subroutines: subs.iter().map(|s| Subroutine { name: s.to_string(), ..Default::default() }).collect(),
+            ..Default::default()
+        }
     }
-} 
\ No newline at end of file
+
+    fn proposal(groups: &[(&str, &[&str])], module: PerlModule) -> RefactoringProposal {
+        let suggested_modules = groups.iter().map(|(name, subs)| suggested(name, ModuleKind::Module, subs)).collect();
+        RefactoringProposal {
+            original_module: module,
+            suggested_modules,
+            impact: RefactoringImpact::default(),
+            rewritten_script: None,
+            cancelled: false,
+            deletion_candidates: vec![],
+        }
+    }
+
+    #[test]
+    fn test_shared_state_split_is_a_warning() -> Result<(), Error> {
+        let module = StaticModuleParser::new().parse_source(Path::new("Counter.pm"), CONTENT).remove(0);
+        let proposal = proposal(&[("Counter::Store", &["increment", "total"]), ("Counter::Text", &["describe"])], module);
+
+        let result = DefaultDependencyValidator::new().validate_dependencies(&proposal)?;
+
+        assert!(result.is_valid);
+        assert_eq!(result.warnings, vec![
+            "The file-scoped variable %counts is used in Counter::Store (increment, total), Counter::Text (describe); \
+             it is modified, so splitting it would give each module its own copy of the state".to_string(),
+            "The file-scoped variable $label is used in Counter::Text (describe), Counter (title); \
+             its declaration has to be shared or duplicated".to_string(),
+        ]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_generated_code_must_keep_exports() {
+        let original = StaticModuleParser::new().parse_source(
+            Path::new("Text.pm"),
+            "package Text;\nuse Exporter 'import';\nour @EXPORT = qw(trim);\nour @EXPORT_OK = qw(pad wrap);\nsub trim { }\nsub pad { }\nsub wrap { }\nsub _helper { }\n1;\n",
+        ).remove(0);
+        let module = |code: &str| NewModuleProposal {
+            suggested_code: code.to_string(),
+            ..suggested("Text::Space", ModuleKind::Module, &["trim", "pad"])
+        };
+
+        let exact = module("package Text::Space;\nuse Exporter 'import';\nour @EXPORT = qw(trim);\nour @EXPORT_OK = qw(pad);\n1;\n");
+        assert!(export_mismatches(&original, &exact).is_empty());
+
+        let changed = module("package Text::Space;\nuse Exporter 'import';\nour @EXPORT_OK = qw(trim pad _helper);\n1;\n");
+        assert_eq!(export_mismatches(&original, &changed), vec![
+            "Text::Space does not export 'trim' by default, as Text does",
+            "Text::Space exports '_helper', which is not part of the interface of Text",
+        ]);
+    }
+
+    #[test]
+    fn test_generated_code_keeps_documentation() {
+        let original = StaticModuleParser::new().parse_source(
+            Path::new("Text.pm"),
+            "package Text;\n\n=head2 trim\n\nRemoves whitespace.\n\n=cut\n\nsub trim { }\nsub pad { }\n1;\n",
+        ).remove(0);
+        let module = |code: &str| NewModuleProposal {
+            suggested_code: code.to_string(),
+            ..suggested("Text::Space", ModuleKind::Module, &["trim", "pad"])
+        };
+
+        let documented = module("package Text::Space;\n\n=head2 trim\n\nRemoves whitespace.\n\n=cut\n\nsub trim { }\nsub pad { }\n1;\n");
+        assert!(documentation_losses(&original, &documented).is_empty());
+
+        let undocumented = module("package Text::Space;\nsub trim { }\nsub pad { }\n1;\n");
+        assert_eq!(documentation_losses(&original, &undocumented), vec!["Text::Space drops the documentation of 'trim' from Text"]);
+    }
+
+    #[test]
+    fn test_load_time_code_split_across_modules() {
+        let module = StaticModuleParser::new().parse_source(
+            Path::new("Templates.pm"),
+            "package Templates;\nmy %seen;\nBEGIN { %seen = () }\nsub render { local $/; $seen{x}++; return <DATA> }\nsub count { return scalar keys %seen }\n1;\n__DATA__\nHello\n",
+        ).remove(0);
+        let render = |code: &str| [NewModuleProposal {
+            suggested_code: code.to_string(),
+            ..suggested("Templates::Render", ModuleKind::Module, &["render"])
+        }];
+
+        assert_eq!(load_time_conflicts(&module, &render("package Templates::Render;\nsub render { }\n1;\n")), vec![
+            "The BEGIN at lines 3-3 is linked to Templates::Render (render), Templates (count); it runs in only one of them",
+            "Templates::Render reads from DATA but has no __DATA__ section",
+        ]);
+        assert_eq!(load_time_conflicts(&module, &render("package Templates::Render;\nsub render { }\n1;\n__DATA__\nHello\n")).len(), 1);
+    }
+
+    #[test]
+    fn test_prototyped_calls_across_modules() {
+        let module = StaticModuleParser::new().parse_source(
+            Path::new("Util.pm"),
+            "package Util;\nsub max_of($$) { my ($x, $y) = @_; $x > $y ? $x : $y }\nsub widest { return max_of length $_[0], length $_[1] }\nsub larger { return max_of(@_) }\nsub forced { return &max_of(@_) }\n1;\n",
+        ).remove(0);
+        let math = [suggested("Util::Math", ModuleKind::Module, &["max_of"])];
+
+        assert_eq!(prototype_conflicts(&module, &math), vec![
+            "widest calls max_of (Util::Math) without parentheses at line 3; the call relies on its prototype ($$) and breaks unless Util::Math is loaded with `use` and imports it",
+            "larger calls max_of (Util::Math) at line 4; its prototype ($$) only applies if Util::Math is loaded with `use` and imports it",
+        ]);
+        assert!(prototype_conflicts(&module, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_dynamic_code_is_a_warning() {
+        let module = StaticModuleParser::new().parse_source(
+            Path::new("Plugin.pm"),
+            "package Plugin;\n*run = \\&call;\nsub call { my ($self, $m) = @_; return $self->$m }\nsub load { return eval \"require $_[0]\" }\nsub name { 'plugin' }\nsub AUTOLOAD { }\n1;\n",
+        ).remove(0);
+        let moved = |subs: &[&str]| [suggested("Plugin::Loader", ModuleKind::Module, subs)];
+
+        assert_eq!(hazard_conflicts(&module, &moved(&["call", "load"])), vec![
+            "call moves to Plugin::Loader but uses dynamic dispatch (line 3); names it resolves at runtime may still point into Plugin",
+            "load moves to Plugin::Loader but uses string eval (line 4); names it resolves at runtime may still point into Plugin",
+            "Plugin uses glob assignment (line 2) outside any subroutine; subroutines it creates or looks up at runtime are not part of the proposal",
+            "The AUTOLOAD of Plugin (line 6) will silently handle calls to moved subroutines that are still made on Plugin",
+        ]);
+        assert_eq!(hazard_conflicts(&module, &moved(&["name", "AUTOLOAD"])).len(), 2);
+        assert!(hazard_conflicts(&module, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_methods_belong_to_their_class() {
+        let module = StaticModuleParser::new().parse_source(
+            Path::new("Account.pm"),
+            "package Account;\nsub new { my ($class) = @_; return bless {}, $class }\nsub deposit { my ($self, $n) = @_; $self->{balance} += $n }\nsub format_amount { my ($n) = @_; sprintf '%.2f', $n }\n1;\n",
+        ).remove(0);
+        let as_role = [suggested("Account::Ledger", ModuleKind::Role, &["deposit"])];
+        assert!(class_boundary_conflicts(&module, &as_role).is_empty());
+
+        let as_module = [suggested("Account::Util", ModuleKind::Module, &["new", "deposit", "format_amount"])];
+        assert_eq!(class_boundary_conflicts(&module, &as_module), vec![
+            "Instance methods of Account (deposit) move to Account::Util, which is not a role of Account; they rely on $self being an object of Account",
+            "The constructor new of Account moves to Account::Util; Account objects have to keep being created by Account",
+        ]);
+    }
+
+    #[test]
+    fn test_attribute_users_split_across_modules() {
+        let module = StaticModuleParser::new().parse_source(
+            Path::new("Mailer.pm"),
+            "package Mailer;\nuse Moo;\nhas smtp => (is => 'lazy');\nhas from => (is => 'ro');\nsub _build_smtp { return {} }\nsub send { my $self = shift; $self->smtp->send($self->from) }\nsub sender { my $self = shift; return $self->from }\n1;\n",
+        ).remove(0);
+        let transport = [suggested("Mailer::Transport", ModuleKind::Role, &["_build_smtp", "send"])];
+
+        assert_eq!(attribute_conflicts(&module, &transport), vec![
+            "The attribute from is used in Mailer::Transport (send), Mailer (sender); \
+             only one of them can declare it, the others have to go through its accessors",
+        ]);
+        let names: Vec<_> = module.attributes_owned_by(&["_build_smtp", "send"]).iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, vec!["smtp"]);
+    }
+
+    #[test]
+    fn test_unknown_subroutine_is_an_issue() -> Result<(), Error> {
+        let module = StaticModuleParser::new().parse_source(Path::new("Counter.pm"), CONTENT).remove(0);
+        let proposal = proposal(&[("Counter::All", &["increment", "total", "describe", "title", "reset"])], module);
+
+        let result = DefaultDependencyValidator::new().validate_dependencies(&proposal)?;
+
+        assert!(!result.is_valid);
+        assert_eq!(result.issues, vec!["Counter::All contains 'reset', which does not exist in Counter"]);
+        assert!(result.warnings.is_empty());
+        Ok(())
+    }
+}
@@ -37,7 +37,7 @@ impl AsyncTestContext for TestContext {
 #[test_context(TestContext)]
 #[tokio::test]
 async fn test_parse_calculator(ctx: &TestContext) {
-    let module = ctx.app.parse_module(&ctx.calculator_file, "json", None).await.unwrap();
+    let module = ctx.app.parse_module(&ctx.calculator_file, "json", None).await.unwrap().remove(0);
 
     assert_eq!(module.name, "Calculator");
     assert_eq!(module.dependencies, vec!["strict", "warnings", "Math::Complex", "List::Util"]);
@@ -52,12 +52,12 @@ async fn test_parse_calculator(ctx: &TestContext) {
 #[test_context(TestContext)]
 #[tokio::test]
 async fn test_parse_order_manager(ctx: &TestContext) {
-    let module = ctx.app.parse_module(&ctx.order_manager_file, "json", None).await.unwrap();
+    let module = ctx.app.parse_module(&ctx.order_manager_file, "json", None).await.unwrap().remove(0);
 
     assert_eq!(module.name, "OrderManager");
-    assert!(module.dependencies.contains(&"strict".to_string()));
-    assert!(module.dependencies.contains(&"warnings".to_string()));
-    assert!(module.dependencies.contains(&"DBI".to_string()));
+    assert!(module.depends_on("strict"));
+    assert!(module.depends_on("warnings"));
+    assert!(module.depends_on("DBI"));
     assert_ge!(module.responsibility_clusters.len(), 2);
 
     // Verify specific cluster details
@@ -71,7 +71,7 @@ async fn test_parse_order_manager(ctx: &TestContext) {
 #[test_context(TestContext)]
 #[tokio::test]
 async fn test_save_and_load_analysis(ctx: &TestContext) -> Result<(), Box<dyn std::error::Error>> {
-    let loaded = ctx.app.load_analysis_from_file(&ctx.calculator_analysis)?;
+    let loaded = ctx.app.load_analysis_from_file(&ctx.calculator_analysis)?.remove(0);
     
     // Verify the loaded data matches the original
     assert_eq!(loaded.name, "Calculator");